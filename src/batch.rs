@@ -0,0 +1,350 @@
+use std::{collections::HashMap, ops::Range};
+
+use crate::{
+    culling::{Aabb, CullStats, Frustum},
+    gpu_culling::GpuCulling,
+    renderer::GpuContext,
+    scene::{EntityId, InstanceRaw, MaterialHandle, MeshHandle},
+};
+
+const MIN_INSTANCE_CAPACITY: usize = 16;
+
+pub type BatchKey = (MeshHandle, MaterialHandle);
+
+pub struct RenderBatch {
+    pub mesh: MeshHandle,
+    pub material: MaterialHandle,
+    /// Every instance in the batch, drawn into shadow maps.
+    pub instance_buffer: wgpu::Buffer,
+    pub instance_count: u32,
+    /// The instances that survived frustum culling, drawn by the scene pass.
+    pub visible_buffer: wgpu::Buffer,
+    pub visible_count: u32,
+    /// Instances casting shadows, see [`RenderBatch::shadow_casters`].
+    pub caster_count: u32,
+    /// The casters of batches where only some instances cast.
+    caster_buffer: Option<wgpu::Buffer>,
+    capacity: usize,
+    visible_capacity: usize,
+    caster_capacity: usize,
+    instances: Vec<InstanceRaw>,
+    entities: Vec<EntityId>,
+    dirty: Option<Range<usize>>,
+    /// Whether the instances changed since the batch was last culled.
+    cull_stale: bool,
+}
+
+impl RenderBatch {
+    fn new(ctx: &GpuContext, (mesh, material): BatchKey) -> Self {
+        Self {
+            mesh,
+            material,
+            instance_buffer: Self::create_instance_buffer(
+                ctx,
+                "Instance Buffer",
+                MIN_INSTANCE_CAPACITY,
+            ),
+            instance_count: 0,
+            visible_buffer: Self::create_instance_buffer(
+                ctx,
+                "Visible Instance Buffer",
+                MIN_INSTANCE_CAPACITY,
+            ),
+            visible_count: 0,
+            caster_count: 0,
+            caster_buffer: None,
+            capacity: MIN_INSTANCE_CAPACITY,
+            visible_capacity: MIN_INSTANCE_CAPACITY,
+            caster_capacity: 0,
+            instances: Vec::new(),
+            entities: Vec::new(),
+            dirty: None,
+            cull_stale: false,
+        }
+    }
+
+    fn create_instance_buffer(ctx: &GpuContext, label: &str, capacity: usize) -> wgpu::Buffer {
+        ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: (capacity * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+            usage: Self::instance_buffer_usage(ctx),
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Instance buffers double as the input of [`GpuCulling`] where it can run.
+    fn instance_buffer_usage(ctx: &GpuContext) -> wgpu::BufferUsages {
+        let usage = wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST;
+        if GpuCulling::is_supported(ctx) {
+            usage | wgpu::BufferUsages::STORAGE
+        } else {
+            usage
+        }
+    }
+
+    pub fn entities(&self) -> &[EntityId] {
+        &self.entities
+    }
+
+    pub fn instances(&self) -> &[InstanceRaw] {
+        &self.instances
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The instances to draw into shadow maps and their count, or `None` when none casts.
+    /// Batches where every instance casts draw their instance buffer directly.
+    pub fn shadow_casters(&self) -> Option<(&wgpu::Buffer, u32)> {
+        match self.caster_count {
+            0 => None,
+            count if count == self.instance_count => Some((&self.instance_buffer, count)),
+            count => Some((self.caster_buffer.as_ref()?, count)),
+        }
+    }
+
+    fn mark_dirty(&mut self, slots: Range<usize>) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(slots.start)..dirty.end.max(slots.end),
+            None => slots,
+        });
+    }
+
+    fn push(&mut self, entity: EntityId, instance: InstanceRaw) -> usize {
+        let slot = self.instances.len();
+        self.instances.push(instance);
+        self.entities.push(entity);
+        self.mark_dirty(slot..slot + 1);
+        slot
+    }
+
+    fn set(&mut self, slot: usize, instance: InstanceRaw) {
+        self.instances[slot] = instance;
+        self.mark_dirty(slot..slot + 1);
+    }
+
+    /// Removes `slot` by moving the last instance into it, returning the entity that moved.
+    fn swap_remove(&mut self, slot: usize) -> Option<EntityId> {
+        self.instances.swap_remove(slot);
+        self.entities.swap_remove(slot);
+        self.mark_dirty(slot..slot);
+
+        let moved = self.entities.get(slot).copied()?;
+        self.mark_dirty(slot..slot + 1);
+        Some(moved)
+    }
+
+    /// Uploads the slots changed since the last flush, leaving the batch to be culled again.
+    fn flush(&mut self, ctx: &GpuContext) {
+        let Some(dirty) = self.dirty.take() else {
+            return;
+        };
+
+        self.cull_stale = true;
+        self.instance_count = self.instances.len() as u32;
+        if self.instances.len() > self.capacity {
+            self.capacity = (self.capacity * 2).max(self.instances.len().next_power_of_two());
+            self.instance_buffer =
+                Self::create_instance_buffer(ctx, "Instance Buffer", self.capacity);
+            ctx.queue.write_buffer(
+                &self.instance_buffer,
+                0,
+                bytemuck::cast_slice(&self.instances),
+            );
+        } else {
+            let dirty = dirty.start..dirty.end.min(self.instances.len());
+            if !dirty.is_empty() {
+                ctx.queue.write_buffer(
+                    &self.instance_buffer,
+                    (dirty.start * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+                    bytemuck::cast_slice(&self.instances[dirty]),
+                );
+            }
+        }
+        self.write_casters(ctx);
+    }
+
+    /// Counts the shadow casters, compacting them into their own buffer when only some of
+    /// the instances cast.
+    fn write_casters(&mut self, ctx: &GpuContext) {
+        let casts = |instance: &&InstanceRaw| instance.shadow_flags().cast;
+        self.caster_count = self.instances.iter().filter(casts).count() as u32;
+        if self.caster_count == 0 || self.caster_count == self.instance_count {
+            return;
+        }
+
+        let casters: Vec<InstanceRaw> = self.instances.iter().filter(casts).copied().collect();
+        if casters.len() > self.caster_capacity || self.caster_buffer.is_none() {
+            self.caster_capacity = casters.len().next_power_of_two().max(MIN_INSTANCE_CAPACITY);
+            self.caster_buffer = Some(Self::create_instance_buffer(
+                ctx,
+                "Shadow Caster Buffer",
+                self.caster_capacity,
+            ));
+        }
+        if let Some(buffer) = &self.caster_buffer {
+            ctx.queue
+                .write_buffer(buffer, 0, bytemuck::cast_slice(&casters));
+        }
+    }
+
+    /// Writes the instances whose `mesh_bounds` intersect `frustum` into the visible buffer,
+    /// collecting them in `visible`.
+    fn cull(
+        &mut self,
+        ctx: &GpuContext,
+        frustum: Option<&Frustum>,
+        mesh_bounds: Option<Aabb>,
+        visible: &mut Vec<InstanceRaw>,
+    ) {
+        visible.clear();
+        visible.extend(
+            self.instances
+                .iter()
+                .filter(|instance| match (frustum, &mesh_bounds) {
+                    (Some(frustum), Some(aabb)) => {
+                        frustum.intersects_aabb(&aabb.transformed(&instance.model.into()))
+                    }
+                    _ => true,
+                })
+                .copied(),
+        );
+        self.write_visible(ctx, visible);
+    }
+
+    fn write_visible(&mut self, ctx: &GpuContext, visible: &[InstanceRaw]) {
+        self.visible_count = visible.len() as u32;
+        if visible.len() > self.visible_capacity {
+            self.visible_capacity = visible.len().next_power_of_two();
+            self.visible_buffer =
+                Self::create_instance_buffer(ctx, "Visible Instance Buffer", self.visible_capacity);
+        }
+        if !visible.is_empty() {
+            ctx.queue
+                .write_buffer(&self.visible_buffer, 0, bytemuck::cast_slice(visible));
+        }
+    }
+}
+
+/// Persistent per mesh/material instance batches. Entities keep a stable slot until they
+/// leave their batch, so moving an instance only rewrites its slot in the instance buffer.
+#[derive(Default)]
+pub struct RenderBatches {
+    batches: Vec<RenderBatch>,
+    lookup: HashMap<BatchKey, usize>,
+    slots: HashMap<EntityId, (usize, usize)>,
+    frustum: Option<Frustum>,
+    cull_stats: CullStats,
+}
+
+impl RenderBatches {
+    pub fn iter(&self) -> impl Iterator<Item = &RenderBatch> {
+        self.batches.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.batches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batches.is_empty()
+    }
+
+    pub fn contains(&self, entity: EntityId) -> bool {
+        self.slots.contains_key(&entity)
+    }
+
+    pub fn clear(&mut self) {
+        for batch in &mut self.batches {
+            batch.instances.clear();
+            batch.entities.clear();
+            batch.mark_dirty(0..0);
+        }
+        self.slots.clear();
+    }
+
+    pub fn insert(
+        &mut self,
+        ctx: &GpuContext,
+        entity: EntityId,
+        key: BatchKey,
+        instance: InstanceRaw,
+    ) {
+        self.remove(entity);
+
+        let batch_index = match self.lookup.get(&key) {
+            Some(&batch_index) => batch_index,
+            None => {
+                self.batches.push(RenderBatch::new(ctx, key));
+                self.lookup.insert(key, self.batches.len() - 1);
+                self.batches.len() - 1
+            }
+        };
+        let slot = self.batches[batch_index].push(entity, instance);
+        self.slots.insert(entity, (batch_index, slot));
+    }
+
+    pub fn update(&mut self, entity: EntityId, instance: InstanceRaw) -> bool {
+        let Some(&(batch_index, slot)) = self.slots.get(&entity) else {
+            return false;
+        };
+
+        self.batches[batch_index].set(slot, instance);
+        true
+    }
+
+    pub fn remove(&mut self, entity: EntityId) -> bool {
+        let Some((batch_index, slot)) = self.slots.remove(&entity) else {
+            return false;
+        };
+
+        if let Some(moved) = self.batches[batch_index].swap_remove(slot) {
+            self.slots.insert(moved, (batch_index, slot));
+        }
+        true
+    }
+
+    pub fn flush(&mut self, ctx: &GpuContext) {
+        for batch in &mut self.batches {
+            batch.flush(ctx);
+        }
+    }
+
+    /// Compacts the instances whose mesh `bounds` intersect `frustum` into each batch's
+    /// visible buffer; `None` keeps everything. Unless the frustum changed since the last
+    /// call, only batches whose instances changed are culled again.
+    pub fn cull(
+        &mut self,
+        ctx: &GpuContext,
+        frustum: Option<Frustum>,
+        bounds: impl Fn(MeshHandle) -> Option<Aabb>,
+    ) {
+        let frustum_changed = self.frustum != frustum;
+        let mut stats = CullStats::default();
+        let mut visible = Vec::new();
+        for batch in &mut self.batches {
+            if frustum_changed || batch.cull_stale {
+                batch.cull_stale = false;
+                batch.cull(ctx, frustum.as_ref(), bounds(batch.mesh), &mut visible);
+            }
+            stats.visible += batch.visible_count;
+            stats.culled += batch.instance_count - batch.visible_count;
+        }
+        self.frustum = frustum;
+        self.cull_stats = stats;
+    }
+
+    pub fn cull_stats(&self) -> CullStats {
+        self.cull_stats
+    }
+}
+
+impl<'a> IntoIterator for &'a RenderBatches {
+    type Item = &'a RenderBatch;
+    type IntoIter = std::slice::Iter<'a, RenderBatch>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.batches.iter()
+    }
+}
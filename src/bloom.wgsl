@@ -0,0 +1,87 @@
+// Bloom over a chain of half-sized copies of the HDR scene color: a 13-tap downsample
+// with a soft threshold on the first step, then tent-filtered upsamples added back up the
+// chain and finally into the scene.
+
+struct Bloom {
+    // One over the source size in pixels.
+    texel_size: vec2<f32>,
+    threshold: f32,
+    knee: f32,
+    // Weight of an upsample; the step into the scene uses the bloom intensity.
+    scale: f32,
+    radius: f32,
+    _padding: vec2<f32>,
+}
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> bloom: Bloom;
+
+struct FullscreenOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> FullscreenOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: FullscreenOutput;
+    out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+fn sample_source(uv: vec2<f32>, offset: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(source_texture, source_sampler, uv + offset * bloom.texel_size, 0.0)
+        .rgb;
+}
+
+// Weighted average of five overlapping 2x2 boxes, which keeps moving highlights stable.
+fn downsample(uv: vec2<f32>) -> vec3<f32> {
+    let a = sample_source(uv, vec2<f32>(-2.0, -2.0));
+    let b = sample_source(uv, vec2<f32>(0.0, -2.0));
+    let c = sample_source(uv, vec2<f32>(2.0, -2.0));
+    let d = sample_source(uv, vec2<f32>(-1.0, -1.0));
+    let e = sample_source(uv, vec2<f32>(1.0, -1.0));
+    let f = sample_source(uv, vec2<f32>(-2.0, 0.0));
+    let g = sample_source(uv, vec2<f32>(0.0, 0.0));
+    let h = sample_source(uv, vec2<f32>(2.0, 0.0));
+    let i = sample_source(uv, vec2<f32>(-1.0, 1.0));
+    let j = sample_source(uv, vec2<f32>(1.0, 1.0));
+    let k = sample_source(uv, vec2<f32>(-2.0, 2.0));
+    let l = sample_source(uv, vec2<f32>(0.0, 2.0));
+    let m = sample_source(uv, vec2<f32>(2.0, 2.0));
+    return (d + e + i + j) * 0.125 + (a + c + k + m) * 0.03125 + (b + f + h + l) * 0.0625
+        + g * 0.125;
+}
+
+@fragment
+fn fs_prefilter(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    // Clamp to half precision's range so a single bright pixel cannot turn into infinity.
+    let color = min(downsample(in.uv), vec3<f32>(60000.0));
+    let brightness = max(color.r, max(color.g, color.b));
+    let soft = clamp(brightness - bloom.threshold + bloom.knee, 0.0, 2.0 * bloom.knee);
+    let curve = soft * soft / (4.0 * bloom.knee + 1e-5);
+    let contribution = max(curve, brightness - bloom.threshold) / max(brightness, 1e-5);
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+@fragment
+fn fs_downsample(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(downsample(in.uv), 1.0);
+}
+
+// 3x3 tent filter; the pipeline adds the result onto the target.
+@fragment
+fn fs_upsample(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let r = bloom.radius;
+    var sum = sample_source(in.uv, vec2<f32>(0.0, 0.0)) * 4.0;
+    sum += (sample_source(in.uv, vec2<f32>(-r, 0.0)) + sample_source(in.uv, vec2<f32>(r, 0.0))
+        + sample_source(in.uv, vec2<f32>(0.0, -r)) + sample_source(in.uv, vec2<f32>(0.0, r)))
+        * 2.0;
+    sum += sample_source(in.uv, vec2<f32>(-r, -r)) + sample_source(in.uv, vec2<f32>(r, -r))
+        + sample_source(in.uv, vec2<f32>(-r, r)) + sample_source(in.uv, vec2<f32>(r, r));
+    return vec4<f32>(sum / 16.0 * bloom.scale, 1.0);
+}
@@ -1,20 +1,93 @@
-use cgmath::SquareMatrix;
+use cgmath::{Angle, SquareMatrix};
+use serde::{Deserialize, Serialize};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
 
+use crate::picking::Ray;
+
+/// Maps OpenGL's -1..1 clip depth to wgpu's 0..1. `Matrix4::new` takes columns.
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
     0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.5,
-    0.0, 0.0, 0.0, 1.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
 );
 
+/// How a [`Camera`] maps its view onto the screen. Every mode follows the camera's aspect.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Projection {
+    /// Vertical field of view in degrees.
+    Perspective { fovy: f32 },
+    /// Shows `height` world units vertically and `height * aspect` across, at any distance.
+    Orthographic { height: f32 },
+    /// A perspective without a far plane. Depth runs from 1 at the near plane to 0 at
+    /// infinity, which keeps precision far away; the renderer flips its depth test to
+    /// match while such a camera is active.
+    InfiniteReverseZ { fovy: f32 },
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection::Perspective { fovy: 45.0 }
+    }
+}
+
+impl Projection {
+    /// The projection in OpenGL clip space, like `cgmath::perspective`; `zfar` is ignored
+    /// by [`Projection::InfiniteReverseZ`].
+    pub fn matrix(&self, aspect: f32, znear: f32, zfar: f32) -> cgmath::Matrix4<f32> {
+        match *self {
+            Projection::Perspective { fovy } => {
+                cgmath::perspective(cgmath::Deg(fovy), aspect, znear, zfar)
+            }
+            Projection::Orthographic { height } => {
+                let (half_width, half_height) = (height * aspect / 2.0, height / 2.0);
+                cgmath::ortho(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    znear,
+                    zfar,
+                )
+            }
+            Projection::InfiniteReverseZ { fovy } => {
+                let focal = 1.0 / (cgmath::Deg(fovy) / 2.0).tan();
+                // After `OPENGL_TO_WGPU_MATRIX` depth is `znear / distance`.
+                #[rustfmt::skip]
+                let matrix = cgmath::Matrix4::new(
+                    focal / aspect, 0.0, 0.0, 0.0,
+                    0.0, focal, 0.0, 0.0,
+                    0.0, 0.0, 1.0, -1.0,
+                    0.0, 0.0, 2.0 * znear, 0.0,
+                );
+                matrix
+            }
+        }
+    }
+
+    /// Half the visible height at `distance` in front of the camera.
+    pub fn half_height(&self, distance: f32) -> f32 {
+        match *self {
+            Projection::Perspective { fovy } | Projection::InfiniteReverseZ { fovy } => {
+                distance * (cgmath::Deg(fovy) / 2.0).tan()
+            }
+            Projection::Orthographic { height } => height / 2.0,
+        }
+    }
+
+    pub fn is_reverse_z(&self) -> bool {
+        matches!(self, Projection::InfiniteReverseZ { .. })
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Camera {
     pub eye: cgmath::Point3<f32>,
     pub target: cgmath::Point3<f32>,
     pub up: cgmath::Vector3<f32>,
     pub aspect: f32,
-    pub fovy: f32,
+    pub projection: Projection,
     pub znear: f32,
     pub zfar: f32,
 }
@@ -26,7 +99,7 @@ impl Camera {
             target,
             up: cgmath::Vector3::unit_y(),
             aspect,
-            fovy: 45.0,
+            projection: Projection::default(),
             znear: 0.1,
             zfar: 100.0,
         }
@@ -34,10 +107,38 @@ impl Camera {
 
     pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
         let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
-        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+        let proj = self.projection.matrix(self.aspect, self.znear, self.zfar);
         proj * view
     }
 
+    /// The world-space ray through `position`, in pixels from the top left of a viewport of
+    /// `size`, such as [`crate::input::InputService::cursor_position`]. It starts on the
+    /// near plane.
+    pub fn screen_ray(&self, position: PhysicalPosition<f64>, size: PhysicalSize<u32>) -> Ray {
+        let x = 2.0 * position.x as f32 / size.width.max(1) as f32 - 1.0;
+        let y = 1.0 - 2.0 * position.y as f32 / size.height.max(1) as f32;
+        let view_proj = OPENGL_TO_WGPU_MATRIX * self.build_view_projection_matrix();
+        let Some(inverse) = view_proj.invert() else {
+            return Ray::new(self.eye, self.target - self.eye);
+        };
+
+        let unproject = |depth: f32| {
+            cgmath::Point3::from_homogeneous(inverse * cgmath::Vector4::new(x, y, depth, 1.0))
+        };
+        // Halfway through the depth range is finite even without a far plane.
+        let near = unproject(if self.projection.is_reverse_z() {
+            1.0
+        } else {
+            0.0
+        });
+        Ray::new(near, unproject(0.5) - near)
+    }
+
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
     pub fn set_aspect(&mut self, aspect: f32) {
         self.aspect = aspect;
     }
@@ -47,17 +148,21 @@ impl Camera {
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
     pub view_proj: [[f32; 4]; 4],
+    /// World-space eye position; `w` is unused.
+    pub view_position: [f32; 4],
 }
 
 impl CameraUniform {
     pub fn new() -> Self {
         Self {
             view_proj: cgmath::Matrix4::identity().into(),
+            view_position: [0.0, 0.0, 0.0, 1.0],
         }
     }
 
     pub fn update_view_proj(&mut self, camera: &Camera) {
         self.view_proj = (OPENGL_TO_WGPU_MATRIX * camera.build_view_projection_matrix()).into();
+        self.view_position = camera.eye.to_homogeneous().into();
     }
 
     pub fn from_camera(camera: &Camera) -> Self {
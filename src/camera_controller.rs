@@ -0,0 +1,212 @@
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Transform, Vector3};
+use winit::{event::MouseButton, keyboard::KeyCode};
+
+use crate::{
+    camera::Camera,
+    input::InputService,
+    scene::{CameraComponent, EntityId, Scene},
+};
+
+const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0;
+
+/// The yaw and pitch in radians of looking along `direction`. Yaw 0 looks down -Z and
+/// increases towards +X.
+fn yaw_pitch(direction: Vector3<f32>) -> (f32, f32) {
+    let direction = direction.normalize();
+    (
+        direction.x.atan2(-direction.z),
+        direction.y.clamp(-1.0, 1.0).asin(),
+    )
+}
+
+fn look_direction(yaw: f32, pitch: f32) -> Vector3<f32> {
+    Vector3::new(
+        pitch.cos() * yaw.sin(),
+        pitch.sin(),
+        -pitch.cos() * yaw.cos(),
+    )
+}
+
+/// WASD movement with mouse look. Q and E move down and up, Shift moves faster.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlyController {
+    /// World units per second.
+    pub speed: f32,
+    /// Speed multiplier while Shift is held.
+    pub boost: f32,
+    /// Radians per pixel of mouse movement.
+    pub sensitivity: f32,
+    /// The button to hold for mouse look, or `None` to always look.
+    pub look_button: Option<MouseButton>,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl FlyController {
+    /// A controller that starts out looking the way `camera` does.
+    pub fn new(camera: &Camera) -> Self {
+        let (yaw, pitch) = yaw_pitch(camera.target - camera.eye);
+        Self {
+            speed: 5.0,
+            boost: 4.0,
+            sensitivity: 0.003,
+            look_button: Some(MouseButton::Right),
+            yaw,
+            pitch,
+        }
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, input: &InputService, dt: f32) {
+        if self
+            .look_button
+            .is_none_or(|button| input.is_mouse_button_pressed(button))
+        {
+            let [dx, dy] = input.mouse_delta();
+            self.yaw += dx * self.sensitivity;
+            self.pitch = (self.pitch - dy * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        let forward = look_direction(self.yaw, self.pitch);
+        let right = forward.cross(Vector3::unit_y()).normalize();
+        let axis = |positive, negative| {
+            input.is_key_pressed(positive) as i32 as f32
+                - input.is_key_pressed(negative) as i32 as f32
+        };
+        let movement = forward * axis(KeyCode::KeyW, KeyCode::KeyS)
+            + right * axis(KeyCode::KeyD, KeyCode::KeyA)
+            + Vector3::unit_y() * axis(KeyCode::KeyE, KeyCode::KeyQ);
+        if movement.magnitude2() > 0.0 {
+            let boosted = input.is_key_pressed(KeyCode::ShiftLeft)
+                || input.is_key_pressed(KeyCode::ShiftRight);
+            let speed = if boosted {
+                self.speed * self.boost
+            } else {
+                self.speed
+            };
+            camera.eye += movement.normalize() * speed * dt;
+        }
+        camera.target = camera.eye + forward;
+        camera.up = Vector3::unit_y();
+    }
+}
+
+/// Circles `target` while a mouse button is dragged and zooms with the wheel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OrbitController {
+    pub target: Point3<f32>,
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Radians per pixel of mouse movement.
+    pub sensitivity: f32,
+    /// Fraction of the distance each wheel line zooms by.
+    pub zoom_speed: f32,
+    pub rotate_button: MouseButton,
+}
+
+impl OrbitController {
+    /// A controller orbiting `camera`'s target from where the camera is.
+    pub fn new(camera: &Camera) -> Self {
+        let offset = camera.eye - camera.target;
+        let (yaw, pitch) = yaw_pitch(-offset);
+        Self {
+            target: camera.target,
+            distance: offset.magnitude(),
+            min_distance: 0.5,
+            max_distance: 500.0,
+            yaw,
+            pitch,
+            sensitivity: 0.005,
+            zoom_speed: 0.1,
+            rotate_button: MouseButton::Left,
+        }
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, input: &InputService) {
+        if input.is_mouse_button_pressed(self.rotate_button) {
+            let [dx, dy] = input.mouse_delta();
+            self.yaw += dx * self.sensitivity;
+            self.pitch = (self.pitch - dy * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+        let zoom = (1.0 - self.zoom_speed).powf(input.scroll_delta());
+        self.distance = (self.distance * zoom).clamp(self.min_distance, self.max_distance);
+
+        camera.target = self.target;
+        camera.eye = self.target - look_direction(self.yaw, self.pitch) * self.distance;
+        camera.up = Vector3::unit_y();
+    }
+}
+
+/// Trails an entity, easing towards a point fixed in the entity's local space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FollowController {
+    pub target: EntityId,
+    /// Where the camera sits, in the target's local space.
+    pub offset: Vector3<f32>,
+    /// Where the camera looks, in the target's local space.
+    pub look_at: Vector3<f32>,
+    /// How quickly the camera catches up, per second; 0 snaps straight to the target.
+    pub smoothing: f32,
+}
+
+impl FollowController {
+    pub fn new(target: EntityId) -> Self {
+        Self {
+            target,
+            offset: Vector3::new(0.0, 2.0, 5.0),
+            look_at: Vector3::new(0.0, 1.0, 0.0),
+            smoothing: 5.0,
+        }
+    }
+
+    pub fn with_offset(mut self, offset: Vector3<f32>) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn update(&self, camera: &mut Camera, scene: &Scene, dt: f32) {
+        let Some(world) = scene.world_transform(self.target) else {
+            return;
+        };
+        let eye = world.transform_point(Point3::from_vec(self.offset));
+        let target = world.transform_point(Point3::from_vec(self.look_at));
+        // Frame-rate independent exponential easing.
+        let t = if self.smoothing > 0.0 {
+            1.0 - (-self.smoothing * dt).exp()
+        } else {
+            1.0
+        };
+        camera.eye += (eye - camera.eye) * t;
+        camera.target += (target - camera.target) * t;
+        camera.up = Vector3::unit_y();
+    }
+}
+
+impl Scene {
+    /// Moves the active camera with the controller on its entity, checking for a
+    /// [`FlyController`], then an [`OrbitController`], then a [`FollowController`].
+    pub fn update_camera_controller(&mut self, input: &InputService, dt: f32) {
+        let Some(entity) = self.active_camera else {
+            return;
+        };
+        let Some(mut camera) = self.get::<CameraComponent>(entity).map(|c| c.camera) else {
+            return;
+        };
+
+        if let Some(controller) = self.get_mut::<FlyController>(entity) {
+            controller.update(&mut camera, input, dt);
+        } else if let Some(controller) = self.get_mut::<OrbitController>(entity) {
+            controller.update(&mut camera, input);
+        } else if let Some(&controller) = self.get::<FollowController>(entity) {
+            controller.update(&mut camera, self, dt);
+        } else {
+            return;
+        }
+
+        if let Some(component) = self.get_mut::<CameraComponent>(entity) {
+            component.camera = camera;
+        }
+    }
+}
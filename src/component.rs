@@ -0,0 +1,168 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+};
+
+use crate::scene::EntityId;
+
+trait ErasedStorage: Any {
+    fn remove_entity(&mut self, entity: EntityId) -> bool;
+    fn len(&self) -> usize;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> ErasedStorage for HashMap<EntityId, T> {
+    fn remove_entity(&mut self, entity: EntityId) -> bool {
+        self.remove(&entity).is_some()
+    }
+
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[derive(Default)]
+pub struct Components {
+    storages: HashMap<TypeId, Box<dyn ErasedStorage>>,
+}
+
+impl Components {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert<T: 'static>(&mut self, entity: EntityId, component: T) -> Option<T> {
+        self.storage_mut::<T>().insert(entity, component)
+    }
+
+    pub fn get<T: 'static>(&self, entity: EntityId) -> Option<&T> {
+        self.storage::<T>()?.get(&entity)
+    }
+
+    pub fn get_mut<T: 'static>(&mut self, entity: EntityId) -> Option<&mut T> {
+        self.existing_storage_mut::<T>()?.get_mut(&entity)
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: EntityId) -> Option<T> {
+        self.existing_storage_mut::<T>()?.remove(&entity)
+    }
+
+    pub fn contains<T: 'static>(&self, entity: EntityId) -> bool {
+        self.storage::<T>()
+            .is_some_and(|storage| storage.contains_key(&entity))
+    }
+
+    pub fn remove_entity(&mut self, entity: EntityId) {
+        for storage in self.storages.values_mut() {
+            storage.remove_entity(entity);
+        }
+    }
+
+    pub fn storage<T: 'static>(&self) -> Option<&HashMap<EntityId, T>> {
+        self.storages
+            .get(&TypeId::of::<T>())?
+            .as_any()
+            .downcast_ref()
+    }
+
+    pub fn storage_mut<T: 'static>(&mut self) -> &mut HashMap<EntityId, T> {
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(HashMap::<EntityId, T>::new()))
+            .as_any_mut()
+            .downcast_mut()
+            .expect("component storage registered under the wrong type")
+    }
+
+    fn existing_storage_mut<T: 'static>(&mut self) -> Option<&mut HashMap<EntityId, T>> {
+        self.storages
+            .get_mut(&TypeId::of::<T>())?
+            .as_any_mut()
+            .downcast_mut()
+    }
+
+    pub fn iter<T: 'static>(&self) -> impl Iterator<Item = (EntityId, &T)> {
+        self.storage::<T>().into_iter().flat_map(|storage| {
+            storage
+                .iter()
+                .map(|(entity, component)| (*entity, component))
+        })
+    }
+
+    pub fn iter_mut<T: 'static>(&mut self) -> impl Iterator<Item = (EntityId, &mut T)> {
+        self.existing_storage_mut::<T>()
+            .into_iter()
+            .flat_map(|storage| {
+                storage
+                    .iter_mut()
+                    .map(|(entity, component)| (*entity, component))
+            })
+    }
+
+    pub fn query<Q: Query>(&self) -> impl Iterator<Item = (EntityId, Q::Item<'_>)> {
+        Q::candidates(self)
+            .filter_map(move |entity| Q::fetch(self, entity).map(|item| (entity, item)))
+    }
+
+    /// The position in `types` of the storage with the fewest entities, or `None` when a
+    /// type has no storage and so no entity can match.
+    fn smallest_storage(&self, types: &[TypeId]) -> Option<usize> {
+        let mut smallest: Option<(usize, usize)> = None;
+        for (index, type_id) in types.iter().enumerate() {
+            let len = self.storages.get(type_id)?.len();
+            if smallest.is_none_or(|(_, min)| len < min) {
+                smallest = Some((index, len));
+            }
+        }
+        smallest.map(|(index, _)| index)
+    }
+}
+
+/// A set of component types fetched together by [`Components::query`], implemented for
+/// tuples of up to four component types. Entities are visited in no particular order.
+pub trait Query {
+    type Item<'a>;
+
+    /// The entities of the smallest storage among the queried types.
+    fn candidates(components: &Components) -> impl Iterator<Item = EntityId> + '_;
+    fn fetch(components: &Components, entity: EntityId) -> Option<Self::Item<'_>>;
+}
+
+macro_rules! impl_query {
+    ($(($component:ident, $index:tt)),+) => {
+        impl<$($component: 'static),+> Query for ($($component,)+) {
+            type Item<'a> = ($(&'a $component,)+);
+
+            fn candidates(components: &Components) -> impl Iterator<Item = EntityId> + '_ {
+                let smallest = components.smallest_storage(&[$(TypeId::of::<$component>()),+]);
+                // Only the smallest storage is `Some`, the chain walks its keys in place.
+                std::iter::empty()
+                    $(.chain(
+                        components
+                            .storage::<$component>()
+                            .filter(|_| smallest == Some($index))
+                            .into_iter()
+                            .flat_map(|storage| storage.keys().copied()),
+                    ))+
+            }
+
+            fn fetch(components: &Components, entity: EntityId) -> Option<Self::Item<'_>> {
+                Some(($(components.get::<$component>(entity)?,)+))
+            }
+        }
+    };
+}
+
+impl_query!((A, 0));
+impl_query!((A, 0), (B, 1));
+impl_query!((A, 0), (B, 1), (C, 2));
+impl_query!((A, 0), (B, 1), (C, 2), (D, 3));
@@ -0,0 +1,77 @@
+// Tests each instance's bounds against the camera frustum and appends the visible ones,
+// counting them into the batch's indirect draw arguments.
+
+// `InstanceRaw` as raw words: the model matrix's columns, the normal matrix and the
+// shadow flags. Copied as integers so the flags' bit patterns, which are subnormal as
+// floats, survive devices that flush denormals.
+struct Instance {
+    words: array<u32, 26>,
+}
+
+fn column(instance: Instance, first: u32) -> vec3<f32> {
+    return bitcast<vec3<f32>>(vec3<u32>(
+        instance.words[first],
+        instance.words[first + 1u],
+        instance.words[first + 2u],
+    ));
+}
+
+struct Frustum {
+    planes: array<vec4<f32>, 6>,
+}
+
+struct Batch {
+    bounds_min: vec4<f32>,
+    bounds_max: vec4<f32>,
+    instance_count: u32,
+}
+
+struct DrawArgs {
+    index_count: u32,
+    instance_count: atomic<u32>,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> frustum: Frustum;
+
+@group(1) @binding(0)
+var<uniform> batch: Batch;
+@group(1) @binding(1)
+var<storage, read> instances: array<Instance>;
+@group(1) @binding(2)
+var<storage, read_write> visible: array<Instance>;
+@group(1) @binding(3)
+var<storage, read_write> draw: DrawArgs;
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= batch.instance_count {
+        return;
+    }
+
+    let instance = instances[index];
+    let x_axis = column(instance, 0u);
+    let y_axis = column(instance, 4u);
+    let z_axis = column(instance, 8u);
+    let translation = column(instance, 12u);
+
+    let local_center = (batch.bounds_min.xyz + batch.bounds_max.xyz) * 0.5;
+    let half = (batch.bounds_max.xyz - batch.bounds_min.xyz) * 0.5;
+    let center = x_axis * local_center.x + y_axis * local_center.y + z_axis * local_center.z
+        + translation;
+    let extents = abs(x_axis) * half.x + abs(y_axis) * half.y + abs(z_axis) * half.z;
+
+    for (var i = 0u; i < 6u; i += 1u) {
+        let plane = frustum.planes[i];
+        if dot(plane.xyz, center) + plane.w < -dot(abs(plane.xyz), extents) {
+            return;
+        }
+    }
+
+    let slot = atomicAdd(&draw.instance_count, 1u);
+    visible[slot] = instance;
+}
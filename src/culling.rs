@@ -0,0 +1,128 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix, Matrix4, Point3, Vector3, Vector4};
+
+use crate::camera::{Camera, CameraUniform};
+
+/// An axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    /// The smallest box around `points`, or an empty box at the origin without any.
+    pub fn from_points(points: impl IntoIterator<Item = Point3<f32>>) -> Self {
+        let mut points = points.into_iter();
+        let Some(first) = points.next() else {
+            return Self::new(Point3::origin(), Point3::origin());
+        };
+        points.fold(Self::new(first, first), |aabb, point| Self {
+            min: Point3::new(
+                aabb.min.x.min(point.x),
+                aabb.min.y.min(point.y),
+                aabb.min.z.min(point.z),
+            ),
+            max: Point3::new(
+                aabb.max.x.max(point.x),
+                aabb.max.y.max(point.y),
+                aabb.max.z.max(point.z),
+            ),
+        })
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+
+    pub fn half_extents(&self) -> Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+
+    /// The radius of the sphere around [`Aabb::center`] enclosing the box.
+    pub fn radius(&self) -> f32 {
+        self.half_extents().magnitude()
+    }
+
+    /// The box enclosing this one after `matrix` is applied to it.
+    pub fn transformed(&self, matrix: &Matrix4<f32>) -> Self {
+        let center = matrix * self.center().to_homogeneous();
+        let half = self.half_extents();
+        let extent =
+            |row: Vector4<f32>| row.x.abs() * half.x + row.y.abs() * half.y + row.z.abs() * half.z;
+        let extents = Vector3::new(
+            extent(matrix.row(0)),
+            extent(matrix.row(1)),
+            extent(matrix.row(2)),
+        );
+        let center = Point3::from_homogeneous(center);
+        Self::new(center - extents, center + extents)
+    }
+}
+
+/// The six planes bounding a camera's view volume, facing inwards.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes of a view-projection matrix into wgpu's clip space, where depth
+    /// runs from 0 to 1 in either direction.
+    pub fn from_matrix(view_proj: Matrix4<f32>) -> Self {
+        let [x, y, z, w] = [0, 1, 2, 3].map(|row| view_proj.row(row));
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|plane| {
+            let length = plane.truncate().magnitude();
+            if length > f32::EPSILON {
+                plane / length
+            } else {
+                // Infinite projections have no far plane; let everything through.
+                Vector4::new(0.0, 0.0, 0.0, 1.0)
+            }
+        });
+        Self { planes }
+    }
+
+    pub fn from_camera(camera: &Camera) -> Self {
+        Self::from_matrix(CameraUniform::from_camera(camera).view_proj.into())
+    }
+
+    /// The `(normal, distance)` of each plane, normalized; points inside have a
+    /// non-negative dot product with all of them.
+    pub fn planes(&self) -> [Vector4<f32>; 6] {
+        self.planes
+    }
+
+    pub fn contains_point(&self, point: Point3<f32>) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.dot(point.to_homogeneous()) >= 0.0)
+    }
+
+    /// Whether any part of `aabb` may be inside. Boxes near a frustum corner can pass
+    /// while being outside, which only costs drawing them.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let center = aabb.center().to_homogeneous();
+        let half = aabb.half_extents();
+        self.planes.iter().all(|plane| {
+            let radius = plane.x.abs() * half.x + plane.y.abs() * half.y + plane.z.abs() * half.z;
+            plane.dot(center) >= -radius
+        })
+    }
+}
+
+/// How many instances the last culling pass kept and skipped.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CullStats {
+    pub visible: u32,
+    pub culled: u32,
+}
+
+impl CullStats {
+    pub fn total(&self) -> u32 {
+        self.visible + self.culled
+    }
+}
@@ -1,6 +1,6 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use log::debug;
 use wgpu::CurrentSurfaceTexture;
 use winit::{
@@ -20,16 +20,15 @@ pub struct Engine<'window> {
     pub windows: WindowService<'window>,
     pub input: InputService,
     pub scene: Scene,
+    last_update: Instant,
 }
 
 impl Engine<'static> {
     pub async fn new(first_window: Arc<Window>) -> Result<Self> {
-        let (ctx, renderer) = GpuContext::new(first_window.clone()).await?;
-        let scene = Scene::default_instanced(
-            &ctx,
-            renderer.texture_bind_group_layout(),
-            renderer.surface.aspect(),
-        )?;
+        let (ctx, renderer) = GpuContext::new(Some(first_window.clone())).await?;
+        let renderer = renderer.context("GPU context was created without a window renderer")?;
+        let scene =
+            Scene::default_instanced(&ctx, renderer.material_layout(), renderer.surface.aspect())?;
         let mut windows = WindowService::new();
         windows.insert(WindowState::new(first_window, renderer));
 
@@ -38,6 +37,7 @@ impl Engine<'static> {
             windows,
             input: InputService::new(),
             scene,
+            last_update: Instant::now(),
         })
     }
 
@@ -58,6 +58,30 @@ impl<'window> Engine<'window> {
         }
     }
 
+    /// Switches a window's scene rendering to `samples` per pixel, see
+    /// [`crate::renderer::RenderResources::set_msaa_samples`].
+    pub fn set_msaa_samples(&mut self, id: WindowId, samples: u32) -> Result<()> {
+        let window = self
+            .windows
+            .get_mut(id)
+            .with_context(|| format!("missing window id {:?}", id))?;
+        window
+            .renderer
+            .resources
+            .set_msaa_samples(&self.ctx, samples)
+    }
+
+    /// Advances per-frame systems such as camera controllers, then resets the frame's input
+    /// deltas.
+    pub fn update(&mut self) {
+        let now = Instant::now();
+        let dt = (now - self.last_update).as_secs_f32();
+        self.last_update = now;
+
+        self.scene.update_camera_controller(&self.input, dt);
+        self.input.end_frame();
+    }
+
     pub fn set_window_focused(&mut self, id: WindowId, focused: bool) {
         self.windows.set_focused(id, focused);
     }
@@ -68,6 +92,9 @@ impl<'window> Engine<'window> {
             return None;
         };
 
+        let gpu_culled = window.renderer.resources.gpu_culling().is_some();
+        self.scene.prepare_render_batches(&self.ctx, gpu_culled);
+
         window.renderer.render(&self.ctx, &self.scene)
     }
 
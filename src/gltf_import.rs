@@ -0,0 +1,449 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use base64::Engine as _;
+use log::warn;
+
+use crate::{
+    pbr::{MaterialLayout, PbrMaterial},
+    primitives::MeshData,
+    renderer::GpuContext,
+    scene::{
+        EntityId, Material, MaterialHandle, Mesh, MeshHandle, MeshRendererComponent, Scene,
+        TransformComponent,
+    },
+    texture::{SamplerDescriptor, Texture},
+};
+
+/// Asset path fragments used to address the contents of a glTF file, e.g.
+/// `models/tree.glb#mesh0/primitive1` or `models/tree.glb#material2`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GltfAsset {
+    Primitive {
+        mesh: usize,
+        primitive: usize,
+    },
+    /// `None` is the glTF default material, used by primitives without one.
+    Material(Option<usize>),
+}
+
+impl GltfAsset {
+    /// Splits `path` into the glTF file and the asset inside it, if it points into one.
+    pub fn parse(path: &str) -> Option<(&str, Self)> {
+        let (file, fragment) = path.rsplit_once('#')?;
+        let extension = Path::new(file).extension()?.to_str()?;
+        if !extension.eq_ignore_ascii_case("gltf") && !extension.eq_ignore_ascii_case("glb") {
+            return None;
+        }
+
+        let asset = if fragment == "material-default" {
+            Self::Material(None)
+        } else if let Some(material) = fragment.strip_prefix("material") {
+            Self::Material(Some(material.parse().ok()?))
+        } else {
+            let (mesh, primitive) = fragment.strip_prefix("mesh")?.split_once("/primitive")?;
+            Self::Primitive {
+                mesh: mesh.parse().ok()?,
+                primitive: primitive.parse().ok()?,
+            }
+        };
+        Some((file, asset))
+    }
+
+    pub fn path(&self, file: &str) -> String {
+        match self {
+            Self::Primitive { mesh, primitive } => {
+                format!("{file}#mesh{mesh}/primitive{primitive}")
+            }
+            Self::Material(Some(material)) => format!("{file}#material{material}"),
+            Self::Material(None) => format!("{file}#material-default"),
+        }
+    }
+}
+
+/// A parsed glTF or GLB file with its buffers resolved.
+pub struct GltfDocument {
+    pub source: String,
+    document: gltf::Document,
+    buffers: Vec<Vec<u8>>,
+    base_dir: Option<PathBuf>,
+}
+
+impl GltfDocument {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("failed to read glTF file {}", path.display()))?;
+        Self::from_slice(&bytes, path.to_string_lossy(), path.parent())
+    }
+
+    /// `base_dir` resolves external buffer and image URIs; without it only embedded data
+    /// can be loaded.
+    pub fn from_slice(
+        bytes: &[u8],
+        source: impl Into<String>,
+        base_dir: Option<&Path>,
+    ) -> Result<Self> {
+        let source = source.into();
+        let gltf::Gltf { document, mut blob } = gltf::Gltf::from_slice(bytes)
+            .with_context(|| format!("failed to parse glTF file `{source}`"))?;
+        let base_dir = base_dir.map(Path::to_path_buf);
+
+        let mut buffers = Vec::with_capacity(document.buffers().len());
+        for buffer in document.buffers() {
+            let data = match buffer.source() {
+                gltf::buffer::Source::Bin => blob
+                    .take()
+                    .with_context(|| format!("`{source}` has no binary chunk"))?,
+                gltf::buffer::Source::Uri(uri) => read_uri(base_dir.as_deref(), uri)
+                    .with_context(|| format!("failed to load buffer {}", buffer.index()))?,
+            };
+            ensure!(
+                data.len() >= buffer.length(),
+                "buffer {} of `{source}` is shorter than declared",
+                buffer.index()
+            );
+            buffers.push(data);
+        }
+
+        Ok(Self {
+            source,
+            document,
+            buffers,
+            base_dir,
+        })
+    }
+
+    pub fn document(&self) -> &gltf::Document {
+        &self.document
+    }
+
+    pub fn load_primitive(&self, ctx: &GpuContext, mesh: usize, primitive: usize) -> Result<Mesh> {
+        let label = GltfAsset::Primitive { mesh, primitive }.path(&self.source);
+        let primitive = self
+            .document
+            .meshes()
+            .nth(mesh)
+            .and_then(|mesh| mesh.primitives().nth(primitive))
+            .with_context(|| format!("`{label}` does not exist"))?;
+        ensure!(
+            primitive.mode() == gltf::mesh::Mode::Triangles,
+            "`{label}` uses unsupported primitive mode {:?}",
+            primitive.mode()
+        );
+
+        let reader = primitive.reader(|buffer| self.buffers.get(buffer.index()).map(Vec::as_slice));
+        let positions = reader
+            .read_positions()
+            .with_context(|| format!("`{label}` has no POSITION attribute"))?;
+        let positions: Vec<[f32; 3]> = positions.collect();
+        let tex_coords = match reader.read_tex_coords(0) {
+            Some(tex_coords) => tex_coords.into_f32().collect(),
+            None => vec![[0.0, 0.0]; positions.len()],
+        };
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        ensure!(
+            tex_coords.len() == positions.len(),
+            "`{label}` has mismatched attribute counts"
+        );
+        ensure!(
+            indices
+                .iter()
+                .all(|&index| (index as usize) < positions.len()),
+            "`{label}` has out of range indices"
+        );
+
+        let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(Iterator::collect);
+        // Tangents only make sense relative to the normals they were authored against.
+        let tangents: Option<Vec<[f32; 4]>> = reader
+            .read_tangents()
+            .filter(|_| normals.is_some())
+            .map(Iterator::collect);
+        ensure!(
+            normals
+                .as_ref()
+                .is_none_or(|normals| normals.len() == positions.len())
+                && tangents
+                    .as_ref()
+                    .is_none_or(|tangents| tangents.len() == positions.len()),
+            "`{label}` has mismatched attribute counts"
+        );
+
+        let data = MeshData {
+            positions,
+            tex_coords,
+            normals,
+            tangents,
+            indices,
+        };
+        Ok(data.to_mesh(ctx, &label))
+    }
+
+    /// A `Shading::Pbr` material with every metallic-roughness texture and factor. The
+    /// default material (`None`) is the glTF default.
+    pub fn load_material(
+        &self,
+        ctx: &GpuContext,
+        material_layout: &MaterialLayout,
+        material: Option<usize>,
+    ) -> Result<Material> {
+        let label = GltfAsset::Material(material).path(&self.source);
+        let material = match material {
+            Some(index) => self
+                .document
+                .materials()
+                .nth(index)
+                .with_context(|| format!("`{label}` does not exist"))?,
+            None => return Material::pbr(ctx, material_layout, PbrMaterial::default(), &label),
+        };
+
+        let srgb = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let linear = wgpu::TextureFormat::Rgba8Unorm;
+        let load =
+            |texture, tex_coord, format| self.texture(ctx, texture, tex_coord, format, &label);
+        let pbr = material.pbr_metallic_roughness();
+        let normal = material.normal_texture();
+        let occlusion = material.occlusion_texture();
+        let description = PbrMaterial {
+            base_color_factor: pbr.base_color_factor(),
+            metallic_factor: pbr.metallic_factor(),
+            roughness_factor: pbr.roughness_factor(),
+            normal_scale: normal.as_ref().map_or(1.0, |normal| normal.scale()),
+            occlusion_strength: occlusion
+                .as_ref()
+                .map_or(1.0, |occlusion| occlusion.strength()),
+            emissive_factor: material.emissive_factor(),
+            base_color_texture: pbr
+                .base_color_texture()
+                .map(|info| load(info.texture(), info.tex_coord(), srgb))
+                .transpose()?,
+            metallic_roughness_texture: pbr
+                .metallic_roughness_texture()
+                .map(|info| load(info.texture(), info.tex_coord(), linear))
+                .transpose()?,
+            normal_texture: normal
+                .map(|info| load(info.texture(), info.tex_coord(), linear))
+                .transpose()?,
+            occlusion_texture: occlusion
+                .map(|info| load(info.texture(), info.tex_coord(), linear))
+                .transpose()?,
+            emissive_texture: material
+                .emissive_texture()
+                .map(|info| load(info.texture(), info.tex_coord(), srgb))
+                .transpose()?,
+            // The material binds one sampler, so the base color texture's wins.
+            sampler: pbr
+                .base_color_texture()
+                .map_or_else(SamplerDescriptor::default, |info| {
+                    sampler_descriptor(info.texture().sampler())
+                }),
+        };
+        Material::pbr(ctx, material_layout, description, &label)
+    }
+
+    fn texture(
+        &self,
+        ctx: &GpuContext,
+        texture: gltf::Texture<'_>,
+        tex_coord: u32,
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Result<Texture> {
+        if tex_coord != 0 {
+            warn!("`{label}` samples TEXCOORD_{tex_coord}, only TEXCOORD_0 is loaded");
+        }
+        let index = texture.source().index();
+        let bytes = self.image_bytes(texture.source())?;
+        let image = image::load_from_memory(&bytes)
+            .with_context(|| format!("failed to decode image {index} of `{label}`"))?;
+        Texture::from_image_with_format(ctx, &image, Some(label), format)
+    }
+
+    fn image_bytes(&self, image: gltf::Image<'_>) -> Result<Vec<u8>> {
+        match image.source() {
+            gltf::image::Source::View { view, .. } => {
+                let buffer = &self.buffers[view.buffer().index()];
+                let start = view.offset();
+                buffer
+                    .get(start..start + view.length())
+                    .map(<[u8]>::to_vec)
+                    .with_context(|| format!("image {} is out of bounds", image.index()))
+            }
+            gltf::image::Source::Uri { uri, .. } => read_uri(self.base_dir.as_deref(), uri)
+                .with_context(|| format!("failed to load image {}", image.index())),
+        }
+    }
+
+    /// Spawns the default scene (or the first one) under a new root entity named after the
+    /// file. Meshes and materials already registered under the same asset path are reused.
+    pub fn spawn(
+        &self,
+        ctx: &GpuContext,
+        material_layout: &MaterialLayout,
+        scene: &mut Scene,
+        parent: Option<EntityId>,
+    ) -> Result<EntityId> {
+        let gltf_scene = self
+            .document
+            .default_scene()
+            .or_else(|| self.document.scenes().next())
+            .with_context(|| format!("`{}` has no scenes", self.source))?;
+
+        let name = gltf_scene.name().map(str::to_owned).or_else(|| {
+            Path::new(&self.source)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        });
+        let root = scene.spawn(name, parent);
+        scene.set_transform(root, TransformComponent::identity());
+
+        let mut pending: Vec<(gltf::Node<'_>, EntityId)> =
+            gltf_scene.nodes().map(|node| (node, root)).collect();
+        pending.reverse();
+        while let Some((node, parent)) = pending.pop() {
+            let entity = scene.spawn(node.name().map(str::to_owned), Some(parent));
+            let (translation, [x, y, z, w], scale) = node.transform().decomposed();
+            scene.set_transform(
+                entity,
+                TransformComponent {
+                    translation: translation.into(),
+                    rotation: cgmath::Quaternion::new(w, x, y, z),
+                    scale: scale.into(),
+                },
+            );
+
+            if let Some(mesh) = node.mesh() {
+                let primitives = mesh.primitives().len();
+                for primitive in mesh.primitives() {
+                    let target = if primitives == 1 {
+                        entity
+                    } else {
+                        let name = format!(
+                            "{} primitive {}",
+                            node.name().unwrap_or("Node"),
+                            primitive.index()
+                        );
+                        let child = scene.spawn(Some(name), Some(entity));
+                        scene.set_transform(child, TransformComponent::identity());
+                        child
+                    };
+
+                    let mesh = self.mesh_handle(ctx, scene, mesh.index(), primitive.index())?;
+                    let material = self.material_handle(
+                        ctx,
+                        material_layout,
+                        scene,
+                        primitive.material().index(),
+                    )?;
+                    scene.add_mesh_renderer(target, MeshRendererComponent { mesh, material });
+                }
+            }
+
+            let first_child = pending.len();
+            pending.extend(node.children().map(|child| (child, entity)));
+            pending[first_child..].reverse();
+        }
+
+        Ok(root)
+    }
+
+    fn mesh_handle(
+        &self,
+        ctx: &GpuContext,
+        scene: &mut Scene,
+        mesh: usize,
+        primitive: usize,
+    ) -> Result<MeshHandle> {
+        let path = GltfAsset::Primitive { mesh, primitive }.path(&self.source);
+        if let Some(handle) = scene.find_mesh(&path) {
+            return Ok(handle);
+        }
+        let mesh = self.load_primitive(ctx, mesh, primitive)?;
+        Ok(scene.add_mesh_asset(path, mesh))
+    }
+
+    fn material_handle(
+        &self,
+        ctx: &GpuContext,
+        material_layout: &MaterialLayout,
+        scene: &mut Scene,
+        material: Option<usize>,
+    ) -> Result<MaterialHandle> {
+        let path = GltfAsset::Material(material).path(&self.source);
+        if let Some(handle) = scene.find_material(&path) {
+            return Ok(handle);
+        }
+        let material = self.load_material(ctx, material_layout, material)?;
+        Ok(scene.add_material_asset(path, material))
+    }
+}
+
+impl Scene {
+    /// Imports a `.gltf` or `.glb` file, returning the root entity of the spawned subtree.
+    /// [`Scene::save`] stores `path` relative to the scene file.
+    pub fn import_gltf(
+        &mut self,
+        ctx: &GpuContext,
+        material_layout: &MaterialLayout,
+        path: impl AsRef<Path>,
+        parent: Option<EntityId>,
+    ) -> Result<EntityId> {
+        GltfDocument::open(path)?.spawn(ctx, material_layout, self, parent)
+    }
+}
+
+fn read_uri(base_dir: Option<&Path>, uri: &str) -> Result<Vec<u8>> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, payload) = data
+            .split_once(";base64,")
+            .context("only base64 data URIs are supported")?;
+        return Ok(base64::engine::general_purpose::STANDARD.decode(payload)?);
+    }
+    if uri.contains("://") {
+        bail!("unsupported URI scheme in `{uri}`");
+    }
+
+    let base_dir =
+        base_dir.with_context(|| format!("cannot resolve `{uri}` without a base directory"))?;
+    let path = base_dir.join(uri);
+    std::fs::read(&path).with_context(|| format!("failed to read {}", path.display()))
+}
+
+fn sampler_descriptor(sampler: gltf::texture::Sampler<'_>) -> SamplerDescriptor {
+    use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+    let address_mode = |mode| match mode {
+        WrappingMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+        WrappingMode::Repeat => wgpu::AddressMode::Repeat,
+    };
+    let nearest = wgpu::FilterMode::Nearest;
+    let linear = wgpu::FilterMode::Linear;
+    let defaults = SamplerDescriptor::default();
+    let (min_filter, mipmap_filter) = match sampler.min_filter() {
+        None => (defaults.min_filter, defaults.mipmap_filter),
+        Some(MinFilter::Nearest | MinFilter::NearestMipmapNearest) => {
+            (nearest, wgpu::MipmapFilterMode::Nearest)
+        }
+        Some(MinFilter::Linear | MinFilter::LinearMipmapNearest) => {
+            (linear, wgpu::MipmapFilterMode::Nearest)
+        }
+        Some(MinFilter::NearestMipmapLinear) => (nearest, wgpu::MipmapFilterMode::Linear),
+        Some(MinFilter::LinearMipmapLinear) => (linear, wgpu::MipmapFilterMode::Linear),
+    };
+    SamplerDescriptor {
+        address_mode_u: address_mode(sampler.wrap_s()),
+        address_mode_v: address_mode(sampler.wrap_t()),
+        mag_filter: match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => nearest,
+            Some(MagFilter::Linear) => linear,
+            None => defaults.mag_filter,
+        },
+        min_filter,
+        mipmap_filter,
+        ..defaults
+    }
+}
@@ -0,0 +1,167 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use log::warn;
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    pbr::MaterialLayout,
+    renderer::{GpuContext, HeadlessRenderer},
+    scene::Scene,
+};
+
+pub const UPDATE_GOLDEN_ENV: &str = "UPDATE_GOLDEN";
+
+pub struct GoldenTest {
+    pub name: String,
+    pub size: PhysicalSize<u32>,
+    pub tolerance: u8,
+    pub golden_dir: PathBuf,
+    pub output_dir: PathBuf,
+}
+
+impl GoldenTest {
+    pub fn new(name: impl Into<String>) -> Self {
+        let root = std::env::var_os("CARGO_MANIFEST_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("."));
+
+        Self {
+            name: name.into(),
+            size: PhysicalSize::new(256, 192),
+            tolerance: 2,
+            golden_dir: root.join("tests").join("golden"),
+            output_dir: root.join("target").join("golden"),
+        }
+    }
+
+    pub fn golden_path(&self) -> PathBuf {
+        self.golden_dir.join(format!("{}.png", self.name))
+    }
+
+    pub fn run<F>(&self, build_scene: F) -> Result<()>
+    where
+        F: FnOnce(&GpuContext, &MaterialLayout, f32) -> Result<Scene>,
+    {
+        let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+        let mut renderer = HeadlessRenderer::new(&ctx, self.size);
+        let mut scene = build_scene(&ctx, renderer.material_layout(), renderer.target.aspect())?;
+        let actual = renderer.render_to_image(&ctx, &mut scene)?;
+
+        if ctx.is_noop() {
+            warn!(
+                "skipping golden comparison for `{}`: the noop adapter does not rasterize",
+                self.name
+            );
+            return Ok(());
+        }
+
+        self.compare(&actual)
+    }
+
+    pub fn compare(&self, actual: &image::RgbaImage) -> Result<()> {
+        let golden_path = self.golden_path();
+        let actual_path = self.output_dir.join(format!("{}.actual.png", self.name));
+        if std::env::var_os(UPDATE_GOLDEN_ENV).is_some() {
+            write_png(&golden_path, actual)?;
+            warn!("wrote golden image {}", golden_path.display());
+            return Ok(());
+        }
+        if !golden_path.exists() {
+            write_png(&actual_path, actual)?;
+            bail!(
+                "no golden image at {}; rendered output written to {}, re-run with {}=1 to accept it",
+                golden_path.display(),
+                actual_path.display(),
+                UPDATE_GOLDEN_ENV
+            );
+        }
+
+        let expected = image::open(&golden_path)
+            .with_context(|| format!("failed to load golden image {}", golden_path.display()))?
+            .to_rgba8();
+        let diff = ImageDiff::new(actual, &expected, self.tolerance)?;
+        if diff.mismatched_pixels == 0 {
+            return Ok(());
+        }
+
+        let diff_path = self.output_dir.join(format!("{}.diff.png", self.name));
+        write_png(&actual_path, actual)?;
+        write_png(&diff_path, &diff.image)?;
+
+        bail!(
+            "`{}` differs from {}: {} pixels exceed tolerance {} (max channel delta {}); see {}",
+            self.name,
+            golden_path.display(),
+            diff.mismatched_pixels,
+            self.tolerance,
+            diff.max_delta,
+            diff_path.display()
+        )
+    }
+}
+
+pub struct ImageDiff {
+    pub mismatched_pixels: usize,
+    pub max_delta: u8,
+    pub image: image::RgbaImage,
+}
+
+impl ImageDiff {
+    pub fn new(
+        actual: &image::RgbaImage,
+        expected: &image::RgbaImage,
+        tolerance: u8,
+    ) -> Result<Self> {
+        ensure!(
+            actual.dimensions() == expected.dimensions(),
+            "image size {:?} does not match golden size {:?}",
+            actual.dimensions(),
+            expected.dimensions()
+        );
+
+        let mut mismatched_pixels = 0;
+        let mut max_delta = 0;
+        let mut image = image::RgbaImage::new(actual.width(), actual.height());
+
+        for ((actual, expected), out) in actual
+            .pixels()
+            .zip(expected.pixels())
+            .zip(image.pixels_mut())
+        {
+            let delta = actual
+                .0
+                .iter()
+                .zip(expected.0.iter())
+                .map(|(a, e)| a.abs_diff(*e))
+                .max()
+                .unwrap_or(0);
+            max_delta = max_delta.max(delta);
+
+            *out = if delta > tolerance {
+                mismatched_pixels += 1;
+                image::Rgba([255, 0, 255, 255])
+            } else {
+                let [r, g, b, _] = expected.0;
+                let luma = ((r as u16 + g as u16 + b as u16) / 12) as u8;
+                image::Rgba([luma, luma, luma, 255])
+            };
+        }
+
+        Ok(Self {
+            mismatched_pixels,
+            max_delta,
+            image,
+        })
+    }
+}
+
+fn write_png(path: &Path, image: &image::RgbaImage) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    image
+        .save(path)
+        .with_context(|| format!("failed to write {}", path.display()))
+}
@@ -0,0 +1,437 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use cgmath::Vector4;
+use wgpu::util::{DeviceExt, DrawIndexedIndirectArgs};
+
+use crate::{
+    batch::RenderBatch,
+    culling::{CullStats, Frustum},
+    render_graph::{PassBuilder, PassContext, RenderGraphPass, ResourceId},
+    renderer::GpuContext,
+    scene::{CameraComponent, InstanceRaw, Scene},
+};
+
+const WORKGROUP_SIZE: u32 = 64;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BatchUniform {
+    bounds_min: [f32; 4],
+    bounds_max: [f32; 4],
+    instance_count: u32,
+    _padding: [u32; 3],
+}
+
+/// The compacted instances and indirect draw arguments of one [`RenderBatch`].
+struct CulledBatch {
+    uniform_buffer: wgpu::Buffer,
+    visible_buffer: wgpu::Buffer,
+    indirect_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    /// The instance buffer the bind group reads, replaced by the batch when it grows.
+    source: wgpu::Buffer,
+    instance_count: u32,
+}
+
+/// Where the copy of the visible counts into [`Readback::buffer`] stands.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ReadbackState {
+    Idle,
+    /// This frame's dispatch copies the counts; mapped once the frame is submitted.
+    Copying {
+        total: u32,
+    },
+    /// Mapping, with `mapped` set once the counts can be read.
+    Mapping {
+        total: u32,
+    },
+}
+
+/// Copies each batch's visible instance count out of its indirect arguments and maps them
+/// without waiting, so the counts arrive a frame or more late instead of stalling.
+struct Readback {
+    buffer: wgpu::Buffer,
+    capacity: usize,
+    batches: usize,
+    state: ReadbackState,
+    mapped: Arc<AtomicBool>,
+    stats: CullStats,
+}
+
+impl Readback {
+    fn create_buffer(ctx: &GpuContext, capacity: usize) -> wgpu::Buffer {
+        ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Readback Buffer"),
+            size: (capacity.max(1) * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Collects the counts of a finished mapping, then starts a new copy of `batches`
+    /// counts out of `total` instances when none is in flight.
+    fn prepare(&mut self, ctx: &GpuContext, batches: usize, total: u32) {
+        if let ReadbackState::Mapping { total } = self.state {
+            if !self.mapped.load(Ordering::Acquire) {
+                return;
+            }
+            let visible = {
+                let range = self.buffer.slice(..).get_mapped_range();
+                let counts: &[u32] = bytemuck::cast_slice(&range);
+                counts[..self.batches].iter().sum::<u32>()
+            };
+            self.buffer.unmap();
+            self.mapped.store(false, Ordering::Release);
+            self.stats = CullStats {
+                visible,
+                culled: total.saturating_sub(visible),
+            };
+            self.state = ReadbackState::Idle;
+        }
+
+        if batches > self.capacity {
+            self.capacity = batches.next_power_of_two();
+            self.buffer = Self::create_buffer(ctx, self.capacity);
+        }
+        self.batches = batches;
+        self.state = ReadbackState::Copying { total };
+    }
+
+    fn request(&mut self) {
+        let ReadbackState::Copying { total } = self.state else {
+            return;
+        };
+        let mapped = self.mapped.clone();
+        self.buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                mapped.store(result.is_ok(), Ordering::Release);
+            });
+        self.state = ReadbackState::Mapping { total };
+    }
+}
+
+/// Culls render batches against the active camera in a compute shader, so the scene pass
+/// draws them with `draw_indexed_indirect` without reading visibility back. Needs compute
+/// shaders and indirect draws, which WebGL lacks; see [`GpuCulling::is_supported`].
+pub struct GpuCulling {
+    pipeline: wgpu::ComputePipeline,
+    batch_bind_group_layout: wgpu::BindGroupLayout,
+    frustum_buffer: wgpu::Buffer,
+    frustum_bind_group: wgpu::BindGroup,
+    batches: Vec<CulledBatch>,
+    readback: Readback,
+}
+
+impl GpuCulling {
+    /// Whether the adapter can run the culling shader and draw its results.
+    pub fn is_supported(ctx: &GpuContext) -> bool {
+        let flags = ctx.adapter.get_downlevel_capabilities().flags;
+        flags.contains(
+            wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::INDIRECT_EXECUTION,
+        ) && ctx.device.limits().max_storage_buffers_per_shader_stage >= 3
+    }
+
+    pub fn new(ctx: &GpuContext) -> Self {
+        let frustum_bind_group_layout =
+            ctx.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    entries: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    }],
+                    label: Some("cull_frustum_bind_group_layout"),
+                });
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let batch_bind_group_layout =
+            ctx.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        storage(1, true),
+                        storage(2, false),
+                        storage(3, false),
+                    ],
+                    label: Some("cull_batch_bind_group_layout"),
+                });
+
+        let frustum_buffer = ctx
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Cull Frustum Buffer"),
+                contents: bytemuck::bytes_of(&[[0.0f32; 4]; 6]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+        let frustum_bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &frustum_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: frustum_buffer.as_entire_binding(),
+            }],
+            label: Some("cull_frustum_bind_group"),
+        });
+
+        let shader = ctx
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Cull Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("cull.wgsl").into()),
+            });
+        let layout = ctx
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Cull Pipeline Layout"),
+                immediate_size: 0,
+                bind_group_layouts: &[
+                    Some(&frustum_bind_group_layout),
+                    Some(&batch_bind_group_layout),
+                ],
+            });
+        let pipeline = ctx
+            .device
+            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Cull Pipeline"),
+                layout: Some(&layout),
+                module: &shader,
+                entry_point: Some("cs_main"),
+                compilation_options: Default::default(),
+                cache: None,
+            });
+
+        Self {
+            pipeline,
+            batch_bind_group_layout,
+            frustum_buffer,
+            frustum_bind_group,
+            batches: Vec::new(),
+            readback: Readback {
+                buffer: Readback::create_buffer(ctx, 1),
+                capacity: 1,
+                batches: 0,
+                state: ReadbackState::Idle,
+                mapped: Arc::new(AtomicBool::new(false)),
+                stats: CullStats::default(),
+            },
+        }
+    }
+
+    /// Uploads the active camera's frustum and each batch's bounds, and resets the draw
+    /// arguments the next [`GpuCulling::dispatch`] counts into. Also collects the visible
+    /// counts of an earlier frame once they have been read back.
+    pub fn prepare(&mut self, ctx: &GpuContext, scene: &Scene) {
+        // Lets a finished readback's callback run without waiting on the GPU.
+        let _ = ctx.device.poll(wgpu::PollType::Poll);
+        let planes = scene
+            .active_camera
+            .and_then(|id| scene.get::<CameraComponent>(id))
+            .map(|camera| Frustum::from_camera(&camera.camera).planes())
+            .unwrap_or([Vector4::new(0.0, 0.0, 0.0, 1.0); 6]);
+        let planes: [[f32; 4]; 6] = planes.map(Into::into);
+        ctx.queue
+            .write_buffer(&self.frustum_buffer, 0, bytemuck::bytes_of(&planes));
+
+        self.batches.truncate(scene.render_batches.len());
+        for (index, batch) in scene.render_batches.iter().enumerate() {
+            let stale = self
+                .batches
+                .get(index)
+                .is_none_or(|culled| culled.source != batch.instance_buffer);
+            if stale {
+                let culled = self.create_batch(ctx, batch);
+                if index < self.batches.len() {
+                    self.batches[index] = culled;
+                } else {
+                    self.batches.push(culled);
+                }
+            }
+
+            let culled = &mut self.batches[index];
+            let mesh = scene.mesh(batch.mesh);
+            let bounds = mesh.map(|mesh| mesh.bounds);
+            culled.instance_count = batch.instance_count;
+            ctx.queue.write_buffer(
+                &culled.uniform_buffer,
+                0,
+                bytemuck::bytes_of(&BatchUniform {
+                    bounds_min: bounds.map_or([0.0; 4], |b| b.min.to_homogeneous().into()),
+                    bounds_max: bounds.map_or([0.0; 4], |b| b.max.to_homogeneous().into()),
+                    instance_count: batch.instance_count,
+                    _padding: [0; 3],
+                }),
+            );
+            let args = DrawIndexedIndirectArgs {
+                index_count: mesh.map_or(0, |mesh| mesh.index_count),
+                instance_count: 0,
+                first_index: 0,
+                base_vertex: 0,
+                first_instance: 0,
+            };
+            ctx.queue
+                .write_buffer(&culled.indirect_buffer, 0, args.as_bytes());
+        }
+
+        let total = self.batches.iter().map(|batch| batch.instance_count).sum();
+        self.readback.prepare(ctx, self.batches.len(), total);
+    }
+
+    fn create_batch(&self, ctx: &GpuContext, batch: &RenderBatch) -> CulledBatch {
+        let uniform_buffer = ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Batch Buffer"),
+            size: std::mem::size_of::<BatchUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let visible_buffer = ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Culled Instance Buffer"),
+            size: (batch.capacity() * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let indirect_buffer = ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Indirect Buffer"),
+            size: std::mem::size_of::<DrawIndexedIndirectArgs>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.batch_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: batch.instance_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: visible_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: indirect_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("cull_batch_bind_group"),
+        });
+
+        CulledBatch {
+            uniform_buffer,
+            visible_buffer,
+            indirect_buffer,
+            bind_group,
+            source: batch.instance_buffer.clone(),
+            instance_count: 0,
+        }
+    }
+
+    /// Records the culling of every batch prepared this frame, and the copy of the visible
+    /// counts when a readback starts this frame.
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder) {
+        self.record_culling(encoder);
+
+        if let ReadbackState::Copying { .. } = self.readback.state {
+            let count_offset = std::mem::size_of::<u32>() as wgpu::BufferAddress;
+            for (index, batch) in self.batches.iter().enumerate() {
+                encoder.copy_buffer_to_buffer(
+                    &batch.indirect_buffer,
+                    count_offset,
+                    &self.readback.buffer,
+                    index as wgpu::BufferAddress * count_offset,
+                    count_offset,
+                );
+            }
+        }
+    }
+
+    fn record_culling(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Cull Pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.frustum_bind_group, &[]);
+        for batch in &self.batches {
+            if batch.instance_count == 0 {
+                continue;
+            }
+            compute_pass.set_bind_group(1, &batch.bind_group, &[]);
+            compute_pass.dispatch_workgroups(batch.instance_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+    }
+
+    /// Maps the visible counts copied by this frame's dispatch; call once the frame is
+    /// submitted.
+    pub fn request_readback(&mut self) {
+        self.readback.request();
+    }
+
+    /// Visible and culled instance counts as of the latest finished readback, which lags
+    /// the current frame by at least one frame.
+    pub fn stats(&self) -> CullStats {
+        self.readback.stats
+    }
+
+    /// The visible instances and indirect draw arguments of the `index`th render batch. The
+    /// arguments can be copied out to read the visible count back.
+    pub fn batch(&self, index: usize) -> Option<(&wgpu::Buffer, &wgpu::Buffer)> {
+        self.batches
+            .get(index)
+            .map(|batch| (&batch.visible_buffer, &batch.indirect_buffer))
+    }
+}
+
+/// Runs [`GpuCulling`] when the renderer has it enabled, before the passes reading
+/// [`ResourceId::VISIBLE_INSTANCES`] draw the culled batches.
+pub struct CullPass;
+
+impl CullPass {
+    pub const NAME: &'static str = "cull";
+}
+
+impl RenderGraphPass for CullPass {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn setup(&self, builder: &mut PassBuilder) {
+        builder.read_write(ResourceId::VISIBLE_INSTANCES);
+    }
+
+    fn execute(&mut self, pass: &mut PassContext<'_>) {
+        if let Some(culling) = pass.resources.gpu_culling() {
+            culling.dispatch(pass.encoder);
+        }
+    }
+}
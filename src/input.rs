@@ -53,6 +53,8 @@ pub struct InputService {
     mouse_buttons: HashSet<MouseButton>,
     controllers: HashMap<ControllerId, ControllerState>,
     position: PhysicalPosition<f64>,
+    mouse_delta: [f32; 2],
+    scroll_delta: f32,
 }
 
 impl InputService {
@@ -88,6 +90,36 @@ impl InputService {
         self.position = position;
     }
 
+    pub fn cursor_position(&self) -> PhysicalPosition<f64> {
+        self.position
+    }
+
+    /// Adds raw mouse motion, in pixels, to this frame's delta.
+    pub fn add_mouse_motion(&mut self, dx: f32, dy: f32) {
+        self.mouse_delta[0] += dx;
+        self.mouse_delta[1] += dy;
+    }
+
+    /// Mouse motion since the last `end_frame`; positive y is downwards.
+    pub fn mouse_delta(&self) -> [f32; 2] {
+        self.mouse_delta
+    }
+
+    /// Adds wheel movement in lines to this frame's delta; positive scrolls up.
+    pub fn add_scroll(&mut self, lines: f32) {
+        self.scroll_delta += lines;
+    }
+
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll_delta
+    }
+
+    /// Resets the per-frame mouse and scroll deltas once the frame has consumed them.
+    pub fn end_frame(&mut self) {
+        self.mouse_delta = [0.0; 2];
+        self.scroll_delta = 0.0;
+    }
+
     pub fn is_mouse_button_pressed(&self, button: MouseButton) -> bool {
         self.mouse_buttons.contains(&button)
     }
@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use winit::{
     application::ApplicationHandler,
-    event::{KeyEvent, WindowEvent},
+    event::{DeviceEvent, DeviceId, KeyEvent, MouseScrollDelta, WindowEvent},
     event_loop::{ActiveEventLoop, EventLoop},
     keyboard::{KeyCode, PhysicalKey},
     window::Window,
@@ -13,12 +13,29 @@ use wasm_bindgen::prelude::*;
 
 use crate::engine::Engine;
 
+pub mod batch;
 pub mod camera;
+pub mod camera_controller;
+pub mod component;
+pub mod culling;
 pub mod engine;
+pub mod gltf_import;
+pub mod golden;
+pub mod gpu_culling;
 pub mod input;
+pub mod light;
+pub mod obj_import;
+pub mod pbr;
+pub mod picking;
+pub mod post_process;
+pub mod primitives;
+pub mod render_graph;
 pub mod renderer;
 pub mod scene;
+pub mod serialization;
+pub mod shadow;
 pub mod texture;
+pub mod tonemap;
 pub mod window;
 
 pub struct App {
@@ -171,6 +188,13 @@ impl ApplicationHandler<Engine<'static>> for App {
             WindowEvent::MouseInput { state, button, .. } => {
                 engine.input.set_mouse_button(button, state.is_pressed());
             }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 40.0,
+                };
+                engine.input.add_scroll(lines);
+            }
             WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
@@ -189,6 +213,26 @@ impl ApplicationHandler<Engine<'static>> for App {
             _ => {}
         }
     }
+
+    fn device_event(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+        _device_id: DeviceId,
+        event: DeviceEvent,
+    ) {
+        let Some(engine) = &mut self.engine else {
+            return;
+        };
+        if let DeviceEvent::MouseMotion { delta: (dx, dy) } = event {
+            engine.input.add_mouse_motion(dx as f32, dy as f32);
+        }
+    }
+
+    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(engine) = &mut self.engine {
+            engine.update();
+        }
+    }
 }
 
 pub fn run() -> anyhow::Result<()> {
@@ -0,0 +1,203 @@
+use cgmath::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::scene::{EntityId, Scene};
+
+/// Lights past this count are ignored.
+pub const MAX_LIGHTS: usize = 16;
+
+const KIND_DIRECTIONAL: f32 = 0.0;
+const KIND_POINT: f32 = 1.0;
+const KIND_SPOT: f32 = 2.0;
+const NO_SHADOW: i32 = -1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LightKind {
+    /// Shines along the entity's forward axis (-Z) from infinitely far away.
+    Directional,
+    /// `range` of zero means the light never fades out.
+    Point { range: f32 },
+    /// Shines along the entity's forward axis (-Z). Angles are half-angles in degrees.
+    Spot {
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+/// A light placed and oriented by its entity's world transform.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LightComponent {
+    pub kind: LightKind,
+    /// Linear RGB.
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Renders a shadow map for this light, see [`crate::shadow::ShadowMaps`].
+    #[serde(default)]
+    pub cast_shadows: bool,
+}
+
+impl LightComponent {
+    pub fn directional(color: [f32; 3], intensity: f32) -> Self {
+        Self {
+            kind: LightKind::Directional,
+            color,
+            intensity,
+            cast_shadows: false,
+        }
+    }
+
+    pub fn point(color: [f32; 3], intensity: f32, range: f32) -> Self {
+        Self {
+            kind: LightKind::Point { range },
+            color,
+            intensity,
+            cast_shadows: false,
+        }
+    }
+
+    pub fn spot(
+        color: [f32; 3],
+        intensity: f32,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot {
+                range,
+                inner_angle,
+                outer_angle,
+            },
+            color,
+            intensity,
+            cast_shadows: false,
+        }
+    }
+
+    pub fn with_shadows(mut self, cast_shadows: bool) -> Self {
+        self.cast_shadows = cast_shadows;
+        self
+    }
+
+    /// The world-space direction the light shines in, along the entity's -Z axis.
+    pub fn direction(world: cgmath::Matrix4<f32>) -> cgmath::Vector3<f32> {
+        let direction = (world * -cgmath::Vector4::unit_z()).truncate();
+        if direction.magnitude2() > 0.0 {
+            direction.normalize()
+        } else {
+            -cgmath::Vector3::unit_z()
+        }
+    }
+
+    pub fn to_raw(&self, world: cgmath::Matrix4<f32>) -> LightRaw {
+        let position = world.w.truncate();
+        let direction = Self::direction(world);
+
+        let (kind, range, cone) = match self.kind {
+            LightKind::Directional => (KIND_DIRECTIONAL, 0.0, [0.0, 0.0]),
+            LightKind::Point { range } => (KIND_POINT, range, [0.0, 0.0]),
+            LightKind::Spot {
+                range,
+                inner_angle,
+                outer_angle,
+            } => {
+                let outer = cgmath::Deg(outer_angle).cos();
+                // Keep the edges apart so the shader's smoothstep stays defined.
+                let inner = cgmath::Deg(inner_angle.min(outer_angle))
+                    .cos()
+                    .max(outer + 1e-4);
+                (KIND_SPOT, range, [inner, outer])
+            }
+        };
+
+        let [r, g, b] = self.color;
+        LightRaw {
+            position: [position.x, position.y, position.z, kind],
+            direction: [direction.x, direction.y, direction.z, range],
+            color: [r, g, b, self.intensity],
+            cone,
+            shadow_tile: NO_SHADOW,
+            shadow_count: 0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightRaw {
+    /// `w` holds the light kind.
+    pub position: [f32; 4],
+    /// `w` holds the range.
+    pub direction: [f32; 4],
+    /// `w` holds the intensity.
+    pub color: [f32; 4],
+    /// Cosines of the inner and outer spot angles.
+    pub cone: [f32; 2],
+    /// The light's first tile in the shadow atlas, negative without shadows.
+    pub shadow_tile: i32,
+    /// One per cascade or cube face, see [`crate::shadow::ShadowMaps::prepare`].
+    pub shadow_count: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightsUniform {
+    pub ambient: [f32; 4],
+    pub count: [u32; 4],
+    pub lights: [LightRaw; MAX_LIGHTS],
+}
+
+impl Default for LightsUniform {
+    fn default() -> Self {
+        bytemuck::Zeroable::zeroed()
+    }
+}
+
+impl Scene {
+    pub fn add_light(&mut self, entity: EntityId, component: LightComponent) {
+        self.insert(entity, component);
+    }
+
+    pub fn remove_light(&mut self, entity: EntityId) -> Option<LightComponent> {
+        self.remove(entity)
+    }
+
+    /// The first `MAX_LIGHTS` lights in `EntityId` order with their world matrices, matching
+    /// the entries of [`Scene::lights_uniform`].
+    pub fn active_lights(
+        &self,
+    ) -> impl Iterator<Item = (EntityId, &LightComponent, cgmath::Matrix4<f32>)> + '_ {
+        let mut lights: Vec<_> = self.query::<(LightComponent,)>().collect();
+        lights.sort_unstable_by_key(|(entity, _)| *entity);
+        lights
+            .into_iter()
+            .take(MAX_LIGHTS)
+            .map(move |(entity, (light,))| {
+                let world = self
+                    .world_transform(entity)
+                    .unwrap_or_else(cgmath::Matrix4::identity);
+                (entity, light, world)
+            })
+    }
+
+    /// Packs the scene's lights in `EntityId` order for upload.
+    pub fn lights_uniform(&self) -> LightsUniform {
+        let [r, g, b] = self.ambient_light;
+        let mut uniform = LightsUniform {
+            ambient: [r, g, b, 1.0],
+            ..Default::default()
+        };
+
+        let mut count = 0;
+        for (index, (_, light, world)) in self.active_lights().enumerate() {
+            uniform.lights[index] = light.to_raw(world);
+            count = index + 1;
+        }
+        if self.iter::<LightComponent>().count() > MAX_LIGHTS {
+            log::warn!("scene has more than {MAX_LIGHTS} lights, the rest are ignored");
+        }
+        uniform.count[0] = count as u32;
+        uniform
+    }
+}
@@ -0,0 +1,245 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use log::warn;
+
+use crate::{
+    pbr::MaterialLayout,
+    primitives::MeshData,
+    renderer::GpuContext,
+    scene::{
+        EntityId, Material, MaterialHandle, Mesh, MeshHandle, MeshRendererComponent, Scene,
+        Shading, TransformComponent,
+    },
+};
+
+/// Asset path fragments used to address the contents of an OBJ file, e.g.
+/// `models/rock.obj#model0` or `models/rock.obj#material1`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ObjAsset {
+    Model(usize),
+    /// `None` is the material used by models without a `usemtl`.
+    Material(Option<usize>),
+}
+
+impl ObjAsset {
+    /// Splits `path` into the OBJ file and the asset inside it, if it points into one.
+    pub fn parse(path: &str) -> Option<(&str, Self)> {
+        let (file, fragment) = path.rsplit_once('#')?;
+        let extension = Path::new(file).extension()?.to_str()?;
+        if !extension.eq_ignore_ascii_case("obj") {
+            return None;
+        }
+
+        let asset = if fragment == "material-default" {
+            Self::Material(None)
+        } else if let Some(material) = fragment.strip_prefix("material") {
+            Self::Material(Some(material.parse().ok()?))
+        } else {
+            Self::Model(fragment.strip_prefix("model")?.parse().ok()?)
+        };
+        Some((file, asset))
+    }
+
+    pub fn path(&self, file: &str) -> String {
+        match self {
+            Self::Model(model) => format!("{file}#model{model}"),
+            Self::Material(Some(material)) => format!("{file}#material{material}"),
+            Self::Material(None) => format!("{file}#material-default"),
+        }
+    }
+}
+
+/// A parsed OBJ file with its MTL materials. Faces are triangulated and each unique
+/// position/texture coordinate pair becomes one vertex.
+pub struct ObjDocument {
+    pub source: String,
+    pub models: Vec<tobj::Model>,
+    pub materials: Vec<tobj::Material>,
+    base_dir: Option<PathBuf>,
+}
+
+impl ObjDocument {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let (models, materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)
+            .with_context(|| format!("failed to load OBJ file {}", path.display()))?;
+        let materials = materials.unwrap_or_else(|err| {
+            warn!("failed to load materials for {}: {err}", path.display());
+            Vec::new()
+        });
+
+        Ok(Self {
+            source: path.to_string_lossy().into_owned(),
+            models,
+            materials,
+            base_dir: path.parent().map(Path::to_path_buf),
+        })
+    }
+
+    /// Keeps the asset paths of loaded meshes and materials relative to a scene file
+    /// instead of the path the OBJ was opened with.
+    pub fn with_source(mut self, source: impl Into<String>) -> Self {
+        self.source = source.into();
+        self
+    }
+
+    pub fn load_model(&self, ctx: &GpuContext, model: usize) -> Result<Mesh> {
+        let label = ObjAsset::Model(model).path(&self.source);
+        let mesh = &self
+            .models
+            .get(model)
+            .with_context(|| format!("`{label}` does not exist"))?
+            .mesh;
+
+        let vertex_count = mesh.positions.len() / 3;
+        let tex_coords = if mesh.texcoords.len() / 2 == vertex_count {
+            // OBJ texture coordinates start at the bottom left.
+            mesh.texcoords
+                .chunks_exact(2)
+                .map(|uv| [uv[0], 1.0 - uv[1]])
+                .collect()
+        } else {
+            vec![[0.0, 0.0]; vertex_count]
+        };
+        let normals = (mesh.normals.len() / 3 == vertex_count).then(|| {
+            mesh.normals
+                .chunks_exact(3)
+                .map(|n| [n[0], n[1], n[2]])
+                .collect()
+        });
+        ensure!(
+            mesh.indices
+                .iter()
+                .all(|&index| (index as usize) < vertex_count),
+            "`{label}` has out of range indices"
+        );
+
+        let data = MeshData {
+            positions: mesh
+                .positions
+                .chunks_exact(3)
+                .map(|p| [p[0], p[1], p[2]])
+                .collect(),
+            tex_coords,
+            normals,
+            tangents: None,
+            indices: mesh.indices.clone(),
+        };
+        Ok(data.to_mesh(ctx, &label))
+    }
+
+    /// Uses `map_Kd` when present, otherwise a solid material of the diffuse color `Kd`.
+    /// The material is lit.
+    pub fn load_material(
+        &self,
+        ctx: &GpuContext,
+        material_layout: &MaterialLayout,
+        material: Option<usize>,
+    ) -> Result<Material> {
+        Ok(self
+            .diffuse_material(ctx, material_layout, material)?
+            .with_shading(Shading::Lit))
+    }
+
+    fn diffuse_material(
+        &self,
+        ctx: &GpuContext,
+        material_layout: &MaterialLayout,
+        material: Option<usize>,
+    ) -> Result<Material> {
+        let label = ObjAsset::Material(material).path(&self.source);
+        let material = match material {
+            Some(index) => self
+                .materials
+                .get(index)
+                .with_context(|| format!("`{label}` does not exist"))?,
+            None => return Material::from_color(ctx, material_layout, [1.0; 4], &label),
+        };
+
+        if let Some(texture) = &material.diffuse_texture {
+            let path = match &self.base_dir {
+                Some(base_dir) => base_dir.join(texture),
+                None => PathBuf::from(texture),
+            };
+            let bytes = std::fs::read(&path)
+                .with_context(|| format!("failed to read `map_Kd` texture {}", path.display()))?;
+            return Material::from_texture_bytes(ctx, material_layout, &bytes, &label);
+        }
+
+        let [r, g, b] = material.diffuse.unwrap_or([1.0; 3]);
+        let alpha = material.dissolve.unwrap_or(1.0);
+        Material::from_color(ctx, material_layout, [r, g, b, alpha], &label)
+    }
+
+    /// Spawns one entity per OBJ object/group under a new root entity named after the file.
+    /// Meshes and materials already registered under the same asset path are reused.
+    pub fn spawn(
+        &self,
+        ctx: &GpuContext,
+        material_layout: &MaterialLayout,
+        scene: &mut Scene,
+        parent: Option<EntityId>,
+    ) -> Result<EntityId> {
+        let name = Path::new(&self.source)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned());
+        let root = scene.spawn(name, parent);
+        scene.set_transform(root, TransformComponent::identity());
+
+        for (index, model) in self.models.iter().enumerate() {
+            if model.mesh.indices.is_empty() {
+                continue;
+            }
+
+            let name = (!model.name.is_empty()).then(|| model.name.clone());
+            let entity = scene.spawn(name, Some(root));
+            scene.set_transform(entity, TransformComponent::identity());
+
+            let mesh = self.mesh_handle(ctx, scene, index)?;
+            let material =
+                self.material_handle(ctx, material_layout, scene, model.mesh.material_id)?;
+            scene.add_mesh_renderer(entity, MeshRendererComponent { mesh, material });
+        }
+
+        Ok(root)
+    }
+
+    fn mesh_handle(&self, ctx: &GpuContext, scene: &mut Scene, model: usize) -> Result<MeshHandle> {
+        let path = ObjAsset::Model(model).path(&self.source);
+        if let Some(handle) = scene.find_mesh(&path) {
+            return Ok(handle);
+        }
+        let mesh = self.load_model(ctx, model)?;
+        Ok(scene.add_mesh_asset(path, mesh))
+    }
+
+    fn material_handle(
+        &self,
+        ctx: &GpuContext,
+        material_layout: &MaterialLayout,
+        scene: &mut Scene,
+        material: Option<usize>,
+    ) -> Result<MaterialHandle> {
+        let path = ObjAsset::Material(material).path(&self.source);
+        if let Some(handle) = scene.find_material(&path) {
+            return Ok(handle);
+        }
+        let material = self.load_material(ctx, material_layout, material)?;
+        Ok(scene.add_material_asset(path, material))
+    }
+}
+
+impl Scene {
+    /// Imports an `.obj` file and its materials, returning the root entity of the spawned
+    /// subtree. [`Scene::save`] stores `path` relative to the scene file.
+    pub fn import_obj(
+        &mut self,
+        ctx: &GpuContext,
+        material_layout: &MaterialLayout,
+        path: impl AsRef<Path>,
+        parent: Option<EntityId>,
+    ) -> Result<EntityId> {
+        ObjDocument::open(path)?.spawn(ctx, material_layout, self, parent)
+    }
+}
@@ -0,0 +1,241 @@
+use anyhow::Result;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    renderer::GpuContext,
+    scene::{Material, Shading},
+    texture::{SamplerDescriptor, Texture},
+};
+
+/// Inputs of the glTF metallic-roughness model. Each texture is multiplied by its factor;
+/// missing textures fall back to a 1x1 texture that leaves the factor unchanged.
+pub struct PbrMaterial {
+    /// Linear RGBA.
+    pub base_color_factor: [f32; 4],
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// Scales the X and Y of the tangent-space normal map.
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    /// Linear RGB.
+    pub emissive_factor: [f32; 3],
+    /// sRGB color with alpha.
+    pub base_color_texture: Option<Texture>,
+    /// Linear; roughness in green, metalness in blue.
+    pub metallic_roughness_texture: Option<Texture>,
+    /// Linear tangent-space normals.
+    pub normal_texture: Option<Texture>,
+    /// Linear; occlusion in red.
+    pub occlusion_texture: Option<Texture>,
+    /// sRGB color.
+    pub emissive_texture: Option<Texture>,
+    /// Shared by every texture of the material.
+    pub sampler: SamplerDescriptor,
+}
+
+impl Default for PbrMaterial {
+    /// The glTF defaults: a white, fully metallic and fully rough surface.
+    fn default() -> Self {
+        Self {
+            base_color_factor: [1.0; 4],
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            emissive_factor: [0.0; 3],
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+            sampler: SamplerDescriptor::default(),
+        }
+    }
+}
+
+impl PbrMaterial {
+    /// A rough, white dielectric: the factors [`Material::from_texture`] uses.
+    pub fn plain() -> Self {
+        Self {
+            metallic_factor: 0.0,
+            ..Self::default()
+        }
+    }
+
+    pub fn uniform(&self) -> MaterialUniform {
+        let [r, g, b] = self.emissive_factor;
+        MaterialUniform {
+            base_color_factor: self.base_color_factor,
+            emissive_factor: [r, g, b, 0.0],
+            metallic_roughness_normal_occlusion: [
+                self.metallic_factor,
+                self.roughness_factor,
+                self.normal_scale,
+                self.occlusion_strength,
+            ],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    pub base_color_factor: [f32; 4],
+    /// `w` is unused.
+    pub emissive_factor: [f32; 4],
+    pub metallic_roughness_normal_occlusion: [f32; 4],
+}
+
+/// The material bind group layout, with the fallback textures, default sampler and plain
+/// factors that materials share instead of allocating their own. Owned by
+/// [`crate::renderer::RenderResources`].
+pub struct MaterialLayout {
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    /// Stands in for missing color, metallic-roughness, occlusion and emissive textures,
+    /// leaving their factors unchanged.
+    white: Texture,
+    /// Stands in for a missing normal map.
+    flat_normal: Texture,
+    default_sampler: wgpu::Sampler,
+    /// The factors of [`Material::from_texture`] materials: a rough, white dielectric.
+    plain_uniform: MaterialUniform,
+    plain_uniform_buffer: wgpu::Buffer,
+}
+
+impl MaterialLayout {
+    pub fn new(ctx: &GpuContext, bind_group_layout: wgpu::BindGroupLayout) -> Self {
+        let default_sampler = SamplerDescriptor::default().create(&ctx.device, Some("Default"));
+        let solid = |rgba: [u8; 4], label| {
+            let texture = ctx.device.create_texture_with_data(
+                &ctx.queue,
+                &wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: 1,
+                        height: 1,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::Rgba8Unorm,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                },
+                wgpu::util::TextureDataOrder::LayerMajor,
+                &rgba,
+            );
+            Texture {
+                view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                texture,
+                sampler: default_sampler.clone(),
+            }
+        };
+        let white = solid([255; 4], "Default White");
+        let flat_normal = solid([128, 128, 255, 255], "Default Normal");
+
+        let plain_uniform = PbrMaterial::plain().uniform();
+        let plain_uniform_buffer =
+            ctx.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Plain Material Buffer"),
+                    contents: bytemuck::bytes_of(&plain_uniform),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+
+        Self {
+            bind_group_layout,
+            white,
+            flat_normal,
+            default_sampler,
+            plain_uniform,
+            plain_uniform_buffer,
+        }
+    }
+}
+
+impl Material {
+    /// Builds a material drawn with `Shading::Pbr`. Every texture is sampled with
+    /// `material.sampler`. Missing textures, the default sampler and plain factors come
+    /// from `material_layout` rather than being allocated per material.
+    pub fn pbr(
+        ctx: &GpuContext,
+        material_layout: &MaterialLayout,
+        material: PbrMaterial,
+        label: &str,
+    ) -> Result<Self> {
+        fn view<'a>(
+            texture: &'a Option<Texture>,
+            fallback: &'a Texture,
+        ) -> wgpu::BindingResource<'a> {
+            wgpu::BindingResource::TextureView(&texture.as_ref().unwrap_or(fallback).view)
+        }
+        let white = &material_layout.white;
+
+        let own_sampler;
+        let sampler = if material.sampler == SamplerDescriptor::default() {
+            &material_layout.default_sampler
+        } else {
+            own_sampler = material.sampler.create(&ctx.device, Some(label));
+            &own_sampler
+        };
+
+        let uniform = material.uniform();
+        let own_uniform_buffer;
+        let uniform_buffer =
+            if bytemuck::bytes_of(&uniform) == bytemuck::bytes_of(&material_layout.plain_uniform) {
+                &material_layout.plain_uniform_buffer
+            } else {
+                own_uniform_buffer =
+                    ctx.device
+                        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                            label: Some(&format!("{label} Material Buffer")),
+                            contents: bytemuck::bytes_of(&uniform),
+                            usage: wgpu::BufferUsages::UNIFORM,
+                        });
+                &own_uniform_buffer
+            };
+
+        let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &material_layout.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: view(&material.base_color_texture, white),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: view(&material.metallic_roughness_texture, white),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: view(&material.normal_texture, &material_layout.flat_normal),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: view(&material.occlusion_texture, white),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: view(&material.emissive_texture, white),
+                },
+            ],
+            label: Some(label),
+        });
+
+        Ok(Self {
+            texture: material.base_color_texture.unwrap_or_else(|| white.clone()),
+            bind_group,
+            shading: Shading::Pbr,
+            sampler: material.sampler,
+        })
+    }
+}
@@ -0,0 +1,138 @@
+use cgmath::{InnerSpace, Point3, SquareMatrix, Transform, Vector3};
+
+use crate::{
+    culling::Aabb,
+    scene::{EntityId, MeshRendererComponent, Scene, TransformComponent},
+};
+
+/// A half-line from `origin` along `direction`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    /// A ray along the normalized `direction`, so distances along it are in world units.
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn at(&self, distance: f32) -> Point3<f32> {
+        self.origin + self.direction * distance
+    }
+
+    /// The distance at which the ray enters `aabb`, or 0 when it starts inside.
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let mut near = 0.0_f32;
+        let mut far = f32::INFINITY;
+        for axis in 0..3 {
+            let inverse = 1.0 / self.direction[axis];
+            let a = (aabb.min[axis] - self.origin[axis]) * inverse;
+            let b = (aabb.max[axis] - self.origin[axis]) * inverse;
+            // NaN from a zero direction inside the slab leaves the range as it is.
+            near = near.max(a.min(b));
+            far = far.min(a.max(b));
+        }
+        (near <= far).then_some(near)
+    }
+
+    /// The distance to the triangle `a`, `b`, `c` from either side, using the
+    /// Möller-Trumbore test.
+    pub fn intersect_triangle(
+        &self,
+        a: Point3<f32>,
+        b: Point3<f32>,
+        c: Point3<f32>,
+    ) -> Option<f32> {
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let p = self.direction.cross(edge2);
+        let determinant = edge1.dot(p);
+        let scale = self.direction.magnitude() * edge1.magnitude() * edge2.magnitude();
+        if determinant.abs() <= f32::EPSILON * scale {
+            return None;
+        }
+
+        let inverse = 1.0 / determinant;
+        let to_origin = self.origin - a;
+        let u = to_origin.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = to_origin.cross(edge1);
+        let v = self.direction.dot(q) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let distance = edge2.dot(q) * inverse;
+        (distance >= 0.0).then_some(distance)
+    }
+}
+
+/// How closely [`Scene::raycast`] tests entities.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum RaycastMode {
+    /// Against each mesh's bounding box, in the entity's local space.
+    #[default]
+    Bounds,
+    /// Against the mesh triangles of entities whose bounds are hit. Meshes without
+    /// [`crate::scene::CollisionData`] are tested against their bounds.
+    Triangles,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RaycastHit {
+    pub entity: EntityId,
+    /// From the ray's origin, in world units for rays made with [`Ray::new`].
+    pub distance: f32,
+    pub point: Point3<f32>,
+}
+
+impl Scene {
+    /// Every entity with a mesh renderer that `ray` hits, nearest first.
+    pub fn raycast(&self, ray: &Ray, mode: RaycastMode) -> Vec<RaycastHit> {
+        let mut hits: Vec<RaycastHit> = self
+            .query::<(MeshRendererComponent, TransformComponent)>()
+            .filter_map(|(entity, (renderer, _))| {
+                let mesh = self.mesh(renderer.mesh)?;
+                let world = self.world_transform(entity)?;
+                // Testing in local space keeps rotated bounds tight. The direction is left
+                // unnormalized so distances stay in world units.
+                let inverse = world.invert()?;
+                let local = Ray {
+                    origin: inverse.transform_point(ray.origin),
+                    direction: inverse.transform_vector(ray.direction),
+                };
+
+                let mut distance = local.intersect_aabb(&mesh.bounds)?;
+                if let (RaycastMode::Triangles, Some(collision)) = (mode, &mesh.collision) {
+                    distance = collision
+                        .triangles()
+                        .filter_map(|[a, b, c]| local.intersect_triangle(a, b, c))
+                        .reduce(f32::min)?;
+                }
+
+                Some(RaycastHit {
+                    entity,
+                    distance,
+                    point: ray.at(distance),
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| {
+            a.distance
+                .total_cmp(&b.distance)
+                .then(a.entity.cmp(&b.entity))
+        });
+        hits
+    }
+
+    /// The nearest entity under `ray`, see [`Scene::raycast`].
+    pub fn pick(&self, ray: &Ray, mode: RaycastMode) -> Option<RaycastHit> {
+        self.raycast(ray, mode).into_iter().next()
+    }
+}
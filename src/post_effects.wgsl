@@ -0,0 +1,92 @@
+// The built-in post-processing effects, compiled after `post_prelude.wgsl`.
+
+@group(1) @binding(0)
+var color_lut: texture_3d<f32>;
+
+fn srgb_decode(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+// Copies the chain's result into the output.
+@fragment
+fn fs_blit(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureLoad(source_texture, vec2<i32>(in.position.xy), 0);
+    if effect.decode_srgb != 0u {
+        return vec4<f32>(srgb_decode(color.rgb), color.a);
+    }
+    return color;
+}
+
+// Looks the color up in a 3D LUT and blends by `params.x`.
+@fragment
+fn fs_color_grading(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = sample_source(in.uv);
+    let size = f32(textureDimensions(color_lut).x);
+    // Land on texel centers so the LUT's end points map exactly.
+    let coords = saturate(color.rgb) * ((size - 1.0) / size) + 0.5 / size;
+    let graded = textureSampleLevel(color_lut, source_sampler, coords, 0.0).rgb;
+    return vec4<f32>(mix(color.rgb, graded, effect.params.x), color.a);
+}
+
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.299, 0.587, 0.114));
+}
+
+// FXAA in the style of Timothy Lottes' console variant: blurs along the edge direction
+// estimated from the luma of the diagonal neighbours. `params` holds the maximum span in
+// pixels and the reduction multiplier and minimum.
+@fragment
+fn fs_fxaa(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let texel = effect.texel_size;
+    let center = sample_source(in.uv);
+    let luma_nw = luma(sample_source(in.uv + vec2<f32>(-1.0, -1.0) * texel).rgb);
+    let luma_ne = luma(sample_source(in.uv + vec2<f32>(1.0, -1.0) * texel).rgb);
+    let luma_sw = luma(sample_source(in.uv + vec2<f32>(-1.0, 1.0) * texel).rgb);
+    let luma_se = luma(sample_source(in.uv + vec2<f32>(1.0, 1.0) * texel).rgb);
+    let luma_m = luma(center.rgb);
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    var direction = vec2<f32>(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let reduce = max(
+        (luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * effect.params.y,
+        effect.params.z,
+    );
+    let scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    let span = effect.params.x;
+    direction = clamp(direction * scale, vec2<f32>(-span), vec2<f32>(span)) * texel;
+
+    let near = 0.5 * (sample_source(in.uv + direction * (1.0 / 3.0 - 0.5)).rgb
+        + sample_source(in.uv + direction * (2.0 / 3.0 - 0.5)).rgb);
+    let far = near * 0.5 + 0.25 * (sample_source(in.uv - direction * 0.5).rgb
+        + sample_source(in.uv + direction * 0.5).rgb);
+    let luma_far = luma(far);
+    let color = select(far, near, luma_far < luma_min || luma_far > luma_max);
+    return vec4<f32>(color, center.a);
+}
+
+// Splits the red and blue channels towards the edges by `params.x` of the screen size.
+@fragment
+fn fs_chromatic_aberration(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let offset = (in.uv - 0.5) * effect.params.x;
+    let red = sample_source(in.uv + offset).r;
+    let center = sample_source(in.uv);
+    let blue = sample_source(in.uv - offset).b;
+    return vec4<f32>(red, center.g, blue, center.a);
+}
+
+// Darkens towards the corners: `params` holds the intensity, the radius where darkening
+// starts and the width of the falloff.
+@fragment
+fn fs_vignette(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = sample_source(in.uv);
+    // Corners are at distance 1.
+    let distance = length(in.uv - 0.5) * 1.41421356;
+    let falloff = smoothstep(effect.params.y, effect.params.y + effect.params.z, distance);
+    return vec4<f32>(color.rgb * (1.0 - falloff * effect.params.x), color.a);
+}
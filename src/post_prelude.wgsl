@@ -0,0 +1,39 @@
+// Prepended to every post-processing effect, built-in or registered. Effects sample
+// `source_texture`, the display-encoded output of the previous effect, and return
+// display-encoded color from their fragment entry point.
+
+struct PostEffect {
+    // The effect's parameters, e.g. `CustomEffect::params`.
+    params: vec4<f32>,
+    // One over the source size in pixels.
+    texel_size: vec2<f32>,
+    // Set for the final copy into an sRGB output, which encodes on write.
+    decode_srgb: u32,
+    _padding: u32,
+}
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> effect: PostEffect;
+
+struct FullscreenOutput {
+    @builtin(position) position: vec4<f32>,
+    // Texture coordinates, running top to bottom.
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> FullscreenOutput {
+    // One triangle covering the screen.
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: FullscreenOutput;
+    out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+fn sample_source(uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(source_texture, source_sampler, uv, 0.0);
+}
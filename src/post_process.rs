@@ -0,0 +1,1096 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+};
+
+use anyhow::{ensure, Result};
+use log::warn;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    render_graph::{
+        PassBuilder, PassContext, RenderGraphPass, ResourceId, TextureSize, TransientTexture,
+    },
+    renderer::{GpuContext, RenderResources},
+    scene::{EntityId, Scene},
+};
+
+/// Source every post effect is compiled after, see [`PostProcessPass::register_effect`].
+pub const POST_EFFECT_PRELUDE: &str = include_str!("post_prelude.wgsl");
+
+/// Bloom mips, each half the size of the previous one.
+pub const MAX_BLOOM_MIPS: usize = 6;
+
+const BLOOM_MIPS: [ResourceId; MAX_BLOOM_MIPS] = [
+    ResourceId("bloom_mip_0"),
+    ResourceId("bloom_mip_1"),
+    ResourceId("bloom_mip_2"),
+    ResourceId("bloom_mip_3"),
+    ResourceId("bloom_mip_4"),
+    ResourceId("bloom_mip_5"),
+];
+const POST_PING: ResourceId = ResourceId("post_ping");
+const POST_PONG: ResourceId = ResourceId("post_pong");
+
+/// Glow around HDR colors brighter than `threshold`, added before tonemapping.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bloom {
+    pub enabled: bool,
+    pub threshold: f32,
+    /// Width of the soft transition below the threshold.
+    pub knee: f32,
+    pub intensity: f32,
+    /// Spread of each upsample in texels.
+    pub radius: f32,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 0.15,
+            radius: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fxaa {
+    pub enabled: bool,
+    /// Longest blur along an edge in pixels.
+    pub span_max: f32,
+    pub reduce_mul: f32,
+    pub reduce_min: f32,
+}
+
+impl Default for Fxaa {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            span_max: 8.0,
+            reduce_mul: 1.0 / 8.0,
+            reduce_min: 1.0 / 128.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vignette {
+    pub enabled: bool,
+    /// How dark the corners get, from 0 to 1.
+    pub intensity: f32,
+    /// Distance from the center where darkening starts; the corners are at 1.
+    pub radius: f32,
+    pub smoothness: f32,
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            intensity: 0.4,
+            radius: 0.5,
+            smoothness: 0.5,
+        }
+    }
+}
+
+/// Remaps display colors through a 3D lookup table.
+#[derive(Clone, Debug)]
+pub struct ColorGrading {
+    pub enabled: bool,
+    pub lut: Option<Arc<ColorLut>>,
+    /// Blend between the original (0) and graded (1) colors.
+    pub strength: f32,
+}
+
+impl Default for ColorGrading {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            lut: None,
+            strength: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChromaticAberration {
+    pub enabled: bool,
+    /// Channel offset at the screen edges, as a fraction of the screen size.
+    pub intensity: f32,
+}
+
+impl Default for ChromaticAberration {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            intensity: 0.01,
+        }
+    }
+}
+
+/// An effect registered with [`PostProcessPass::register_effect`], enabled on a camera.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CustomEffect {
+    pub name: String,
+    pub enabled: bool,
+    /// Available to the shader as `effect.params`.
+    pub params: [f32; 4],
+}
+
+impl CustomEffect {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            enabled: true,
+            params: [0.0; 4],
+        }
+    }
+
+    pub fn with_params(mut self, params: [f32; 4]) -> Self {
+        self.params = params;
+        self
+    }
+}
+
+/// The post-processing stack of a camera entity; cameras without one get no effects.
+///
+/// Bloom runs in HDR before tonemapping. The rest run on the tonemapped image in the
+/// order color grading, FXAA, chromatic aberration, vignette, then `custom` in order.
+#[derive(Clone, Debug, Default)]
+pub struct PostProcessSettings {
+    pub bloom: Bloom,
+    pub color_grading: ColorGrading,
+    pub fxaa: Fxaa,
+    pub chromatic_aberration: ChromaticAberration,
+    pub vignette: Vignette,
+    pub custom: Vec<CustomEffect>,
+}
+
+impl PostProcessSettings {
+    /// Whether any effect runs after tonemapping. Without one, tonemapping writes the
+    /// output directly.
+    pub fn has_ldr_effects(&self) -> bool {
+        (self.color_grading.enabled && self.color_grading.lut.is_some())
+            || self.fxaa.enabled
+            || self.chromatic_aberration.enabled
+            || self.vignette.enabled
+            || self.custom.iter().any(|effect| effect.enabled)
+    }
+}
+
+impl Scene {
+    pub fn set_post_process(&mut self, camera: EntityId, settings: PostProcessSettings) {
+        self.insert(camera, settings);
+    }
+
+    pub fn remove_post_process(&mut self, camera: EntityId) -> Option<PostProcessSettings> {
+        self.remove(camera)
+    }
+
+    /// The active camera's post-processing stack.
+    pub fn active_post_process(&self) -> Option<&PostProcessSettings> {
+        self.get::<PostProcessSettings>(self.active_camera?)
+    }
+}
+
+/// A 3D color lookup table for [`ColorGrading`].
+#[derive(Debug)]
+pub struct ColorLut {
+    pub size: u32,
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+impl ColorLut {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    /// A table that leaves colors unchanged.
+    pub fn identity(ctx: &GpuContext, size: u32) -> Self {
+        let size = size.max(2);
+        let step = |i: u32| (i * 255 / (size - 1)) as u8;
+        let mut texels = Vec::with_capacity((size * size * size * 4) as usize);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    texels.extend_from_slice(&[step(r), step(g), step(b), 255]);
+                }
+            }
+        }
+        Self::from_texels(ctx, size, &texels, "identity_color_lut")
+    }
+
+    /// Reads the common strip layout: `size` square slices of increasing blue placed left to
+    /// right, with red along x and green along y inside each slice.
+    pub fn from_strip(ctx: &GpuContext, strip: &image::RgbaImage, label: &str) -> Result<Self> {
+        let size = strip.height();
+        ensure!(
+            size >= 2 && strip.width() == size * size,
+            "color LUT strip `{}` is {}x{}, expected {}x{}",
+            label,
+            strip.width(),
+            size,
+            size * size,
+            size
+        );
+
+        let mut texels = Vec::with_capacity((size * size * size * 4) as usize);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    texels.extend_from_slice(&strip.get_pixel(b * size + r, g).0);
+                }
+            }
+        }
+        Ok(Self::from_texels(ctx, size, &texels, label))
+    }
+
+    fn from_texels(ctx: &GpuContext, size: u32, texels: &[u8], label: &str) -> Self {
+        let texture = ctx.device.create_texture_with_data(
+            &ctx.queue,
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: size,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D3,
+                format: Self::FORMAT,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            texels,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            size,
+            texture,
+            view,
+        }
+    }
+}
+
+/// Per-draw uniforms in one buffer, each bound at its own aligned offset.
+struct UniformSlots {
+    label: &'static str,
+    buffer: wgpu::Buffer,
+    size: wgpu::BufferAddress,
+    stride: wgpu::BufferAddress,
+    capacity: usize,
+}
+
+impl UniformSlots {
+    fn new(ctx: &GpuContext, label: &'static str, size: usize, capacity: usize) -> Self {
+        let size = size as wgpu::BufferAddress;
+        let alignment = ctx.device.limits().min_uniform_buffer_offset_alignment as u64;
+        let stride = size.div_ceil(alignment) * alignment;
+        Self {
+            label,
+            buffer: Self::create_buffer(ctx, label, stride, capacity),
+            size,
+            stride,
+            capacity,
+        }
+    }
+
+    fn create_buffer(
+        ctx: &GpuContext,
+        label: &str,
+        stride: wgpu::BufferAddress,
+        capacity: usize,
+    ) -> wgpu::Buffer {
+        ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: stride * capacity as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Uploads one value per slot, growing the buffer when needed.
+    fn write<T: bytemuck::Pod>(&mut self, ctx: &GpuContext, values: &[T]) {
+        if values.len() > self.capacity {
+            self.capacity = values.len().next_power_of_two();
+            self.buffer = Self::create_buffer(ctx, self.label, self.stride, self.capacity);
+        }
+        let mut bytes = vec![0; self.stride as usize * values.len()];
+        for (slot, value) in bytes.chunks_exact_mut(self.stride as usize).zip(values) {
+            slot[..self.size as usize].copy_from_slice(bytemuck::bytes_of(value));
+        }
+        ctx.queue.write_buffer(&self.buffer, 0, &bytes);
+    }
+
+    fn binding(&self, slot: usize) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: &self.buffer,
+            offset: slot as wgpu::BufferAddress * self.stride,
+            size: wgpu::BufferSize::new(self.size),
+        })
+    }
+}
+
+/// Source texture, sampler and uniform, the layout shared by bloom and the post effects.
+fn effect_bind_group_layout(ctx: &GpuContext, label: &str) -> wgpu::BindGroupLayout {
+    ctx.device
+        .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some(label),
+        })
+}
+
+fn effect_bind_group(
+    ctx: &GpuContext,
+    layout: &wgpu::BindGroupLayout,
+    source: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+    uniform: wgpu::BindingResource<'_>,
+) -> wgpu::BindGroup {
+    ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(source),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: uniform,
+            },
+        ],
+        label: Some("post_effect_bind_group"),
+    })
+}
+
+fn linear_sampler(ctx: &GpuContext) -> wgpu::Sampler {
+    ctx.device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("post_sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+fn fullscreen_pipeline(
+    ctx: &GpuContext,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    entry_point: &str,
+    format: wgpu::TextureFormat,
+    blend: Option<wgpu::BlendState>,
+) -> wgpu::RenderPipeline {
+    ctx.device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            multiview_mask: None,
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module,
+                entry_point: Some("vs_fullscreen"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module,
+                entry_point: Some(entry_point),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            cache: None,
+        })
+}
+
+fn draw_fullscreen(
+    encoder: &mut wgpu::CommandEncoder,
+    label: &str,
+    target: &wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+    pipeline: &wgpu::RenderPipeline,
+    bind_groups: &[&wgpu::BindGroup],
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        multiview_mask: None,
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+            depth_slice: None,
+        })],
+        depth_stencil_attachment: None,
+        occlusion_query_set: None,
+        timestamp_writes: None,
+    });
+    render_pass.set_pipeline(pipeline);
+    for (index, bind_group) in bind_groups.iter().enumerate() {
+        render_pass.set_bind_group(index as u32, *bind_group, &[]);
+    }
+    render_pass.draw(0..3, 0..1);
+}
+
+fn texel_size(size: winit::dpi::PhysicalSize<u32>) -> [f32; 2] {
+    [1.0 / size.width as f32, 1.0 / size.height as f32]
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomUniform {
+    texel_size: [f32; 2],
+    threshold: f32,
+    knee: f32,
+    scale: f32,
+    radius: f32,
+    _padding: [f32; 2],
+}
+
+struct BloomPipelines {
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    prefilter: wgpu::RenderPipeline,
+    downsample: wgpu::RenderPipeline,
+    upsample: wgpu::RenderPipeline,
+    uniforms: UniformSlots,
+}
+
+impl BloomPipelines {
+    fn new(ctx: &GpuContext) -> Self {
+        let bind_group_layout = effect_bind_group_layout(ctx, "bloom_bind_group_layout");
+        let layout = ctx
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Bloom Pipeline Layout"),
+                immediate_size: 0,
+                bind_group_layouts: &[Some(&bind_group_layout)],
+            });
+        let shader = ctx
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Bloom Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("bloom.wgsl").into()),
+            });
+        let format = RenderResources::HDR_FORMAT;
+        let additive = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Zero,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+        };
+
+        Self {
+            prefilter: fullscreen_pipeline(
+                ctx,
+                "Bloom Prefilter Pipeline",
+                &layout,
+                &shader,
+                "fs_prefilter",
+                format,
+                None,
+            ),
+            downsample: fullscreen_pipeline(
+                ctx,
+                "Bloom Downsample Pipeline",
+                &layout,
+                &shader,
+                "fs_downsample",
+                format,
+                None,
+            ),
+            upsample: fullscreen_pipeline(
+                ctx,
+                "Bloom Upsample Pipeline",
+                &layout,
+                &shader,
+                "fs_upsample",
+                format,
+                Some(additive),
+            ),
+            sampler: linear_sampler(ctx),
+            uniforms: UniformSlots::new(
+                ctx,
+                "Bloom Buffer",
+                std::mem::size_of::<BloomUniform>(),
+                2 * MAX_BLOOM_MIPS,
+            ),
+            bind_group_layout,
+        }
+    }
+}
+
+/// Adds the active camera's [`Bloom`] into [`ResourceId::HDR_COLOR`]. Its mips are only
+/// allocated while bloom is enabled.
+#[derive(Default)]
+pub struct BloomPass {
+    pipelines: Option<BloomPipelines>,
+}
+
+impl BloomPass {
+    pub const NAME: &'static str = "bloom";
+
+    fn mip_size(mip: usize) -> TextureSize {
+        TextureSize::Scaled(0.5f32.powi(mip as i32 + 1))
+    }
+}
+
+impl RenderGraphPass for BloomPass {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn setup(&self, builder: &mut PassBuilder) {
+        let enabled = builder.scene().is_none_or(|scene| {
+            scene
+                .active_post_process()
+                .is_some_and(|settings| settings.bloom.enabled)
+        });
+        if !enabled {
+            return;
+        }
+        builder.read_write(ResourceId::HDR_COLOR);
+        for (mip, &id) in BLOOM_MIPS.iter().enumerate() {
+            let mut texture = TransientTexture::color(RenderResources::HDR_FORMAT);
+            texture.size = Self::mip_size(mip);
+            builder.create_texture(id, texture);
+        }
+    }
+
+    fn execute(&mut self, pass: &mut PassContext<'_>) {
+        let bloom = match pass.scene.active_post_process() {
+            Some(settings) if settings.bloom.enabled => settings.bloom,
+            _ => return,
+        };
+        let sizes: Vec<_> = (0..MAX_BLOOM_MIPS)
+            .map(|mip| Self::mip_size(mip).resolve(pass.size))
+            .take_while(|size| size.width >= 2 && size.height >= 2)
+            .collect();
+        if sizes.is_empty() {
+            return;
+        }
+
+        let pipelines = self
+            .pipelines
+            .get_or_insert_with(|| BloomPipelines::new(pass.gpu));
+        let uniform = |source: winit::dpi::PhysicalSize<u32>, scale: f32| BloomUniform {
+            texel_size: texel_size(source),
+            threshold: bloom.threshold,
+            knee: bloom.knee.max(1e-4),
+            scale,
+            radius: bloom.radius,
+            _padding: [0.0; 2],
+        };
+
+        // Prefilter, the remaining downsamples, the upsamples and the composite.
+        let mut steps = Vec::new();
+        steps.push((ResourceId::HDR_COLOR, BLOOM_MIPS[0], pass.size, 1.0));
+        for mip in 1..sizes.len() {
+            steps.push((BLOOM_MIPS[mip - 1], BLOOM_MIPS[mip], sizes[mip - 1], 1.0));
+        }
+        for mip in (0..sizes.len() - 1).rev() {
+            steps.push((BLOOM_MIPS[mip + 1], BLOOM_MIPS[mip], sizes[mip + 1], 1.0));
+        }
+        steps.push((
+            BLOOM_MIPS[0],
+            ResourceId::HDR_COLOR,
+            sizes[0],
+            bloom.intensity,
+        ));
+
+        let uniforms: Vec<_> = steps
+            .iter()
+            .map(|&(_, _, source, scale)| uniform(source, scale))
+            .collect();
+        pipelines.uniforms.write(pass.gpu, &uniforms);
+
+        let downsamples = sizes.len();
+        for (index, &(source, target, _, _)) in steps.iter().enumerate() {
+            let bind_group = effect_bind_group(
+                pass.gpu,
+                &pipelines.bind_group_layout,
+                pass.texture(source),
+                &pipelines.sampler,
+                pipelines.uniforms.binding(index),
+            );
+            let (label, pipeline, load) = match index {
+                0 => (
+                    "Bloom Prefilter",
+                    &pipelines.prefilter,
+                    wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                ),
+                index if index < downsamples => (
+                    "Bloom Downsample",
+                    &pipelines.downsample,
+                    wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                ),
+                _ => ("Bloom Upsample", &pipelines.upsample, wgpu::LoadOp::Load),
+            };
+            draw_fullscreen(
+                pass.encoder,
+                label,
+                pass.texture(target),
+                load,
+                pipeline,
+                &[&bind_group],
+            );
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PostEffectUniform {
+    params: [f32; 4],
+    texel_size: [f32; 2],
+    decode_srgb: u32,
+    _padding: u32,
+}
+
+struct PostPipelines {
+    bind_group_layout: wgpu::BindGroupLayout,
+    lut_bind_group_layout: wgpu::BindGroupLayout,
+    layout: wgpu::PipelineLayout,
+    sampler: wgpu::Sampler,
+    color_grading: wgpu::RenderPipeline,
+    fxaa: wgpu::RenderPipeline,
+    chromatic_aberration: wgpu::RenderPipeline,
+    vignette: wgpu::RenderPipeline,
+    builtin: wgpu::ShaderModule,
+    /// The copy into the output, rebuilt when the output format changes.
+    blit: Option<(wgpu::TextureFormat, wgpu::RenderPipeline)>,
+}
+
+impl PostPipelines {
+    fn new(ctx: &GpuContext) -> Self {
+        let bind_group_layout = effect_bind_group_layout(ctx, "post_effect_bind_group_layout");
+        let lut_bind_group_layout =
+            ctx.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    entries: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D3,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    }],
+                    label: Some("color_lut_bind_group_layout"),
+                });
+        let layout = ctx
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Post Effect Pipeline Layout"),
+                immediate_size: 0,
+                bind_group_layouts: &[Some(&bind_group_layout)],
+            });
+        let lut_layout = ctx
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Color Grading Pipeline Layout"),
+                immediate_size: 0,
+                bind_group_layouts: &[Some(&bind_group_layout), Some(&lut_bind_group_layout)],
+            });
+        let builtin = ctx
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Post Effects Shader"),
+                source: wgpu::ShaderSource::Wgsl(
+                    format!(
+                        "{}\n{}",
+                        POST_EFFECT_PRELUDE,
+                        include_str!("post_effects.wgsl")
+                    )
+                    .into(),
+                ),
+            });
+
+        let format = RenderResources::LDR_FORMAT;
+        let effect = |label, layout, entry_point| {
+            fullscreen_pipeline(ctx, label, layout, &builtin, entry_point, format, None)
+        };
+        Self {
+            color_grading: effect("Color Grading Pipeline", &lut_layout, "fs_color_grading"),
+            fxaa: effect("FXAA Pipeline", &layout, "fs_fxaa"),
+            chromatic_aberration: effect(
+                "Chromatic Aberration Pipeline",
+                &layout,
+                "fs_chromatic_aberration",
+            ),
+            vignette: effect("Vignette Pipeline", &layout, "fs_vignette"),
+            sampler: linear_sampler(ctx),
+            bind_group_layout,
+            lut_bind_group_layout,
+            layout,
+            builtin,
+            blit: None,
+        }
+    }
+}
+
+enum EffectState {
+    /// Compiled in [`RenderGraphPass::prepare`] once a camera enables the effect.
+    Registered,
+    /// Waiting on the error scope around the compilation.
+    Compiling(
+        wgpu::RenderPipeline,
+        Pin<Box<dyn Future<Output = Option<wgpu::Error>>>>,
+    ),
+    Ready(wgpu::RenderPipeline),
+    Failed,
+}
+
+struct RegisteredEffect {
+    source: String,
+    state: EffectState,
+}
+
+/// Runs the active camera's LDR effects on [`ResourceId::LDR_COLOR`] and copies the
+/// result into the output. Does nothing when the camera has no LDR effects, as
+/// tonemapping then writes the output itself.
+///
+/// Replace the graph's pass with one carrying extra effects to register them:
+/// `graph.add_pass(PostProcessPass::new().with_effect("sepia", SEPIA_WGSL))`.
+#[derive(Default)]
+pub struct PostProcessPass {
+    effects: HashMap<String, RegisteredEffect>,
+    pipelines: Option<PostPipelines>,
+    uniforms: Option<UniformSlots>,
+}
+
+impl PostProcessPass {
+    pub const NAME: &'static str = "post_process";
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a WGSL effect under `name`. The source is compiled after
+    /// [`POST_EFFECT_PRELUDE`] and must define
+    /// `@fragment fn fs_effect(in: FullscreenOutput) -> @location(0) vec4<f32>`.
+    /// It compiles before the first frame a camera enables it in, and runs once the
+    /// device reports no errors, which native backends do right away.
+    pub fn register_effect(&mut self, name: impl Into<String>, wgsl: impl Into<String>) {
+        self.effects.insert(
+            name.into(),
+            RegisteredEffect {
+                source: wgsl.into(),
+                state: EffectState::Registered,
+            },
+        );
+    }
+
+    pub fn with_effect(mut self, name: impl Into<String>, wgsl: impl Into<String>) -> Self {
+        self.register_effect(name, wgsl);
+        self
+    }
+
+    pub fn has_effect(&self, name: &str) -> bool {
+        self.effects.contains_key(name)
+    }
+
+    /// The most effects `settings` runs, counting custom effects that may yet compile.
+    fn max_stages(&self, settings: &PostProcessSettings) -> usize {
+        let built_in = [
+            settings.color_grading.enabled && settings.color_grading.lut.is_some(),
+            settings.fxaa.enabled,
+            settings.chromatic_aberration.enabled,
+            settings.vignette.enabled,
+        ];
+        let custom = settings.custom.iter().filter(|effect| {
+            effect.enabled
+                && self
+                    .effects
+                    .get(&effect.name)
+                    .is_some_and(|registered| !matches!(registered.state, EffectState::Failed))
+        });
+        built_in.iter().filter(|&&enabled| enabled).count() + custom.count()
+    }
+
+    /// Starts compiling the effect `name`. The pipeline is only used once the error scope around
+    /// it resolves without an error, see [`PostProcessPass::poll_effect`].
+    fn compile_effect(
+        ctx: &GpuContext,
+        layout: &wgpu::PipelineLayout,
+        name: &str,
+        source: &str,
+    ) -> EffectState {
+        let scope = ctx.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = ctx
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(name),
+                source: wgpu::ShaderSource::Wgsl(
+                    format!("{}\n{}", POST_EFFECT_PRELUDE, source).into(),
+                ),
+            });
+        let pipeline = fullscreen_pipeline(
+            ctx,
+            name,
+            layout,
+            &module,
+            "fs_effect",
+            RenderResources::LDR_FORMAT,
+            None,
+        );
+        EffectState::Compiling(pipeline, Box::pin(scope.pop()))
+    }
+
+    /// Checks a compiling effect's error scope without waiting for it.
+    fn poll_effect(name: &str, state: EffectState) -> EffectState {
+        let (pipeline, mut error) = match state {
+            EffectState::Compiling(pipeline, error) => (pipeline, error),
+            state => return state,
+        };
+        let mut context = Context::from_waker(Waker::noop());
+        match error.as_mut().poll(&mut context) {
+            Poll::Pending => EffectState::Compiling(pipeline, error),
+            Poll::Ready(Some(error)) => {
+                warn!("post effect `{}` failed to compile: {}", name, error);
+                EffectState::Failed
+            }
+            Poll::Ready(None) => EffectState::Ready(pipeline),
+        }
+    }
+}
+
+impl RenderGraphPass for PostProcessPass {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn prepare(&mut self, ctx: &GpuContext, scene: &Scene) {
+        let settings = match scene.active_post_process() {
+            Some(settings) => settings,
+            None => return,
+        };
+        let pipelines = self
+            .pipelines
+            .get_or_insert_with(|| PostPipelines::new(ctx));
+        for effect in settings.custom.iter().filter(|effect| effect.enabled) {
+            if let Some(registered) = self.effects.get_mut(&effect.name) {
+                let state = std::mem::replace(&mut registered.state, EffectState::Failed);
+                let state = match state {
+                    EffectState::Registered => Self::compile_effect(
+                        ctx,
+                        &pipelines.layout,
+                        &effect.name,
+                        &registered.source,
+                    ),
+                    state => state,
+                };
+                registered.state = Self::poll_effect(&effect.name, state);
+            }
+        }
+    }
+
+    fn setup(&self, builder: &mut PassBuilder) {
+        let targets = match builder.scene().map(Scene::active_post_process) {
+            None => 2,
+            Some(Some(settings)) if settings.has_ldr_effects() => self.max_stages(settings),
+            Some(_) => return,
+        };
+        builder
+            .read(ResourceId::LDR_COLOR)
+            .write(ResourceId::OUTPUT);
+        // Effects alternate between the two, the final copy writes the output.
+        for &id in [POST_PING, POST_PONG].iter().take(targets) {
+            builder.create_texture(id, TransientTexture::color(RenderResources::LDR_FORMAT));
+        }
+    }
+
+    fn execute(&mut self, pass: &mut PassContext<'_>) {
+        let settings = match pass.scene.active_post_process() {
+            Some(settings) if settings.has_ldr_effects() => settings,
+            _ => return,
+        };
+        let output_format = pass
+            .texture_format(ResourceId::OUTPUT)
+            .expect("the output is always a texture");
+        let pipelines = self
+            .pipelines
+            .get_or_insert_with(|| PostPipelines::new(pass.gpu));
+        if pipelines.blit.as_ref().map(|(format, _)| *format) != Some(output_format) {
+            let blit = fullscreen_pipeline(
+                pass.gpu,
+                "Post Blit Pipeline",
+                &pipelines.layout,
+                &pipelines.builtin,
+                "fs_blit",
+                output_format,
+                None,
+            );
+            pipelines.blit = Some((output_format, blit));
+        }
+
+        // Each stage is a pipeline, its parameters and the LUT it reads, if any.
+        let mut stages: Vec<(&wgpu::RenderPipeline, [f32; 4], Option<&ColorLut>)> = Vec::new();
+        let grading = &settings.color_grading;
+        if let (true, Some(lut)) = (grading.enabled, &grading.lut) {
+            stages.push((
+                &pipelines.color_grading,
+                [grading.strength, 0.0, 0.0, 0.0],
+                Some(lut),
+            ));
+        }
+        let fxaa = &settings.fxaa;
+        if fxaa.enabled {
+            stages.push((
+                &pipelines.fxaa,
+                [fxaa.span_max, fxaa.reduce_mul, fxaa.reduce_min, 0.0],
+                None,
+            ));
+        }
+        let aberration = &settings.chromatic_aberration;
+        if aberration.enabled {
+            stages.push((
+                &pipelines.chromatic_aberration,
+                [aberration.intensity, 0.0, 0.0, 0.0],
+                None,
+            ));
+        }
+        let vignette = &settings.vignette;
+        if vignette.enabled {
+            stages.push((
+                &pipelines.vignette,
+                [
+                    vignette.intensity,
+                    vignette.radius,
+                    vignette.smoothness,
+                    0.0,
+                ],
+                None,
+            ));
+        }
+        for effect in settings.custom.iter().filter(|effect| effect.enabled) {
+            let registered = self.effects.get(&effect.name);
+            if let Some(EffectState::Ready(pipeline)) =
+                registered.map(|registered| &registered.state)
+            {
+                stages.push((pipeline, effect.params, None));
+            }
+        }
+        let blit = &pipelines.blit.as_ref().unwrap().1;
+        stages.push((blit, [0.0; 4], None));
+
+        let texel_size = texel_size(pass.size);
+        let uniforms: Vec<_> = stages
+            .iter()
+            .enumerate()
+            .map(|(index, &(_, params, _))| PostEffectUniform {
+                params,
+                texel_size,
+                decode_srgb: (index == stages.len() - 1 && output_format.is_srgb()) as u32,
+                _padding: 0,
+            })
+            .collect();
+        let slots = self.uniforms.get_or_insert_with(|| {
+            UniformSlots::new(
+                pass.gpu,
+                "Post Effect Buffer",
+                std::mem::size_of::<PostEffectUniform>(),
+                8,
+            )
+        });
+        slots.write(pass.gpu, &uniforms);
+
+        let mut source = ResourceId::LDR_COLOR;
+        for (index, &(pipeline, _, lut)) in stages.iter().enumerate() {
+            let target = if index == stages.len() - 1 {
+                ResourceId::OUTPUT
+            } else if index % 2 == 0 {
+                POST_PING
+            } else {
+                POST_PONG
+            };
+            let bind_group = effect_bind_group(
+                pass.gpu,
+                &pipelines.bind_group_layout,
+                pass.texture(source),
+                &pipelines.sampler,
+                slots.binding(index),
+            );
+            let lut_bind_group = lut.map(|lut| {
+                pass.gpu
+                    .device
+                    .create_bind_group(&wgpu::BindGroupDescriptor {
+                        layout: &pipelines.lut_bind_group_layout,
+                        entries: &[wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&lut.view),
+                        }],
+                        label: Some("color_lut_bind_group"),
+                    })
+            });
+            let mut bind_groups = vec![&bind_group];
+            bind_groups.extend(lut_bind_group.as_ref());
+            draw_fullscreen(
+                pass.encoder,
+                "Post Effect",
+                pass.texture(target),
+                wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                pipeline,
+                &bind_groups,
+            );
+            source = target;
+        }
+    }
+}
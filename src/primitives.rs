@@ -0,0 +1,734 @@
+use std::{collections::HashMap, f32::consts::PI};
+
+use anyhow::{Context, Result};
+use cgmath::prelude::*;
+use cgmath::{Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    renderer::GpuContext,
+    scene::{Mesh, MeshHandle, Scene, Vertex},
+};
+
+const BUILTIN_PRIMITIVE_PREFIX: &str = "builtin://primitive/";
+
+/// CPU-side geometry with optional per-vertex normals and tangents. Triangles wind
+/// counter-clockwise when seen from outside.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeshData {
+    pub positions: Vec<[f32; 3]>,
+    pub tex_coords: Vec<[f32; 2]>,
+    pub normals: Option<Vec<[f32; 3]>>,
+    /// Tangent in `xyz` with the bitangent sign in `w`.
+    pub tangents: Option<Vec<[f32; 4]>>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Interleaves the attributes for `Mesh::new`, using smooth normals and derived tangents
+    /// when none are set.
+    pub fn vertices(&self) -> Vec<Vertex> {
+        let smooth_normals;
+        let normals = match &self.normals {
+            Some(normals) => normals,
+            None => {
+                smooth_normals = self.smooth_normals();
+                &smooth_normals
+            }
+        };
+        let derived_tangents;
+        let tangents = match &self.tangents {
+            Some(tangents) => tangents,
+            None => {
+                derived_tangents = self.derived_tangents(normals);
+                &derived_tangents
+            }
+        };
+
+        self.positions
+            .iter()
+            .zip(&self.tex_coords)
+            .zip(normals.iter().zip(tangents))
+            .map(|((&position, &tex_coords), (&normal, &tangent))| Vertex {
+                position,
+                tex_coords,
+                normal,
+                tangent,
+            })
+            .collect()
+    }
+
+    /// Sets the normals to the area-weighted average of the adjacent face normals.
+    pub fn compute_normals(&mut self) {
+        self.normals = Some(self.smooth_normals());
+    }
+
+    fn smooth_normals(&self) -> Vec<[f32; 3]> {
+        let mut normals = vec![Vector3::zero(); self.positions.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let p = |index: u32| Vector3::from(self.positions[index as usize]);
+            let face = (p(triangle[1]) - p(triangle[0])).cross(p(triangle[2]) - p(triangle[0]));
+            for &index in triangle {
+                normals[index as usize] += face;
+            }
+        }
+
+        normals
+            .into_iter()
+            .map(|normal| {
+                if normal.magnitude2() > 0.0 {
+                    normal.normalize().into()
+                } else {
+                    [0.0, 1.0, 0.0]
+                }
+            })
+            .collect()
+    }
+
+    pub fn to_mesh(&self, ctx: &GpuContext, label: &str) -> Mesh {
+        Mesh::with_smallest_indices(ctx, label, &self.vertices(), &self.indices)
+    }
+
+    /// Like [`MeshData::to_mesh`], also keeping the geometry for exact raycasts, see
+    /// [`Mesh::with_collision_data`].
+    pub fn to_mesh_with_collision_data(&self, ctx: &GpuContext, label: &str) -> Mesh {
+        let vertices = self.vertices();
+        match Mesh::smallest_index_format(vertices.len()) {
+            wgpu::IndexFormat::Uint16 => {
+                let indices: Vec<u16> = self.indices.iter().map(|&index| index as u16).collect();
+                Mesh::with_collision_data(ctx, label, &vertices, &indices)
+            }
+            wgpu::IndexFormat::Uint32 => {
+                Mesh::with_collision_data(ctx, label, &vertices, &self.indices)
+            }
+        }
+    }
+
+    /// Derives tangents from the texture coordinates, orthogonalized against the normals.
+    /// Does nothing without normals.
+    pub fn compute_tangents(&mut self) {
+        if let Some(normals) = &self.normals {
+            self.tangents = Some(self.derived_tangents(normals));
+        }
+    }
+
+    fn derived_tangents(&self, normals: &[[f32; 3]]) -> Vec<[f32; 4]> {
+        let mut tangents = vec![Vector3::zero(); self.positions.len()];
+        let mut bitangents = vec![Vector3::zero(); self.positions.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [
+                triangle[0] as usize,
+                triangle[1] as usize,
+                triangle[2] as usize,
+            ];
+            let p = |index: usize| Vector3::from(self.positions[index]);
+            let uv = |index: usize| Vector2::from(self.tex_coords[index]);
+            let (edge1, edge2) = (p(b) - p(a), p(c) - p(a));
+            let (duv1, duv2) = (uv(b) - uv(a), uv(c) - uv(a));
+
+            let determinant = duv1.x * duv2.y - duv2.x * duv1.y;
+            if determinant.abs() <= f32::EPSILON {
+                continue;
+            }
+            let r = 1.0 / determinant;
+            let tangent = (edge1 * duv2.y - edge2 * duv1.y) * r;
+            let bitangent = (edge2 * duv1.x - edge1 * duv2.x) * r;
+            for index in [a, b, c] {
+                tangents[index] += tangent;
+                bitangents[index] += bitangent;
+            }
+        }
+
+        normals
+            .iter()
+            .zip(tangents.into_iter().zip(bitangents))
+            .map(|(&normal, (tangent, bitangent))| {
+                let normal = Vector3::from(normal);
+                let mut tangent = tangent - normal * normal.dot(tangent);
+                if tangent.magnitude2() <= f32::EPSILON {
+                    tangent = any_perpendicular(normal);
+                }
+                let tangent = tangent.normalize();
+                let sign = if normal.cross(tangent).dot(bitangent) < 0.0 {
+                    -1.0
+                } else {
+                    1.0
+                };
+                [tangent.x, tangent.y, tangent.z, sign]
+            })
+            .collect()
+    }
+}
+
+/// Which optional vertex attributes a generator should emit. Tangents imply normals.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PrimitiveAttributes {
+    pub normals: bool,
+    pub tangents: bool,
+}
+
+impl PrimitiveAttributes {
+    pub const NONE: Self = Self {
+        normals: false,
+        tangents: false,
+    };
+    pub const ALL: Self = Self {
+        normals: true,
+        tangents: true,
+    };
+}
+
+/// Procedural shapes centered on the origin with +Y up. Segment counts are clamped to the
+/// smallest values that still produce a closed shape.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Primitive {
+    Cube {
+        size: f32,
+        subdivisions: u32,
+    },
+    UvSphere {
+        radius: f32,
+        sectors: u32,
+        stacks: u32,
+    },
+    Icosphere {
+        radius: f32,
+        subdivisions: u32,
+    },
+    /// A flat grid in the XZ plane facing +Y.
+    Plane {
+        width: f32,
+        depth: f32,
+        subdivisions_x: u32,
+        subdivisions_z: u32,
+    },
+    Cylinder {
+        radius: f32,
+        height: f32,
+        sectors: u32,
+        stacks: u32,
+    },
+    Cone {
+        radius: f32,
+        height: f32,
+        sectors: u32,
+        stacks: u32,
+    },
+    /// `height` is the length of the cylindrical section between the two hemispheres.
+    Capsule {
+        radius: f32,
+        height: f32,
+        sectors: u32,
+        rings: u32,
+    },
+    /// Lies in the XZ plane around the Y axis.
+    Torus {
+        major_radius: f32,
+        minor_radius: f32,
+        major_segments: u32,
+        minor_segments: u32,
+    },
+}
+
+impl Primitive {
+    pub fn cube(size: f32) -> Self {
+        Self::Cube {
+            size,
+            subdivisions: 1,
+        }
+    }
+
+    pub fn uv_sphere(radius: f32) -> Self {
+        Self::UvSphere {
+            radius,
+            sectors: 32,
+            stacks: 16,
+        }
+    }
+
+    pub fn icosphere(radius: f32) -> Self {
+        Self::Icosphere {
+            radius,
+            subdivisions: 3,
+        }
+    }
+
+    pub fn plane(width: f32, depth: f32) -> Self {
+        Self::Plane {
+            width,
+            depth,
+            subdivisions_x: 1,
+            subdivisions_z: 1,
+        }
+    }
+
+    pub fn cylinder(radius: f32, height: f32) -> Self {
+        Self::Cylinder {
+            radius,
+            height,
+            sectors: 32,
+            stacks: 1,
+        }
+    }
+
+    pub fn cone(radius: f32, height: f32) -> Self {
+        Self::Cone {
+            radius,
+            height,
+            sectors: 32,
+            stacks: 1,
+        }
+    }
+
+    pub fn capsule(radius: f32, height: f32) -> Self {
+        Self::Capsule {
+            radius,
+            height,
+            sectors: 32,
+            rings: 8,
+        }
+    }
+
+    pub fn torus(major_radius: f32, minor_radius: f32) -> Self {
+        Self::Torus {
+            major_radius,
+            minor_radius,
+            major_segments: 32,
+            minor_segments: 16,
+        }
+    }
+
+    pub fn generate(&self, attributes: PrimitiveAttributes) -> MeshData {
+        let mut builder = Builder::default();
+        match *self {
+            Self::Cube { size, subdivisions } => {
+                let half = size * 0.5;
+                let faces = [
+                    ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+                    ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+                    ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0]),
+                    ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0]),
+                    ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0]),
+                    ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0]),
+                ];
+                for (normal, right) in faces {
+                    let normal = Vector3::from(normal);
+                    let right = Vector3::from(right);
+                    builder.grid(
+                        normal * half,
+                        right * size,
+                        normal.cross(right) * size,
+                        subdivisions.max(1),
+                        subdivisions.max(1),
+                    );
+                }
+            }
+            Self::UvSphere {
+                radius,
+                sectors,
+                stacks,
+            } => {
+                let stacks = stacks.max(2);
+                let rings = (0..=stacks).map(|stack| {
+                    let phi = PI * stack as f32 / stacks as f32;
+                    Ring::on_sphere(radius, phi, 0.0, stack as f32 / stacks as f32)
+                });
+                builder.lathe(rings.collect(), sectors.max(3));
+            }
+            Self::Icosphere {
+                radius,
+                subdivisions,
+            } => builder.icosphere(radius, subdivisions),
+            Self::Plane {
+                width,
+                depth,
+                subdivisions_x,
+                subdivisions_z,
+            } => builder.grid(
+                Vector3::zero(),
+                Vector3::unit_x() * width,
+                -Vector3::unit_z() * depth,
+                subdivisions_x.max(1),
+                subdivisions_z.max(1),
+            ),
+            Self::Cylinder {
+                radius,
+                height,
+                sectors,
+                stacks,
+            } => {
+                let (sectors, stacks) = (sectors.max(3), stacks.max(1));
+                let rings = (0..=stacks).map(|stack| {
+                    let t = stack as f32 / stacks as f32;
+                    Ring {
+                        radius,
+                        y: height * (0.5 - t),
+                        normal: [1.0, 0.0],
+                        v: t,
+                    }
+                });
+                builder.lathe(rings.collect(), sectors);
+                builder.cap(radius, height * 0.5, sectors, true);
+                builder.cap(radius, -height * 0.5, sectors, false);
+            }
+            Self::Cone {
+                radius,
+                height,
+                sectors,
+                stacks,
+            } => {
+                let (sectors, stacks) = (sectors.max(3), stacks.max(1));
+                let slant = Vector2::new(height, radius).normalize();
+                let rings = (0..=stacks).map(|stack| {
+                    let t = stack as f32 / stacks as f32;
+                    Ring {
+                        radius: radius * t,
+                        y: height * (0.5 - t),
+                        normal: [slant.x, slant.y],
+                        v: t,
+                    }
+                });
+                builder.lathe(rings.collect(), sectors);
+                builder.cap(radius, -height * 0.5, sectors, false);
+            }
+            Self::Capsule {
+                radius,
+                height,
+                sectors,
+                rings,
+            } => {
+                let rings = rings.max(1);
+                let half = height * 0.5;
+                let length = PI * radius + height;
+                let top = (0..=rings).map(|ring| {
+                    let phi = 0.5 * PI * ring as f32 / rings as f32;
+                    Ring::on_sphere(radius, phi, half, radius * phi / length)
+                });
+                let bottom = (0..=rings).map(|ring| {
+                    let phi = 0.5 * PI * (1.0 + ring as f32 / rings as f32);
+                    Ring::on_sphere(radius, phi, -half, (radius * phi + height) / length)
+                });
+                builder.lathe(top.chain(bottom).collect(), sectors.max(3));
+            }
+            Self::Torus {
+                major_radius,
+                minor_radius,
+                major_segments,
+                minor_segments,
+            } => builder.torus(
+                major_radius,
+                minor_radius,
+                major_segments.max(3),
+                minor_segments.max(3),
+            ),
+        }
+
+        builder.finish(attributes)
+    }
+
+    pub fn to_mesh(&self, ctx: &GpuContext) -> Mesh {
+        self.generate(PrimitiveAttributes {
+            normals: true,
+            tangents: false,
+        })
+        .to_mesh(ctx, &self.builtin_path())
+    }
+
+    /// The asset path scenes use to refer to this primitive, e.g.
+    /// `builtin://primitive/Cube(size:1.0,subdivisions:1)`.
+    pub fn builtin_path(&self) -> String {
+        let description =
+            ron::to_string(self).expect("primitive descriptions always serialize to RON");
+        format!("{BUILTIN_PRIMITIVE_PREFIX}{description}")
+    }
+
+    pub fn from_builtin_path(path: &str) -> Option<Result<Self>> {
+        let description = path.strip_prefix(BUILTIN_PRIMITIVE_PREFIX)?;
+        Some(
+            ron::from_str(description)
+                .with_context(|| format!("invalid primitive description `{description}`")),
+        )
+    }
+}
+
+impl Scene {
+    /// Registers the mesh for `primitive`, reusing it if the same primitive was added before.
+    pub fn add_primitive(&mut self, ctx: &GpuContext, primitive: &Primitive) -> MeshHandle {
+        let path = primitive.builtin_path();
+        match self.find_mesh(&path) {
+            Some(handle) => handle,
+            None => self.add_mesh_asset(path, primitive.to_mesh(ctx)),
+        }
+    }
+}
+
+/// One latitude of a surface of revolution. `normal` is the (radial, vertical) part of
+/// the surface normal.
+struct Ring {
+    radius: f32,
+    y: f32,
+    normal: [f32; 2],
+    v: f32,
+}
+
+impl Ring {
+    fn on_sphere(radius: f32, phi: f32, y_offset: f32, v: f32) -> Self {
+        let (sin, cos) = phi.sin_cos();
+        // Snap the poles so `lathe` recognizes them despite rounding in sin(PI).
+        let sin = if sin.abs() < 1e-6 { 0.0 } else { sin };
+        Self {
+            radius: radius * sin,
+            y: radius * cos + y_offset,
+            normal: [sin, cos],
+            v,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Builder {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    tex_coords: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl Builder {
+    fn vertex(&mut self, position: Vector3<f32>, normal: Vector3<f32>, uv: [f32; 2]) -> u32 {
+        self.positions.push(position.into());
+        self.normals.push(normal.into());
+        self.tex_coords.push(uv);
+        (self.positions.len() - 1) as u32
+    }
+
+    /// A grid of `columns` x `rows` quads spanning `right` and `up` around `center`, facing
+    /// `right x up`.
+    fn grid(
+        &mut self,
+        center: Vector3<f32>,
+        right: Vector3<f32>,
+        up: Vector3<f32>,
+        columns: u32,
+        rows: u32,
+    ) {
+        let normal = right.cross(up).normalize();
+        let first = self.positions.len() as u32;
+        for row in 0..=rows {
+            let t = row as f32 / rows as f32;
+            for column in 0..=columns {
+                let s = column as f32 / columns as f32;
+                let position = center + right * (s - 0.5) + up * (t - 0.5);
+                self.vertex(position, normal, [s, 1.0 - t]);
+            }
+        }
+
+        let stride = columns + 1;
+        for row in 0..rows {
+            for column in 0..columns {
+                let a = first + row * stride + column;
+                let (b, c, d) = (a + 1, a + stride + 1, a + stride);
+                self.indices.extend_from_slice(&[a, b, c, a, c, d]);
+            }
+        }
+    }
+
+    /// Revolves `rings`, ordered top to bottom, around the Y axis. Rings with zero radius
+    /// are poles and only emit one triangle per sector.
+    fn lathe(&mut self, rings: Vec<Ring>, sectors: u32) {
+        let first = self.positions.len() as u32;
+        for ring in &rings {
+            for sector in 0..=sectors {
+                let u = sector as f32 / sectors as f32;
+                let (sin, cos) = (2.0 * PI * u).sin_cos();
+                let position = Vector3::new(ring.radius * sin, ring.y, ring.radius * cos);
+                let normal =
+                    Vector3::new(ring.normal[0] * sin, ring.normal[1], ring.normal[0] * cos);
+                self.vertex(position, normal.normalize(), [u, ring.v]);
+            }
+        }
+
+        let stride = sectors + 1;
+        for (index, pair) in rings.windows(2).enumerate() {
+            let row = first + index as u32 * stride;
+            for sector in 0..sectors {
+                let a = row + sector;
+                let (b, c, d) = (a + stride, a + stride + 1, a + 1);
+                if pair[1].radius != 0.0 {
+                    self.indices.extend_from_slice(&[a, b, c]);
+                }
+                if pair[0].radius != 0.0 {
+                    self.indices.extend_from_slice(&[a, c, d]);
+                }
+            }
+        }
+    }
+
+    fn cap(&mut self, radius: f32, y: f32, sectors: u32, top: bool) {
+        let normal = if top {
+            Vector3::unit_y()
+        } else {
+            -Vector3::unit_y()
+        };
+        let center = self.vertex(Vector3::new(0.0, y, 0.0), normal, [0.5, 0.5]);
+        for sector in 0..=sectors {
+            let (sin, cos) = (2.0 * PI * sector as f32 / sectors as f32).sin_cos();
+            let v = if top {
+                0.5 + 0.5 * cos
+            } else {
+                0.5 - 0.5 * cos
+            };
+            self.vertex(
+                Vector3::new(radius * sin, y, radius * cos),
+                normal,
+                [0.5 + 0.5 * sin, v],
+            );
+        }
+        for sector in 0..sectors {
+            let (a, b) = (center + 1 + sector, center + 2 + sector);
+            if top {
+                self.indices.extend_from_slice(&[center, a, b]);
+            } else {
+                self.indices.extend_from_slice(&[center, b, a]);
+            }
+        }
+    }
+
+    fn torus(&mut self, major_radius: f32, minor_radius: f32, major: u32, minor: u32) {
+        let first = self.positions.len() as u32;
+        for i in 0..=major {
+            let u = i as f32 / major as f32;
+            let (sin_u, cos_u) = (2.0 * PI * u).sin_cos();
+            let radial = Vector3::new(sin_u, 0.0, cos_u);
+            for j in 0..=minor {
+                let v = j as f32 / minor as f32;
+                let (sin_v, cos_v) = (2.0 * PI * v).sin_cos();
+                let normal = radial * cos_v + Vector3::unit_y() * sin_v;
+                let position = radial * major_radius + normal * minor_radius;
+                self.vertex(position, normal, [u, v]);
+            }
+        }
+
+        let stride = minor + 1;
+        for i in 0..major {
+            for j in 0..minor {
+                let a = first + i * stride + j;
+                let (b, c, d) = (a + stride, a + stride + 1, a + 1);
+                self.indices.extend_from_slice(&[a, b, c, a, c, d]);
+            }
+        }
+    }
+
+    fn icosphere(&mut self, radius: f32, subdivisions: u32) {
+        let t = (1.0 + 5f32.sqrt()) / 2.0;
+        let mut points: Vec<Vector3<f32>> = [
+            [-1.0, t, 0.0],
+            [1.0, t, 0.0],
+            [-1.0, -t, 0.0],
+            [1.0, -t, 0.0],
+            [0.0, -1.0, t],
+            [0.0, 1.0, t],
+            [0.0, -1.0, -t],
+            [0.0, 1.0, -t],
+            [t, 0.0, -1.0],
+            [t, 0.0, 1.0],
+            [-t, 0.0, -1.0],
+            [-t, 0.0, 1.0],
+        ]
+        .iter()
+        .map(|&point| Vector3::from(point).normalize())
+        .collect();
+        let mut triangles: Vec<[u32; 3]> = vec![
+            [0, 11, 5],
+            [0, 5, 1],
+            [0, 1, 7],
+            [0, 7, 10],
+            [0, 10, 11],
+            [1, 5, 9],
+            [5, 11, 4],
+            [11, 10, 2],
+            [10, 7, 6],
+            [7, 1, 8],
+            [3, 9, 4],
+            [3, 4, 2],
+            [3, 2, 6],
+            [3, 6, 8],
+            [3, 8, 9],
+            [4, 9, 5],
+            [2, 4, 11],
+            [6, 2, 10],
+            [8, 6, 7],
+            [9, 8, 1],
+        ];
+
+        for _ in 0..subdivisions {
+            let mut midpoints = HashMap::new();
+            let mut midpoint = |a: u32, b: u32| {
+                *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    points.push(((points[a as usize] + points[b as usize]) * 0.5).normalize());
+                    (points.len() - 1) as u32
+                })
+            };
+            triangles = triangles
+                .iter()
+                .flat_map(|&[a, b, c]| {
+                    let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                    [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+                })
+                .collect();
+        }
+
+        // Spherical UVs wrap around at u = 1, so triangles crossing the seam get copies of
+        // their low-u corners shifted by one.
+        let uv = |point: Vector3<f32>| {
+            [
+                0.5 + point.x.atan2(point.z) / (2.0 * PI),
+                point.y.clamp(-1.0, 1.0).acos() / PI,
+            ]
+        };
+        let mut vertices = HashMap::new();
+        for triangle in triangles {
+            let uvs = triangle.map(|index| uv(points[index as usize]));
+            let wraps = uvs.iter().any(|uv| uv[0] > 0.75) && uvs.iter().any(|uv| uv[0] < 0.25);
+            for (&index, mut uv) in triangle.iter().zip(uvs) {
+                let shifted = wraps && uv[0] < 0.5;
+                if shifted {
+                    uv[0] += 1.0;
+                }
+                let point = points[index as usize];
+                let vertex = *vertices
+                    .entry((index, shifted))
+                    .or_insert_with(|| self.vertex(point * radius, point, uv));
+                self.indices.push(vertex);
+            }
+        }
+    }
+
+    fn finish(self, attributes: PrimitiveAttributes) -> MeshData {
+        let mut data = MeshData {
+            positions: self.positions,
+            tex_coords: self.tex_coords,
+            normals: Some(self.normals),
+            tangents: None,
+            indices: self.indices,
+        };
+        if attributes.tangents {
+            data.compute_tangents();
+        } else if !attributes.normals {
+            data.normals = None;
+        }
+        data
+    }
+}
+
+fn any_perpendicular(normal: Vector3<f32>) -> Vector3<f32> {
+    let axis = if normal.x.abs() < 0.9 {
+        Vector3::unit_x()
+    } else {
+        Vector3::unit_y()
+    };
+    normal.cross(axis)
+}
@@ -0,0 +1,662 @@
+use std::collections::{BTreeSet, HashMap};
+
+use anyhow::{bail, Result};
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    gpu_culling::CullPass,
+    post_process::{BloomPass, PostProcessPass},
+    renderer::{GpuContext, RenderResources},
+    scene::Scene,
+    texture::Texture,
+    tonemap::TonemapPass,
+};
+
+/// Names a texture or buffer that passes read and write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ResourceId(pub &'static str);
+
+impl ResourceId {
+    /// The frame's final color target: the swapchain image or the offscreen target.
+    pub const OUTPUT: Self = Self("output");
+    /// The scene in linear HDR color, a transient [`RenderResources::HDR_FORMAT`] texture
+    /// sized to the target.
+    pub const HDR_COLOR: Self = Self("hdr_color");
+    /// The multisampled scene color that resolves into [`ResourceId::HDR_COLOR`], created
+    /// only while [`RenderResources::msaa_samples`] is above 1.
+    pub const HDR_COLOR_MSAA: Self = Self("hdr_color_msaa");
+    /// The tonemapped scene, a transient [`RenderResources::LDR_FORMAT`] texture that the
+    /// post-processing effects run on.
+    pub const LDR_COLOR: Self = Self("ldr_color");
+    /// The main pass's depth buffer, a transient texture sized to the target and
+    /// multisampled like the scene color.
+    pub const DEPTH: Self = Self("depth");
+    /// [`crate::shadow::ShadowMaps::atlas`].
+    pub const SHADOW_ATLAS: Self = Self("shadow_atlas");
+    /// [`RenderResources::camera_buffer`].
+    pub const CAMERA: Self = Self("camera");
+    /// [`RenderResources::lights_buffer`].
+    pub const LIGHTS: Self = Self("lights");
+    /// Orders [`crate::gpu_culling::CullPass`] before the passes drawing the scene. Only a
+    /// dependency marker: each batch has its own culled buffers, which passes reach through
+    /// [`crate::gpu_culling::GpuCulling::batch`], so [`PassContext::buffer`] has nothing
+    /// registered under this id.
+    pub const VISIBLE_INSTANCES: Self = Self("visible_instances");
+
+    /// Resources the renderer provides rather than a pass, including the dependency-only
+    /// [`ResourceId::VISIBLE_INSTANCES`].
+    pub const IMPORTED: [Self; 5] = [
+        Self::OUTPUT,
+        Self::SHADOW_ATLAS,
+        Self::CAMERA,
+        Self::LIGHTS,
+        Self::VISIBLE_INSTANCES,
+    ];
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextureSize {
+    /// Matches the render target, following it through resizes.
+    Surface,
+    /// The render target's size multiplied by a factor, at least 1x1.
+    Scaled(f32),
+    Fixed(PhysicalSize<u32>),
+}
+
+impl TextureSize {
+    pub fn resolve(self, target: PhysicalSize<u32>) -> PhysicalSize<u32> {
+        let size = match self {
+            TextureSize::Surface => target,
+            TextureSize::Scaled(factor) => PhysicalSize::new(
+                (target.width as f32 * factor).round() as u32,
+                (target.height as f32 * factor).round() as u32,
+            ),
+            TextureSize::Fixed(size) => size,
+        };
+        PhysicalSize::new(size.width.max(1), size.height.max(1))
+    }
+}
+
+/// A texture the graph allocates for the passes of a frame and keeps until its
+/// description or the target size changes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransientTexture {
+    pub format: wgpu::TextureFormat,
+    pub size: TextureSize,
+    pub usage: wgpu::TextureUsages,
+    pub sample_count: u32,
+}
+
+impl TransientTexture {
+    /// A target-sized attachment that later passes can also sample.
+    pub fn color(format: wgpu::TextureFormat) -> Self {
+        Self {
+            format,
+            size: TextureSize::Surface,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            sample_count: 1,
+        }
+    }
+
+    /// A target-sized depth buffer in [`Texture::DEPTH_FORMAT`].
+    pub fn depth() -> Self {
+        Self {
+            format: Texture::DEPTH_FORMAT,
+            size: TextureSize::Surface,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            sample_count: 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    /// Consumes the resource; runs after every pass writing it.
+    Read,
+    /// Overwrites the resource; runs before every pass reading or modifying it.
+    Write,
+    /// Loads and adds to the resource, e.g. an overlay drawn onto the output. Runs after
+    /// the writers and before the readers, in the order the passes were added.
+    ReadWrite,
+}
+
+/// Collects what a pass reads and writes during [`RenderGraphPass::setup`].
+pub struct PassBuilder<'a> {
+    accesses: Vec<(ResourceId, Access)>,
+    textures: Vec<(ResourceId, TransientTexture)>,
+    msaa_samples: u32,
+    scene: Option<&'a Scene>,
+}
+
+impl Default for PassBuilder<'_> {
+    fn default() -> Self {
+        Self {
+            accesses: Vec::new(),
+            textures: Vec::new(),
+            msaa_samples: 1,
+            scene: None,
+        }
+    }
+}
+
+impl<'a> PassBuilder<'a> {
+    /// The scene's MSAA sample count for this frame, see
+    /// [`RenderResources::set_msaa_samples`].
+    pub fn msaa_samples(&self) -> u32 {
+        self.msaa_samples
+    }
+
+    /// The frame's scene, so passes can skip resources it does not need. `None` outside
+    /// a frame, as in [`RenderGraph::execution_order`], where passes declare everything
+    /// they might use.
+    pub fn scene(&self) -> Option<&'a Scene> {
+        self.scene
+    }
+
+    pub fn read(&mut self, id: ResourceId) -> &mut Self {
+        self.accesses.push((id, Access::Read));
+        self
+    }
+
+    pub fn write(&mut self, id: ResourceId) -> &mut Self {
+        self.accesses.push((id, Access::Write));
+        self
+    }
+
+    pub fn read_write(&mut self, id: ResourceId) -> &mut Self {
+        self.accesses.push((id, Access::ReadWrite));
+        self
+    }
+
+    /// Asks the graph for a transient texture and declares the pass as its writer.
+    pub fn create_texture(&mut self, id: ResourceId, texture: TransientTexture) -> &mut Self {
+        self.textures.push((id, texture));
+        self.write(id)
+    }
+
+    pub fn accesses(&self) -> &[(ResourceId, Access)] {
+        &self.accesses
+    }
+}
+
+/// A step of the frame. Implement this to insert custom passes with
+/// [`RenderGraph::add_pass`].
+pub trait RenderGraphPass {
+    /// Unique within a graph.
+    fn name(&self) -> &str;
+
+    /// Creates GPU state the frame needs, such as pipelines, before recording starts.
+    /// Called every frame before [`RenderGraphPass::setup`].
+    fn prepare(&mut self, _ctx: &GpuContext, _scene: &Scene) {}
+
+    /// Declares the resources the pass reads and writes; called every frame.
+    fn setup(&self, builder: &mut PassBuilder);
+
+    fn execute(&mut self, pass: &mut PassContext<'_>);
+}
+
+enum Resource<'a> {
+    Texture {
+        view: &'a wgpu::TextureView,
+        format: wgpu::TextureFormat,
+    },
+    Buffer(&'a wgpu::Buffer),
+}
+
+/// What a pass gets to record its commands.
+pub struct PassContext<'a> {
+    pub gpu: &'a GpuContext,
+    pub scene: &'a Scene,
+    pub resources: &'a RenderResources,
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    /// The size of [`ResourceId::OUTPUT`].
+    pub size: PhysicalSize<u32>,
+    pub clear_color: wgpu::Color,
+    registry: &'a HashMap<ResourceId, Resource<'a>>,
+}
+
+impl<'a> PassContext<'a> {
+    /// Panics if the graph has no texture named `id`.
+    pub fn texture(&self, id: ResourceId) -> &'a wgpu::TextureView {
+        match self.registry.get(&id) {
+            Some(Resource::Texture { view, .. }) => view,
+            _ => panic!("render graph has no texture `{}`", id.0),
+        }
+    }
+
+    pub fn texture_format(&self, id: ResourceId) -> Option<wgpu::TextureFormat> {
+        match self.registry.get(&id) {
+            Some(Resource::Texture { format, .. }) => Some(*format),
+            _ => None,
+        }
+    }
+
+    /// Panics if the graph has no buffer named `id`, as for dependency markers like
+    /// [`ResourceId::VISIBLE_INSTANCES`].
+    pub fn buffer(&self, id: ResourceId) -> &'a wgpu::Buffer {
+        match self.registry.get(&id) {
+            Some(Resource::Buffer(buffer)) => buffer,
+            _ => panic!("render graph has no buffer `{}`", id.0),
+        }
+    }
+}
+
+/// The texture a frame ends up in.
+pub struct FrameTarget<'a> {
+    pub view: &'a wgpu::TextureView,
+    pub format: wgpu::TextureFormat,
+    pub size: PhysicalSize<u32>,
+    pub clear_color: wgpu::Color,
+}
+
+struct PooledTexture {
+    texture: TransientTexture,
+    size: PhysicalSize<u32>,
+    view: wgpu::TextureView,
+}
+
+/// Orders passes by the resources they declare, allocates their transient textures and
+/// records them into one command buffer per frame.
+#[derive(Default)]
+pub struct RenderGraph {
+    passes: Vec<Box<dyn RenderGraphPass>>,
+    textures: HashMap<ResourceId, PooledTexture>,
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The culling, shadow, scene, bloom, tonemapping and post-processing passes the
+    /// renderers start with.
+    pub fn with_default_passes() -> Self {
+        let mut graph = Self::new();
+        graph.add_pass(CullPass);
+        graph.add_pass(ShadowPass);
+        graph.add_pass(ScenePass);
+        graph.add_pass(BloomPass::default());
+        graph.add_pass(TonemapPass::default());
+        graph.add_pass(PostProcessPass::default());
+        graph
+    }
+
+    /// Replaces any pass with the same name.
+    pub fn add_pass(&mut self, pass: impl RenderGraphPass + 'static) {
+        let pass: Box<dyn RenderGraphPass> = Box::new(pass);
+        match self.passes.iter().position(|p| p.name() == pass.name()) {
+            Some(index) => self.passes[index] = pass,
+            None => self.passes.push(pass),
+        }
+    }
+
+    pub fn remove_pass(&mut self, name: &str) -> Option<Box<dyn RenderGraphPass>> {
+        let index = self.passes.iter().position(|pass| pass.name() == name)?;
+        Some(self.passes.remove(index))
+    }
+
+    pub fn pass_names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.passes.iter().map(|pass| pass.name())
+    }
+
+    /// The transient textures the last frame allocated, kept for the next one.
+    pub fn transient_textures(&self) -> impl Iterator<Item = ResourceId> + '_ {
+        self.textures.keys().copied()
+    }
+
+    /// The order [`RenderGraph::execute`] runs the graph's passes in.
+    pub fn execution_order(&self) -> Result<Vec<&str>> {
+        let passes: Vec<&dyn RenderGraphPass> = self.passes.iter().map(|p| p.as_ref()).collect();
+        let builders = setup_passes(&passes, 1, None);
+        let order = sort_passes(&passes, &builders, &ResourceId::IMPORTED)?;
+        Ok(order
+            .into_iter()
+            .map(|index| passes[index].name())
+            .collect())
+    }
+
+    /// Records and submits one frame. `frame_passes` join the graph's passes for this
+    /// frame only, which lets them borrow state such as UI contexts.
+    pub fn execute<'p>(
+        &mut self,
+        ctx: &GpuContext,
+        scene: &Scene,
+        resources: &RenderResources,
+        target: FrameTarget<'_>,
+        frame_passes: &mut [&'p mut (dyn RenderGraphPass + 'p)],
+    ) -> Result<()> {
+        let mut passes: Vec<&mut (dyn RenderGraphPass + 'p)> = self
+            .passes
+            .iter_mut()
+            .map(|pass| pass.as_mut() as &mut (dyn RenderGraphPass + 'p))
+            .chain(frame_passes.iter_mut().map(|pass| &mut **pass))
+            .collect();
+        for pass in &mut passes {
+            pass.prepare(ctx, scene);
+        }
+        let builders = {
+            let shared: Vec<&dyn RenderGraphPass> = passes.iter().map(|p| &**p).collect();
+            setup_passes(&shared, resources.msaa_samples(), Some(scene))
+        };
+
+        let mut imported = HashMap::new();
+        imported.insert(
+            ResourceId::OUTPUT,
+            Resource::Texture {
+                view: target.view,
+                format: target.format,
+            },
+        );
+        imported.insert(
+            ResourceId::SHADOW_ATLAS,
+            Resource::Texture {
+                view: &resources.shadows.atlas.view,
+                format: Texture::DEPTH_FORMAT,
+            },
+        );
+        imported.insert(
+            ResourceId::CAMERA,
+            Resource::Buffer(&resources.camera_buffer),
+        );
+        imported.insert(
+            ResourceId::LIGHTS,
+            Resource::Buffer(&resources.lights_buffer),
+        );
+        let order = {
+            let shared: Vec<&dyn RenderGraphPass> = passes.iter().map(|p| &**p).collect();
+            sort_passes(&shared, &builders, &ResourceId::IMPORTED)?
+        };
+
+        let mut transient: HashMap<ResourceId, TransientTexture> = HashMap::new();
+        for (index, builder) in builders.iter().enumerate() {
+            for &(id, texture) in &builder.textures {
+                if imported.contains_key(&id) {
+                    bail!(
+                        "pass `{}` creates `{}`, which is imported",
+                        passes[index].name(),
+                        id.0
+                    );
+                }
+                if transient
+                    .get(&id)
+                    .is_some_and(|existing| *existing != texture)
+                {
+                    bail!("passes create `{}` with different descriptions", id.0);
+                }
+                transient.insert(id, texture);
+            }
+        }
+        self.textures.retain(|id, _| transient.contains_key(id));
+        for (&id, &texture) in &transient {
+            let size = texture.size.resolve(target.size);
+            let stale = self
+                .textures
+                .get(&id)
+                .is_none_or(|pooled| pooled.texture != texture || pooled.size != size);
+            if stale {
+                let view = create_transient_texture(ctx, id, texture, size);
+                self.textures.insert(
+                    id,
+                    PooledTexture {
+                        texture,
+                        size,
+                        view,
+                    },
+                );
+            }
+        }
+
+        let mut registry = imported;
+        for (&id, pooled) in &self.textures {
+            registry.insert(
+                id,
+                Resource::Texture {
+                    view: &pooled.view,
+                    format: pooled.texture.format,
+                },
+            );
+        }
+
+        let mut encoder = ctx
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Graph Encoder"),
+            });
+        for index in order {
+            let mut pass = PassContext {
+                gpu: ctx,
+                scene,
+                resources,
+                encoder: &mut encoder,
+                size: target.size,
+                clear_color: target.clear_color,
+                registry: &registry,
+            };
+            passes[index].execute(&mut pass);
+        }
+        ctx.queue.submit(std::iter::once(encoder.finish()));
+        Ok(())
+    }
+}
+
+fn setup_passes<'a>(
+    passes: &[&dyn RenderGraphPass],
+    msaa_samples: u32,
+    scene: Option<&'a Scene>,
+) -> Vec<PassBuilder<'a>> {
+    passes
+        .iter()
+        .map(|pass| {
+            let mut builder = PassBuilder {
+                msaa_samples,
+                scene,
+                ..Default::default()
+            };
+            pass.setup(&mut builder);
+            builder
+        })
+        .collect()
+}
+
+/// Topologically sorts the passes, preferring the order they were added in.
+fn sort_passes(
+    passes: &[&dyn RenderGraphPass],
+    builders: &[PassBuilder],
+    imported: &[ResourceId],
+) -> Result<Vec<usize>> {
+    let mut names = BTreeSet::new();
+    for pass in passes {
+        if !names.insert(pass.name()) {
+            bail!("more than one pass is named `{}`", pass.name());
+        }
+    }
+
+    let mut users: HashMap<ResourceId, [Vec<usize>; 3]> = HashMap::new();
+    for (index, builder) in builders.iter().enumerate() {
+        for &(id, access) in builder.accesses() {
+            let slot = match access {
+                Access::Write => 0,
+                Access::ReadWrite => 1,
+                Access::Read => 2,
+            };
+            let list = &mut users.entry(id).or_default()[slot];
+            if list.last() != Some(&index) {
+                list.push(index);
+            }
+        }
+    }
+
+    let mut edges = vec![BTreeSet::new(); passes.len()];
+    for (id, [writers, modifiers, readers]) in &users {
+        if writers.is_empty() && !imported.contains(id) {
+            let user = modifiers.first().or_else(|| readers.first()).unwrap();
+            bail!(
+                "pass `{}` reads `{}`, but no pass writes it",
+                passes[*user].name(),
+                id.0
+            );
+        }
+        // Writers and modifiers each keep the order they were added in.
+        let mut edge = |from: usize, to: usize| {
+            if from != to {
+                edges[from].insert(to);
+            }
+        };
+        let mut chain = writers.iter().chain(modifiers).peekable();
+        while let (Some(&from), Some(&&to)) = (chain.next(), chain.peek()) {
+            edge(from, to);
+        }
+        if let Some(&last) = modifiers.last().or_else(|| writers.last()) {
+            for &reader in readers {
+                edge(last, reader);
+            }
+        }
+    }
+
+    let mut incoming = vec![0; passes.len()];
+    for &to in edges.iter().flatten() {
+        incoming[to] += 1;
+    }
+
+    let mut ready: BTreeSet<usize> = (0..passes.len()).filter(|&i| incoming[i] == 0).collect();
+    let mut order = Vec::with_capacity(passes.len());
+    while let Some(index) = ready.pop_first() {
+        order.push(index);
+        for &to in &edges[index] {
+            incoming[to] -= 1;
+            if incoming[to] == 0 {
+                ready.insert(to);
+            }
+        }
+    }
+    if order.len() != passes.len() {
+        let stuck: Vec<&str> = (0..passes.len())
+            .filter(|index| !order.contains(index))
+            .map(|index| passes[index].name())
+            .collect();
+        bail!("render graph has a cycle between {:?}", stuck);
+    }
+    Ok(order)
+}
+
+fn create_transient_texture(
+    ctx: &GpuContext,
+    id: ResourceId,
+    texture: TransientTexture,
+    size: PhysicalSize<u32>,
+) -> wgpu::TextureView {
+    ctx.device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some(id.0),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: texture.sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: texture.format,
+            usage: texture.usage,
+            view_formats: &[],
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+/// Renders the shadow atlas, see [`crate::shadow::ShadowMaps`].
+pub struct ShadowPass;
+
+impl ShadowPass {
+    pub const NAME: &'static str = "shadows";
+}
+
+impl RenderGraphPass for ShadowPass {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn setup(&self, builder: &mut PassBuilder) {
+        builder.write(ResourceId::SHADOW_ATLAS);
+    }
+
+    fn execute(&mut self, pass: &mut PassContext<'_>) {
+        pass.resources.draw_shadows(pass.encoder, pass.scene);
+    }
+}
+
+/// Clears [`ResourceId::HDR_COLOR`] and draws the scene's render batches into it, through
+/// [`ResourceId::HDR_COLOR_MSAA`] when MSAA is on.
+pub struct ScenePass;
+
+impl ScenePass {
+    pub const NAME: &'static str = "scene";
+}
+
+impl RenderGraphPass for ScenePass {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn setup(&self, builder: &mut PassBuilder) {
+        let samples = builder.msaa_samples();
+        let mut depth = TransientTexture::depth();
+        depth.sample_count = samples;
+        builder
+            .read(ResourceId::CAMERA)
+            .read(ResourceId::LIGHTS)
+            .read(ResourceId::SHADOW_ATLAS)
+            .read(ResourceId::VISIBLE_INSTANCES)
+            .create_texture(
+                ResourceId::HDR_COLOR,
+                TransientTexture::color(RenderResources::HDR_FORMAT),
+            )
+            .create_texture(ResourceId::DEPTH, depth);
+        if samples > 1 {
+            builder.create_texture(
+                ResourceId::HDR_COLOR_MSAA,
+                TransientTexture {
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    sample_count: samples,
+                    ..TransientTexture::color(RenderResources::HDR_FORMAT)
+                },
+            );
+        }
+    }
+
+    fn execute(&mut self, pass: &mut PassContext<'_>) {
+        let (view, resolve_target, store) = if pass.resources.msaa_samples() > 1 {
+            (
+                pass.texture(ResourceId::HDR_COLOR_MSAA),
+                Some(pass.texture(ResourceId::HDR_COLOR)),
+                wgpu::StoreOp::Discard,
+            )
+        } else {
+            (
+                pass.texture(ResourceId::HDR_COLOR),
+                None,
+                wgpu::StoreOp::Store,
+            )
+        };
+        let mut render_pass = pass.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Scene Pass"),
+            multiview_mask: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(pass.clear_color),
+                    store,
+                },
+                depth_slice: None,
+            })],
+            depth_stencil_attachment: Some(
+                pass.resources
+                    .depth()
+                    .attachment(pass.texture(ResourceId::DEPTH)),
+            ),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.resources.draw_scene(&mut render_pass, pass.scene);
+    }
+}
@@ -5,7 +5,7 @@ use log::debug;
 use std::default::Default;
 use std::{path::PathBuf, sync::Arc, time::Instant};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use wgpu::{util::DeviceExt, CurrentSurfaceTexture};
 use wgpu::{
     BackendOptions, Dx12BackendOptions, ExperimentalFeatures, GlBackendOptions, InstanceFlags,
@@ -15,13 +15,23 @@ use winit::{dpi::PhysicalSize, window::Window};
 use winit::event::WindowEvent;
 use crate::{
     camera::CameraUniform,
-    scene::{InstanceRaw, Scene, Vertex},
+    culling::CullStats,
+    gpu_culling::GpuCulling,
+    light::LightsUniform,
+    pbr::MaterialLayout,
+    render_graph::{
+        FrameTarget, PassBuilder, PassContext, RenderGraph, RenderGraphPass, ResourceId,
+    },
+    scene::{CameraComponent, InstanceRaw, Scene, Shading, Vertex},
+    shadow::{ShadowConfig, ShadowMaps},
+    texture::MipmapGenerator,
 };
 
 pub struct Renderer<'window> {
     pub window: Arc<winit::window::Window>,
     pub surface: RenderSurface<'window>,
     pub resources: RenderResources,
+    pub graph: RenderGraph,
     pub imgui: ImguiState,
 }
 
@@ -44,7 +54,8 @@ impl<'window> Renderer<'window> {
         size: PhysicalSize<u32>,
     ) -> Result<Self> {
         let surface = RenderSurface::new(ctx, surface, size)?;
-        let resources = RenderResources::new(ctx, surface.surface_format);
+        let resources =
+            RenderResources::new(ctx, RenderResources::HDR_FORMAT, DepthSettings::default());
 
         // Setup ImGui immediately
         let mut context = dear_imgui_rs::Context::create();
@@ -102,12 +113,13 @@ impl<'window> Renderer<'window> {
             window,
             surface,
             resources,
+            graph: RenderGraph::with_default_passes(),
             imgui,
         })
     }
 
-    pub fn texture_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
-        &self.resources.texture_bind_group_layout
+    pub fn material_layout(&self) -> &MaterialLayout {
+        &self.resources.material_layout
     }
 
     pub fn resize(&mut self, ctx: &GpuContext, size: PhysicalSize<u32>) {
@@ -119,13 +131,9 @@ impl<'window> Renderer<'window> {
             return None;
         }
 
-        if let Some(camera_uniform) = scene.active_camera_uniform() {
-            ctx.queue.write_buffer(
-                &self.resources.camera_buffer,
-                0,
-                bytemuck::bytes_of(&camera_uniform),
-            );
-        }
+        self.resources.update_camera(ctx, scene);
+        self.resources.update_lights(ctx, scene);
+        self.resources.update_culling(ctx, scene);
 
         let output = match self.surface.surface.get_current_texture() {
             CurrentSurfaceTexture::Success(current_texture) => current_texture,
@@ -135,14 +143,10 @@ impl<'window> Renderer<'window> {
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = ctx
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
-            });
 
         self.imgui.render(ctx, &mut self.surface, &mut self.window);
 
+        let culling = self.resources.cull_stats(scene);
         self.imgui
             .platform
             .prepare_frame(&self.window, &mut self.imgui.context);
@@ -161,74 +165,295 @@ impl<'window> Renderer<'window> {
                         1000.0 / ui.io().framerate(),
                         ui.io().framerate()
                     ));
+                    ui.text(format!(
+                        "Instances: {} visible, {} culled",
+                        culling.visible, culling.culled
+                    ));
                 });
         }
 
+        let target = FrameTarget {
+            view: &view,
+            format: self.surface.config.format,
+            size: PhysicalSize::new(self.surface.config.width, self.surface.config.height),
+            clear_color: self.imgui.clear_color,
+        };
+        let mut imgui_pass = ImguiPass {
+            imgui: &mut self.imgui,
+        };
+        if let Err(error) =
+            self.graph
+                .execute(ctx, scene, &self.resources, target, &mut [&mut imgui_pass])
         {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                multiview_mask: None,
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                    depth_slice: None,
-                })],
-                // depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                //     view: &self.surface.depth_texture.view,
-                //     depth_ops: Some(wgpu::Operations {
-                //         load: wgpu::LoadOp::Clear(1.0),
-                //         store: wgpu::StoreOp::Store,
-                //     }),
-                //     stencil_ops: None,
-                // }),
-                depth_stencil_attachment: None,
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            });
+            log::error!("failed to render frame: {error:#}");
+        }
+        self.resources.finish_culling();
+        output.present();
 
-            render_pass.set_pipeline(&self.resources.render_pipeline);
-            render_pass.set_bind_group(1, &self.resources.camera_bind_group, &[]);
-
-            for batch in &scene.render_batches {
-                let Some(mesh) = scene.mesh(batch.mesh) else {
-                    continue;
-                };
-                let Some(material) = scene.material(batch.material) else {
-                    continue;
-                };
-
-                render_pass.set_bind_group(0, &material.bind_group, &[]);
-                render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-                render_pass.set_vertex_buffer(1, batch.instance_buffer.slice(..));
-                render_pass
-                    .set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-                render_pass.draw_indexed(0..mesh.index_count, 0, 0..batch.instance_count);
-            }
+        None
+    }
+}
+
+/// Draws the ImGui frame on top of the output.
+struct ImguiPass<'a> {
+    imgui: &'a mut ImguiState,
+}
+
+impl ImguiPass<'_> {
+    const NAME: &'static str = "imgui";
+}
+
+impl RenderGraphPass for ImguiPass<'_> {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn setup(&self, builder: &mut PassBuilder) {
+        builder.read_write(ResourceId::OUTPUT);
+    }
+
+    fn execute(&mut self, pass: &mut PassContext<'_>) {
+        let mut render_pass = pass.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("ImGui Pass"),
+            multiview_mask: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: pass.texture(ResourceId::OUTPUT),
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        // Call new_frame before rendering
+        self.imgui
+            .renderer
+            .new_frame()
+            .expect("Failed to prepare new frame");
+
+        self.imgui
+            .renderer
+            .render_context(&mut self.imgui.context, &mut render_pass);
+    }
+}
+
+pub struct HeadlessRenderer {
+    pub target: OffscreenTarget,
+    pub resources: RenderResources,
+    pub graph: RenderGraph,
+    pub clear_color: wgpu::Color,
+}
 
-            // Call new_frame before rendering
-            self.imgui
-                .renderer
-                .new_frame()
-                .expect("Failed to prepare new frame");
+impl HeadlessRenderer {
+    pub fn new(ctx: &GpuContext, size: PhysicalSize<u32>) -> Self {
+        let target = OffscreenTarget::new(ctx, size, OffscreenTarget::DEFAULT_FORMAT);
+        let resources =
+            RenderResources::new(ctx, RenderResources::HDR_FORMAT, DepthSettings::default());
 
-            self.imgui
-                .renderer
-                .render_context(&mut self.imgui.context, &mut render_pass);
+        Self {
+            target,
+            resources,
+            graph: RenderGraph::with_default_passes(),
+            clear_color: wgpu::Color {
+                r: 0.1,
+                g: 0.2,
+                b: 0.3,
+                a: 1.0,
+            },
         }
+    }
+
+    pub fn material_layout(&self) -> &MaterialLayout {
+        &self.resources.material_layout
+    }
+
+    pub fn resize(&mut self, ctx: &GpuContext, size: PhysicalSize<u32>) {
+        self.target.resize(ctx, size);
+    }
+
+    /// Brings the scene's render batches up to date and culls them for this frame, like
+    /// [`crate::engine::Engine::render_window`] does for windows.
+    pub fn render(&mut self, ctx: &GpuContext, scene: &mut Scene) -> Result<()> {
+        let gpu_culled = self.resources.gpu_culling().is_some();
+        scene.prepare_render_batches(ctx, gpu_culled);
+        let scene = &*scene;
 
+        self.resources.update_camera(ctx, scene);
+        self.resources.update_lights(ctx, scene);
+        self.resources.update_culling(ctx, scene);
+
+        let target = FrameTarget {
+            view: &self.target.view,
+            format: self.target.format,
+            size: self.target.size,
+            clear_color: self.clear_color,
+        };
+        let result = self
+            .graph
+            .execute(ctx, scene, &self.resources, target, &mut []);
+        self.resources.finish_culling();
+        result
+    }
+
+    pub fn render_to_image(
+        &mut self,
+        ctx: &GpuContext,
+        scene: &mut Scene,
+    ) -> Result<image::RgbaImage> {
+        self.render(ctx, scene)?;
+        self.target.read_pixels(ctx)
+    }
+}
+
+pub struct OffscreenTarget {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub size: PhysicalSize<u32>,
+    pub format: wgpu::TextureFormat,
+}
+
+impl OffscreenTarget {
+    pub const DEFAULT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    pub fn new(ctx: &GpuContext, size: PhysicalSize<u32>, format: wgpu::TextureFormat) -> Self {
+        let size = PhysicalSize::new(size.width.max(1), size.height.max(1));
+        let texture = Self::create_color_texture(ctx, size, format);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self {
+            texture,
+            view,
+            size,
+            format,
+        }
+    }
+
+    fn create_color_texture(
+        ctx: &GpuContext,
+        size: PhysicalSize<u32>,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::Texture {
+        ctx.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offscreen_color_texture"),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        })
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.size.width as f32 / self.size.height as f32
+    }
+
+    pub fn resize(&mut self, ctx: &GpuContext, size: PhysicalSize<u32>) {
+        if size.width == 0 || size.height == 0 || size == self.size {
+            return;
+        }
+
+        *self = Self::new(ctx, size, self.format);
+    }
+
+    pub fn read_pixels(&self, ctx: &GpuContext) -> Result<image::RgbaImage> {
+        let bytes_per_pixel = self
+            .format
+            .block_copy_size(None)
+            .context("offscreen target format cannot be copied to a buffer")?;
+        anyhow::ensure!(
+            bytes_per_pixel == 4,
+            "reading back {:?} targets is not supported",
+            self.format
+        );
+
+        let unpadded_bytes_per_row = self.size.width * bytes_per_pixel;
+        let padded_bytes_per_row = unpadded_bytes_per_row
+            .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let readback_buffer = ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Offscreen Readback Buffer"),
+            size: (padded_bytes_per_row * self.size.height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = ctx
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Offscreen Readback Encoder"),
+            });
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                aspect: wgpu::TextureAspect::All,
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &readback_buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(self.size.height),
+                },
+            },
+            wgpu::Extent3d {
+                width: self.size.width,
+                height: self.size.height,
+                depth_or_array_layers: 1,
+            },
+        );
         ctx.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
 
-        None
+        let (sender, receiver) = std::sync::mpsc::channel();
+        readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        ctx.device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .context("failed to wait for offscreen readback")?;
+        receiver
+            .recv()
+            .context("offscreen readback was never mapped")?
+            .context("failed to map offscreen readback buffer")?;
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * self.size.height) as usize);
+        {
+            let mapped = readback_buffer.slice(..).get_mapped_range();
+            for row in mapped.chunks_exact(padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+            }
+        }
+        readback_buffer.unmap();
+
+        if matches!(
+            self.format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        image::RgbaImage::from_raw(self.size.width, self.size.height, pixels)
+            .context("offscreen readback has an unexpected size")
     }
 }
 
@@ -237,11 +462,16 @@ pub struct GpuContext {
     pub adapter: wgpu::Adapter,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
+    pub mipmaps: MipmapGenerator,
 }
 
 impl GpuContext {
-    pub async fn new(window: Arc<Window>) -> Result<(Self, Renderer<'static>)> {
-        let instance = Self::create_instance();
+    pub async fn new(window: Option<Arc<Window>>) -> Result<(Self, Option<Renderer<'static>>)> {
+        let Some(window) = window else {
+            return Ok((Self::headless().await?, None));
+        };
+
+        let instance = Self::create_instance(wgpu::Backends::PRIMARY);
         let surface = instance
             .create_surface(window.clone())
             .context("failed to create initial render surface")?;
@@ -255,15 +485,49 @@ impl GpuContext {
             .await
             .context("failed to find a compatible GPU adapter")?;
 
+        let required_limits = if cfg!(target_arch = "wasm32") {
+            wgpu::Limits::downlevel_webgl2_defaults()
+        } else {
+            wgpu::Limits::default()
+        };
+        let ctx = Self::from_adapter(instance, adapter, required_limits).await?;
+        let renderer = Renderer::from_surface(&ctx, surface, window.clone(), window.inner_size())?;
+
+        Ok((ctx, Some(renderer)))
+    }
+
+    pub async fn headless() -> Result<Self> {
+        let instance = Self::create_instance(wgpu::Backends::all());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter: true,
+            })
+            .await
+            .context("failed to find a fallback or noop GPU adapter")?;
+        debug!("headless adapter: {:?}", adapter.get_info());
+
+        let required_limits = adapter.limits();
+        Self::from_adapter(instance, adapter, required_limits).await
+    }
+
+    pub fn is_noop(&self) -> bool {
+        self.adapter.get_info().backend == wgpu::Backend::Noop
+    }
+
+    async fn from_adapter(
+        instance: wgpu::Instance,
+        adapter: wgpu::Adapter,
+        required_limits: wgpu::Limits,
+    ) -> Result<Self> {
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor {
                 label: None,
-                required_features: wgpu::Features::empty(),
-                required_limits: if cfg!(target_arch = "wasm32") {
-                    wgpu::Limits::downlevel_webgl2_defaults()
-                } else {
-                    wgpu::Limits::default()
-                },
+                // Lets MSAA use every sample count the adapter supports, not just 1 and 4.
+                required_features: adapter.features()
+                    & wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+                required_limits,
                 memory_hints: Default::default(),
                 trace: wgpu::Trace::Off,
                 experimental_features: ExperimentalFeatures::disabled(),
@@ -271,21 +535,21 @@ impl GpuContext {
             .await
             .context("failed to create logical GPU device")?;
 
-        let ctx = Self {
+        Ok(Self {
+            mipmaps: MipmapGenerator::new(&device),
             instance,
             adapter,
             device,
             queue,
-        };
-        let renderer = Renderer::from_surface(&ctx, surface, window.clone(), window.inner_size())?;
-
-        Ok((ctx, renderer))
+        })
     }
 
-    fn create_instance() -> wgpu::Instance {
+    fn create_instance(
+        #[cfg_attr(target_arch = "wasm32", allow(unused_variables))] backends: wgpu::Backends,
+    ) -> wgpu::Instance {
         wgpu::Instance::new(wgpu::InstanceDescriptor {
             #[cfg(not(target_arch = "wasm32"))]
-            backends: wgpu::Backends::PRIMARY,
+            backends,
             #[cfg(target_arch = "wasm32")]
             backends: wgpu::Backends::GL,
             display: None,
@@ -348,16 +612,95 @@ impl<'window> ImguiState {
     }
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DepthSettings {
+    /// The test for standard depth, where nearer is smaller.
+    pub compare: wgpu::CompareFunction,
+    pub write_enabled: bool,
+    /// Depth runs from 1 near to 0 far, see [`crate::camera::Projection::InfiniteReverseZ`].
+    /// Mirrors `compare` and clears to 0.
+    pub reverse_z: bool,
+}
+
+impl DepthSettings {
+    pub fn state(&self) -> wgpu::DepthStencilState {
+        wgpu::DepthStencilState {
+            format: crate::texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: Some(self.write_enabled),
+            depth_compare: Some(self.compare_function()),
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }
+    }
+
+    pub fn compare_function(&self) -> wgpu::CompareFunction {
+        use wgpu::CompareFunction::*;
+
+        match (self.reverse_z, self.compare) {
+            (true, Less) => Greater,
+            (true, LessEqual) => GreaterEqual,
+            (true, Greater) => Less,
+            (true, GreaterEqual) => LessEqual,
+            (_, compare) => compare,
+        }
+    }
+
+    pub fn attachment<'a>(
+        &self,
+        view: &'a wgpu::TextureView,
+    ) -> wgpu::RenderPassDepthStencilAttachment<'a> {
+        wgpu::RenderPassDepthStencilAttachment {
+            view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(if self.reverse_z { 0.0 } else { 1.0 }),
+                store: wgpu::StoreOp::Store,
+            }),
+            stencil_ops: None,
+        }
+    }
+}
+
+impl Default for DepthSettings {
+    fn default() -> Self {
+        Self {
+            compare: wgpu::CompareFunction::Less,
+            write_enabled: true,
+            reverse_z: false,
+        }
+    }
+}
+
 pub struct RenderResources {
+    /// Draws materials with [`Shading::Unlit`].
     pub render_pipeline: wgpu::RenderPipeline,
-    pub texture_bind_group_layout: wgpu::BindGroupLayout,
+    /// Draws materials with [`Shading::Lit`].
+    pub lit_pipeline: wgpu::RenderPipeline,
+    /// Draws materials with [`Shading::Pbr`].
+    pub pbr_pipeline: wgpu::RenderPipeline,
+    pub material_layout: MaterialLayout,
     pub camera_bind_group_layout: wgpu::BindGroupLayout,
     pub camera_buffer: wgpu::Buffer,
     pub camera_bind_group: wgpu::BindGroup,
+    pub lights_bind_group_layout: wgpu::BindGroupLayout,
+    pub lights_buffer: wgpu::Buffer,
+    pub lights_bind_group: wgpu::BindGroup,
+    pub shadows: ShadowMaps,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    target_format: wgpu::TextureFormat,
+    depth: DepthSettings,
+    msaa_samples: u32,
+    gpu_culling: Option<GpuCulling>,
 }
 
 impl RenderResources {
-    pub fn new(ctx: &GpuContext, target_format: wgpu::TextureFormat) -> Self {
+    /// The scene's color target, tonemapped into the output by
+    /// [`crate::tonemap::TonemapPass`].
+    pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    /// The tonemapped, sRGB-encoded color the post-processing effects run on.
+    pub const LDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    pub fn new(ctx: &GpuContext, target_format: wgpu::TextureFormat, depth: DepthSettings) -> Self {
         let texture_bind_group_layout =
             ctx.device
                 .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -378,9 +721,61 @@ impl RenderResources {
                             ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                             count: None,
                         },
+                        // Material factors and the remaining PBR maps, see `pbr::PbrMaterial`.
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 3,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 4,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 5,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 6,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            },
+                            count: None,
+                        },
                     ],
                     label: Some("texture_bind_group_layout"),
                 });
+        let material_layout = MaterialLayout::new(ctx, texture_bind_group_layout);
 
         let camera_uniform = CameraUniform::new();
         let camera_buffer = ctx
@@ -396,7 +791,7 @@ impl RenderResources {
                 .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                     entries: &[wgpu::BindGroupLayoutEntry {
                         binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
                         ty: wgpu::BindingType::Buffer {
                             ty: wgpu::BufferBindingType::Uniform,
                             has_dynamic_offset: false,
@@ -416,6 +811,41 @@ impl RenderResources {
             label: Some("camera_bind_group"),
         });
 
+        let lights_buffer = ctx
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Lights Buffer"),
+                contents: bytemuck::bytes_of(&LightsUniform::default()),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        let lights_bind_group_layout =
+            ctx.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    entries: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    }],
+                    label: Some("lights_bind_group_layout"),
+                });
+
+        let lights_bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &lights_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: lights_buffer.as_entire_binding(),
+            }],
+            label: Some("lights_bind_group"),
+        });
+
+        let shadows = ShadowMaps::new(ctx, ShadowConfig::default());
+
         let shader = ctx
             .device
             .create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -429,26 +859,194 @@ impl RenderResources {
                     label: Some("Render Pipeline Layout"),
                     immediate_size: 0,
                     bind_group_layouts: &[
-                        Some(&texture_bind_group_layout),
+                        Some(&material_layout.bind_group_layout),
                         Some(&camera_bind_group_layout),
+                        Some(&lights_bind_group_layout),
+                        Some(&shadows.bind_group_layout),
                     ],
                 });
 
-        let render_pipeline = ctx
+        let render_pipeline = Self::create_render_pipeline(
+            ctx,
+            &render_pipeline_layout,
+            &shader,
+            target_format,
+            depth,
+            1,
+            Shading::Unlit,
+        );
+        let lit_pipeline = Self::create_render_pipeline(
+            ctx,
+            &render_pipeline_layout,
+            &shader,
+            target_format,
+            depth,
+            1,
+            Shading::Lit,
+        );
+        let pbr_pipeline = Self::create_render_pipeline(
+            ctx,
+            &render_pipeline_layout,
+            &shader,
+            target_format,
+            depth,
+            1,
+            Shading::Pbr,
+        );
+
+        Self {
+            render_pipeline,
+            lit_pipeline,
+            pbr_pipeline,
+            material_layout,
+            camera_bind_group_layout,
+            camera_buffer,
+            camera_bind_group,
+            lights_bind_group_layout,
+            lights_buffer,
+            lights_bind_group,
+            shadows,
+            render_pipeline_layout,
+            shader,
+            target_format,
+            depth,
+            msaa_samples: 1,
+            gpu_culling: GpuCulling::is_supported(ctx).then(|| GpuCulling::new(ctx)),
+        }
+    }
+
+    pub fn depth(&self) -> DepthSettings {
+        self.depth
+    }
+
+    pub fn set_depth(&mut self, ctx: &GpuContext, depth: DepthSettings) {
+        if self.depth == depth {
+            return;
+        }
+
+        self.depth = depth;
+        self.recreate_pipelines(ctx);
+    }
+
+    /// Samples per pixel of the scene's color and depth targets; 1 disables MSAA.
+    pub fn msaa_samples(&self) -> u32 {
+        self.msaa_samples
+    }
+
+    /// The MSAA sample counts out of 1, 2, 4 and 8 that both the scene's color and depth
+    /// formats support on this device.
+    pub fn supported_msaa_samples(&self, ctx: &GpuContext) -> Vec<u32> {
+        let adapter_specific = ctx
             .device
+            .features()
+            .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+        let formats = [self.target_format, crate::texture::Texture::DEPTH_FORMAT];
+        let flags: Vec<_> = formats
+            .iter()
+            .map(|&format| {
+                if adapter_specific {
+                    ctx.adapter.get_texture_format_features(format).flags
+                } else {
+                    format
+                        .guaranteed_format_features(ctx.device.features())
+                        .flags
+                }
+            })
+            .collect();
+        [1, 2, 4, 8]
+            .iter()
+            .copied()
+            .filter(|&samples| flags.iter().all(|f| f.sample_count_supported(samples)))
+            .collect()
+    }
+
+    /// Switches the scene to `samples` per pixel. The render graph picks the new count up
+    /// on the next frame and recreates its multisampled targets.
+    pub fn set_msaa_samples(&mut self, ctx: &GpuContext, samples: u32) -> Result<()> {
+        if self.msaa_samples == samples {
+            return Ok(());
+        }
+        let supported = self.supported_msaa_samples(ctx);
+        if !supported.contains(&samples) {
+            bail!(
+                "{}x MSAA is not supported by this adapter, expected one of {:?}",
+                samples,
+                supported
+            );
+        }
+
+        self.msaa_samples = samples;
+        self.recreate_pipelines(ctx);
+        Ok(())
+    }
+
+    /// The compute culling path, present unless disabled or unsupported by the adapter.
+    pub fn gpu_culling(&self) -> Option<&GpuCulling> {
+        self.gpu_culling.as_ref()
+    }
+
+    /// Switches between culling on the GPU and drawing the batches' CPU-culled
+    /// [`crate::batch::RenderBatch::visible_buffer`]s.
+    pub fn set_gpu_culling(&mut self, ctx: &GpuContext, enabled: bool) -> Result<()> {
+        if enabled == self.gpu_culling.is_some() {
+            return Ok(());
+        }
+        if enabled && !GpuCulling::is_supported(ctx) {
+            bail!("this adapter cannot run compute shaders with indirect draws");
+        }
+
+        self.gpu_culling = enabled.then(|| GpuCulling::new(ctx));
+        Ok(())
+    }
+
+    fn recreate_pipelines(&mut self, ctx: &GpuContext) {
+        let create = |shading| {
+            Self::create_render_pipeline(
+                ctx,
+                &self.render_pipeline_layout,
+                &self.shader,
+                self.target_format,
+                self.depth,
+                self.msaa_samples,
+                shading,
+            )
+        };
+        let pipelines = (
+            create(Shading::Unlit),
+            create(Shading::Lit),
+            create(Shading::Pbr),
+        );
+        (self.render_pipeline, self.lit_pipeline, self.pbr_pipeline) = pipelines;
+    }
+
+    fn create_render_pipeline(
+        ctx: &GpuContext,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        target_format: wgpu::TextureFormat,
+        depth: DepthSettings,
+        msaa_samples: u32,
+        shading: Shading,
+    ) -> wgpu::RenderPipeline {
+        let (label, fragment_entry_point) = match shading {
+            Shading::Unlit => ("Render Pipeline", "fs_main"),
+            Shading::Lit => ("Lit Render Pipeline", "fs_lit"),
+            Shading::Pbr => ("PBR Render Pipeline", "fs_pbr"),
+        };
+        ctx.device
             .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("Render Pipeline"),
+                label: Some(label),
                 multiview_mask: None,
-                layout: Some(&render_pipeline_layout),
+                layout: Some(layout),
                 vertex: wgpu::VertexState {
-                    module: &shader,
+                    module: shader,
                     entry_point: Some("vs_main"),
                     buffers: &[Vertex::desc(), InstanceRaw::desc()],
                     compilation_options: Default::default(),
                 },
                 fragment: Some(wgpu::FragmentState {
-                    module: &shader,
-                    entry_point: Some("fs_main"),
+                    module: shader,
+                    entry_point: Some(fragment_entry_point),
                     targets: &[Some(wgpu::ColorTargetState {
                         format: target_format,
                         blend: Some(wgpu::BlendState {
@@ -468,28 +1066,128 @@ impl RenderResources {
                     unclipped_depth: false,
                     conservative: false,
                 },
-                // depth_stencil: Some(wgpu::DepthStencilState {
-                //     format: crate::texture::Texture::DEPTH_FORMAT,
-                //     depth_write_enabled: Some(true),
-                //     depth_compare: Some(wgpu::CompareFunction::Less),
-                //     stencil: wgpu::StencilState::default(),
-                //     bias: wgpu::DepthBiasState::default(),
-                // }),
-                depth_stencil: None,
+                depth_stencil: Some(depth.state()),
                 multisample: wgpu::MultisampleState {
-                    count: 1,
+                    count: msaa_samples,
                     mask: !0,
                     alpha_to_coverage_enabled: false,
                 },
                 cache: None,
-            });
+            })
+    }
+}
 
-        Self {
-            render_pipeline,
-            texture_bind_group_layout,
-            camera_bind_group_layout,
-            camera_buffer,
-            camera_bind_group,
+impl RenderResources {
+    /// Uploads the active camera and matches the depth test to its projection.
+    pub fn update_camera(&mut self, ctx: &GpuContext, scene: &Scene) {
+        let reverse_z = scene
+            .active_camera
+            .and_then(|id| scene.get::<CameraComponent>(id))
+            .is_some_and(|camera| camera.camera.projection.is_reverse_z());
+        if reverse_z != self.depth.reverse_z {
+            self.set_depth(
+                ctx,
+                DepthSettings {
+                    reverse_z,
+                    ..self.depth
+                },
+            );
+        }
+
+        if let Some(camera_uniform) = scene.active_camera_uniform() {
+            ctx.queue
+                .write_buffer(&self.camera_buffer, 0, bytemuck::bytes_of(&camera_uniform));
+        }
+    }
+
+    /// Prepares [`GpuCulling`] for the scene's render batches when it is enabled.
+    pub fn update_culling(&mut self, ctx: &GpuContext, scene: &Scene) {
+        if let Some(culling) = &mut self.gpu_culling {
+            culling.prepare(ctx, scene);
+        }
+    }
+
+    /// Starts reading back the GPU visible counts; call after the frame is submitted.
+    pub fn finish_culling(&mut self) {
+        if let Some(culling) = &mut self.gpu_culling {
+            culling.request_readback();
+        }
+    }
+
+    /// The instance counts of whichever path culls the scene: the GPU's read back counts,
+    /// or the scene's own from [`Scene::cull_render_batches`].
+    pub fn cull_stats(&self, scene: &Scene) -> CullStats {
+        match &self.gpu_culling {
+            Some(culling) => culling.stats(),
+            None => scene.cull_stats(),
+        }
+    }
+
+    /// Uploads the scene's lights and assigns shadow maps to the ones casting shadows.
+    pub fn update_lights(&mut self, ctx: &GpuContext, scene: &Scene) {
+        let mut lights = scene.lights_uniform();
+        self.shadows.prepare(ctx, scene, &mut lights);
+        ctx.queue
+            .write_buffer(&self.lights_buffer, 0, bytemuck::bytes_of(&lights));
+    }
+
+    pub fn set_shadow_config(&mut self, ctx: &GpuContext, config: ShadowConfig) -> Result<()> {
+        self.shadows.set_config(ctx, config)
+    }
+
+    /// Fills the shadow atlas; must be encoded before the pass calling `draw_scene`, which
+    /// [`crate::render_graph::ShadowPass`] takes care of.
+    pub fn draw_shadows(&self, encoder: &mut wgpu::CommandEncoder, scene: &Scene) {
+        self.shadows.draw(encoder, scene);
+    }
+
+    pub fn draw_scene(&self, render_pass: &mut wgpu::RenderPass<'_>, scene: &Scene) {
+        render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.lights_bind_group, &[]);
+        render_pass.set_bind_group(3, &self.shadows.bind_group, &[]);
+
+        let mut current_shading = None;
+
+        for (index, batch) in scene.render_batches.iter().enumerate() {
+            let culled = self
+                .gpu_culling
+                .as_ref()
+                .and_then(|culling| culling.batch(index));
+            let instance_count = match culled {
+                Some(_) => batch.instance_count,
+                None => batch.visible_count,
+            };
+            if instance_count == 0 {
+                continue;
+            }
+            let Some(mesh) = scene.mesh(batch.mesh) else {
+                continue;
+            };
+            let Some(material) = scene.material(batch.material) else {
+                continue;
+            };
+
+            if current_shading != Some(material.shading) {
+                render_pass.set_pipeline(match material.shading {
+                    Shading::Unlit => &self.render_pipeline,
+                    Shading::Lit => &self.lit_pipeline,
+                    Shading::Pbr => &self.pbr_pipeline,
+                });
+                current_shading = Some(material.shading);
+            }
+            render_pass.set_bind_group(0, &material.bind_group, &[]);
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
+            match culled {
+                Some((visible_buffer, indirect_buffer)) => {
+                    render_pass.set_vertex_buffer(1, visible_buffer.slice(..));
+                    render_pass.draw_indexed_indirect(indirect_buffer, 0);
+                }
+                None => {
+                    render_pass.set_vertex_buffer(1, batch.visible_buffer.slice(..));
+                    render_pass.draw_indexed(0..mesh.index_count, 0, 0..batch.visible_count);
+                }
+            }
         }
     }
 }
@@ -498,7 +1196,6 @@ pub struct RenderSurface<'window> {
     pub surface: wgpu::Surface<'window>,
     pub config: SurfaceConfiguration,
     pub size: PhysicalSize<u32>,
-    pub depth_texture: crate::texture::Texture,
     pub surface_format: wgpu::TextureFormat,
     pub is_configured: bool,
 }
@@ -539,13 +1236,10 @@ impl<'window> RenderSurface<'window> {
             desired_maximum_frame_latency: 2,
         };
 
-        let depth_texture =
-            crate::texture::Texture::create_depth_texture(&ctx.device, &config, "depth_texture");
         let mut render_surface = Self {
             surface,
             config,
             size,
-            depth_texture,
             surface_format,
             is_configured: false,
         };
@@ -575,11 +1269,6 @@ impl<'window> RenderSurface<'window> {
 
         self.config.width = size.width;
         self.config.height = size.height;
-        self.depth_texture = crate::texture::Texture::create_depth_texture(
-            &ctx.device,
-            &self.config,
-            "depth_texture",
-        );
         self.configure(ctx);
     }
 }
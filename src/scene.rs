@@ -1,15 +1,29 @@
-use std::collections::{BTreeMap, HashMap};
+use std::{
+    any::TypeId,
+    collections::{HashMap, HashSet},
+};
 
 use anyhow::{Context, Result};
 use cgmath::prelude::*;
+use serde::{Deserialize, Serialize};
 use wgpu::util::DeviceExt;
 
 use crate::{
+    batch::RenderBatches,
     camera::{Camera, CameraUniform},
+    component::{Components, Query},
+    culling::{Aabb, CullStats, Frustum},
+    pbr::{MaterialLayout, PbrMaterial},
     renderer::GpuContext,
-    texture::Texture,
+    shadow::ShadowFlags,
+    texture::{SamplerDescriptor, Texture},
+    tonemap::Tonemapping,
 };
 
+pub const PENTAGON_MESH_PATH: &str = "builtin://pentagon";
+pub const HAPPY_TREE_MATERIAL_PATH: &str = "builtin://happy-tree.png";
+pub(crate) const HAPPY_TREE_PNG: &[u8] = include_bytes!("happy-tree.png");
+
 const NUM_INSTANCES_PER_ROW: u32 = 10;
 const INSTANCE_DISPLACEMENT: cgmath::Vector3<f32> = cgmath::Vector3::new(
     NUM_INSTANCES_PER_ROW as f32 * 0.5,
@@ -64,6 +78,37 @@ impl TransformComponent {
         }
     }
 
+    pub fn from_matrix(matrix: cgmath::Matrix4<f32>) -> Self {
+        let translation = matrix.w.truncate();
+        let mut scale = cgmath::Vector3::new(
+            matrix.x.truncate().magnitude(),
+            matrix.y.truncate().magnitude(),
+            matrix.z.truncate().magnitude(),
+        );
+        if matrix.determinant() < 0.0 {
+            scale.x = -scale.x;
+        }
+
+        let safe = |value: f32| {
+            if value.abs() > f32::EPSILON {
+                value
+            } else {
+                1.0
+            }
+        };
+        let rotation = cgmath::Matrix3::from_cols(
+            matrix.x.truncate() / safe(scale.x),
+            matrix.y.truncate() / safe(scale.y),
+            matrix.z.truncate() / safe(scale.z),
+        );
+
+        Self {
+            translation,
+            rotation: cgmath::Quaternion::from(rotation).normalize(),
+            scale,
+        }
+    }
+
     pub fn matrix(&self) -> cgmath::Matrix4<f32> {
         cgmath::Matrix4::from_translation(self.translation)
             * cgmath::Matrix4::from(self.rotation)
@@ -86,11 +131,28 @@ pub struct MeshRendererComponent {
 #[derive(Clone, Copy, Debug)]
 pub struct CameraComponent {
     pub camera: Camera,
+    /// Exposure compensation in stops; each step doubles the scene's brightness.
+    pub exposure: f32,
+    pub tonemapping: Tonemapping,
 }
 
 impl CameraComponent {
     pub fn new(camera: Camera) -> Self {
-        Self { camera }
+        Self {
+            camera,
+            exposure: 0.0,
+            tonemapping: Tonemapping::default(),
+        }
+    }
+
+    pub fn with_exposure(mut self, exposure: f32) -> Self {
+        self.exposure = exposure;
+        self
+    }
+
+    pub fn with_tonemapping(mut self, tonemapping: Tonemapping) -> Self {
+        self.tonemapping = tonemapping;
+        self
     }
 }
 
@@ -99,6 +161,9 @@ impl CameraComponent {
 pub struct Vertex {
     pub position: [f32; 3],
     pub tex_coords: [f32; 2],
+    pub normal: [f32; 3],
+    /// Tangent in `xyz` with the bitangent sign in `w`.
+    pub tangent: [f32; 4],
 }
 
 impl Vertex {
@@ -118,6 +183,16 @@ impl Vertex {
                     shader_location: 1,
                     format: wgpu::VertexFormat::Float32x2,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ],
         }
     }
@@ -127,15 +202,44 @@ impl Vertex {
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct InstanceRaw {
     pub model: [[f32; 4]; 4],
+    /// Inverse transpose of the model matrix's upper 3x3, for transforming normals.
+    pub normal: [[f32; 3]; 3],
+    /// `ShadowFlags` bits, see [`ShadowFlags::bits`].
+    pub flags: u32,
 }
 
 impl InstanceRaw {
     pub fn from_transform(transform: &TransformComponent) -> Self {
+        Self::from_matrix(transform.matrix())
+    }
+
+    pub fn from_matrix(matrix: cgmath::Matrix4<f32>) -> Self {
+        let linear = cgmath::Matrix3::from_cols(
+            matrix.x.truncate(),
+            matrix.y.truncate(),
+            matrix.z.truncate(),
+        );
+        let normal = linear
+            .invert()
+            .map(|inverse| inverse.transpose())
+            .unwrap_or(linear);
+
         Self {
-            model: transform.matrix().into(),
+            model: matrix.into(),
+            normal: normal.into(),
+            flags: ShadowFlags::default().bits(),
         }
     }
 
+    pub fn with_shadow_flags(mut self, flags: ShadowFlags) -> Self {
+        self.flags = flags.bits();
+        self
+    }
+
+    pub fn shadow_flags(&self) -> ShadowFlags {
+        ShadowFlags::from_bits(self.flags)
+    }
+
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
         use std::mem;
         wgpu::VertexBufferLayout {
@@ -162,19 +266,126 @@ impl InstanceRaw {
                     shader_location: 8,
                     format: wgpu::VertexFormat::Float32x4,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
+                    shader_location: 9,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 19]>() as wgpu::BufferAddress,
+                    shader_location: 10,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 22]>() as wgpu::BufferAddress,
+                    shader_location: 11,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 25]>() as wgpu::BufferAddress,
+                    shader_location: 12,
+                    format: wgpu::VertexFormat::Uint32,
+                },
             ],
         }
     }
 }
 
+/// An integer type usable as mesh index data.
+pub trait MeshIndex: bytemuck::Pod + Into<u32> {
+    const FORMAT: wgpu::IndexFormat;
+
+    fn collision_indices(indices: &[Self]) -> CollisionIndices;
+}
+
+impl MeshIndex for u16 {
+    const FORMAT: wgpu::IndexFormat = wgpu::IndexFormat::Uint16;
+
+    fn collision_indices(indices: &[Self]) -> CollisionIndices {
+        CollisionIndices::U16(indices.to_vec())
+    }
+}
+
+impl MeshIndex for u32 {
+    const FORMAT: wgpu::IndexFormat = wgpu::IndexFormat::Uint32;
+
+    fn collision_indices(indices: &[Self]) -> CollisionIndices {
+        CollisionIndices::U32(indices.to_vec())
+    }
+}
+
+/// [`CollisionData`] indices, in the width the mesh was uploaded with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CollisionIndices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl CollisionIndices {
+    pub fn len(&self) -> usize {
+        match self {
+            CollisionIndices::U16(indices) => indices.len(),
+            CollisionIndices::U32(indices) => indices.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<u32> {
+        match self {
+            CollisionIndices::U16(indices) => indices.get(index).map(|&index| index.into()),
+            CollisionIndices::U32(indices) => indices.get(index).copied(),
+        }
+    }
+}
+
+/// CPU copies of a mesh's vertex positions and indices for exact raycasts, see
+/// [`Mesh::with_collision_data`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct CollisionData {
+    pub positions: Vec<[f32; 3]>,
+    pub indices: CollisionIndices,
+}
+
+impl CollisionData {
+    pub fn new<I: MeshIndex>(vertices: &[Vertex], indices: &[I]) -> Self {
+        Self {
+            positions: vertices.iter().map(|vertex| vertex.position).collect(),
+            indices: I::collision_indices(indices),
+        }
+    }
+
+    /// The corners of each whole triangle.
+    pub fn triangles(&self) -> impl Iterator<Item = [cgmath::Point3<f32>; 3]> + '_ {
+        let corner = move |index| {
+            let vertex = self.indices.get(index).unwrap() as usize;
+            cgmath::Point3::from(self.positions[vertex])
+        };
+        (0..self.indices.len() / 3)
+            .map(move |triangle| [0, 1, 2].map(|offset| corner(triangle * 3 + offset)))
+    }
+}
+
 pub struct Mesh {
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub index_count: u32,
+    pub index_format: wgpu::IndexFormat,
+    /// Local-space bounds of the vertices, used for culling and picking.
+    pub bounds: Aabb,
+    /// Only kept for meshes made with [`Mesh::with_collision_data`].
+    pub collision: Option<CollisionData>,
 }
 
 impl Mesh {
-    pub fn new(ctx: &GpuContext, label: &str, vertices: &[Vertex], indices: &[u16]) -> Self {
+    pub fn new<I: MeshIndex>(
+        ctx: &GpuContext,
+        label: &str,
+        vertices: &[Vertex],
+        indices: &[I],
+    ) -> Self {
         let vertex_buffer = ctx
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -194,64 +405,198 @@ impl Mesh {
             vertex_buffer,
             index_buffer,
             index_count: indices.len() as u32,
+            index_format: I::FORMAT,
+            bounds: Aabb::from_points(vertices.iter().map(|vertex| vertex.position.into())),
+            collision: None,
         }
     }
+
+    /// Also keeps [`CollisionData`], which [`crate::picking::RaycastMode::Triangles`] tests
+    /// against. Other meshes only keep their bounds on the CPU.
+    pub fn with_collision_data<I: MeshIndex>(
+        ctx: &GpuContext,
+        label: &str,
+        vertices: &[Vertex],
+        indices: &[I],
+    ) -> Self {
+        Self {
+            collision: Some(CollisionData::new(vertices, indices)),
+            ..Self::new(ctx, label, vertices, indices)
+        }
+    }
+
+    /// Stores `indices` as 16-bit when every vertex is addressable with it, otherwise as
+    /// 32-bit.
+    pub fn with_smallest_indices(
+        ctx: &GpuContext,
+        label: &str,
+        vertices: &[Vertex],
+        indices: &[u32],
+    ) -> Self {
+        match Self::smallest_index_format(vertices.len()) {
+            wgpu::IndexFormat::Uint16 => {
+                let indices: Vec<u16> = indices.iter().map(|&index| index as u16).collect();
+                Self::new(ctx, label, vertices, &indices)
+            }
+            wgpu::IndexFormat::Uint32 => Self::new(ctx, label, vertices, indices),
+        }
+    }
+
+    pub fn smallest_index_format(vertex_count: usize) -> wgpu::IndexFormat {
+        if vertex_count <= u16::MAX as usize + 1 {
+            wgpu::IndexFormat::Uint16
+        } else {
+            wgpu::IndexFormat::Uint32
+        }
+    }
+
+    pub fn pentagon(ctx: &GpuContext) -> Self {
+        Self::new(ctx, "Pentagon", PENTAGON_VERTICES, PENTAGON_INDICES)
+    }
+}
+
+/// Selects the render pipeline a material is drawn with.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Shading {
+    /// The texture color as-is, ignoring lights.
+    #[default]
+    Unlit,
+    /// Blinn-Phong shading from the scene's lights.
+    Lit,
+    /// The glTF metallic-roughness BRDF, see [`crate::pbr::PbrMaterial`].
+    Pbr,
 }
 
 pub struct Material {
     #[allow(dead_code)]
     pub texture: Texture,
     pub bind_group: wgpu::BindGroup,
+    pub shading: Shading,
+    /// How every texture of the material is sampled.
+    pub sampler: SamplerDescriptor,
 }
 
 impl Material {
     pub fn from_texture_bytes(
         ctx: &GpuContext,
-        texture_bind_group_layout: &wgpu::BindGroupLayout,
+        material_layout: &MaterialLayout,
         bytes: &[u8],
         label: &str,
     ) -> Result<Self> {
-        let texture = Texture::from_bytes(&ctx.device, &ctx.queue, bytes, label)?;
-        let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
-                },
+        let texture = Texture::from_bytes(ctx, bytes, label)?;
+        Self::from_texture(ctx, material_layout, texture, label)
+    }
+
+    /// A 1x1 material of a single linear RGBA color.
+    pub fn from_color(
+        ctx: &GpuContext,
+        material_layout: &MaterialLayout,
+        color: [f32; 4],
+        label: &str,
+    ) -> Result<Self> {
+        let [r, g, b, a] = color;
+        let texture = Texture::solid(
+            ctx,
+            [
+                linear_to_srgb8(r),
+                linear_to_srgb8(g),
+                linear_to_srgb8(b),
+                (a.clamp(0.0, 1.0) * 255.0).round() as u8,
             ],
-            label: Some(label),
-        });
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            label,
+        )?;
+        Self::from_texture(ctx, material_layout, texture, label)
+    }
 
-        Ok(Self {
+    /// An unlit material of `texture`. Switched to `Shading::Pbr` it is a rough dielectric.
+    pub fn from_texture(
+        ctx: &GpuContext,
+        material_layout: &MaterialLayout,
+        texture: Texture,
+        label: &str,
+    ) -> Result<Self> {
+        Self::from_texture_with_sampler(
+            ctx,
+            material_layout,
             texture,
-            bind_group,
-        })
+            SamplerDescriptor::default(),
+            label,
+        )
+    }
+
+    /// Like `from_texture`, sampling with `sampler`, e.g. [`SamplerDescriptor::pixel_art`].
+    pub fn from_texture_with_sampler(
+        ctx: &GpuContext,
+        material_layout: &MaterialLayout,
+        texture: Texture,
+        sampler: SamplerDescriptor,
+        label: &str,
+    ) -> Result<Self> {
+        let material = PbrMaterial {
+            base_color_texture: Some(texture),
+            sampler,
+            ..PbrMaterial::plain()
+        };
+        Ok(Self::pbr(ctx, material_layout, material, label)?.with_shading(Shading::Unlit))
+    }
+
+    pub fn with_shading(mut self, shading: Shading) -> Self {
+        self.shading = shading;
+        self
     }
 }
 
-pub struct RenderBatch {
-    pub mesh: MeshHandle,
-    pub material: MaterialHandle,
-    pub instance_buffer: wgpu::Buffer,
-    pub instance_count: u32,
+fn linear_to_srgb8(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let srgb = if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (srgb * 255.0).round() as u8
+}
+
+#[derive(Default)]
+struct ChangeTracker {
+    all_transforms: bool,
+    transforms: HashSet<EntityId>,
+    all_moved: bool,
+    moved: HashSet<EntityId>,
+    renderers: HashSet<EntityId>,
+    rebuild: bool,
+}
+
+impl ChangeTracker {
+    fn transforms_stale(&self) -> bool {
+        self.all_transforms || !self.transforms.is_empty()
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.transforms_stale()
+            && !self.all_moved
+            && self.moved.is_empty()
+            && self.renderers.is_empty()
+            && !self.rebuild
+    }
 }
 
 #[derive(Default)]
 pub struct Scene {
     next_entity_id: u32,
     pub entities: HashMap<EntityId, Entity>,
-    pub transforms: HashMap<EntityId, TransformComponent>,
-    pub mesh_renderers: HashMap<EntityId, MeshRendererComponent>,
-    pub cameras: HashMap<EntityId, CameraComponent>,
+    components: Components,
     pub active_camera: Option<EntityId>,
+    /// Linear RGB added to every lit surface.
+    pub ambient_light: [f32; 3],
     pub meshes: Vec<Mesh>,
     pub materials: Vec<Material>,
-    pub render_batches: Vec<RenderBatch>,
+    mesh_paths: HashMap<MeshHandle, String>,
+    material_paths: HashMap<MaterialHandle, String>,
+    pub render_batches: RenderBatches,
+    world_transforms: HashMap<EntityId, cgmath::Matrix4<f32>>,
+    changes: ChangeTracker,
+    gpu_culled: bool,
 }
 
 impl Scene {
@@ -261,22 +606,15 @@ impl Scene {
 
     pub fn default_instanced(
         ctx: &GpuContext,
-        texture_bind_group_layout: &wgpu::BindGroupLayout,
+        material_layout: &MaterialLayout,
         aspect: f32,
     ) -> Result<Self> {
         let mut scene = Self::new();
-        let mesh = scene.add_mesh(Mesh::new(
-            ctx,
-            "Pentagon",
-            PENTAGON_VERTICES,
-            PENTAGON_INDICES,
-        ));
-        let material = scene.add_material(Material::from_texture_bytes(
-            ctx,
-            texture_bind_group_layout,
-            include_bytes!("happy-tree.png"),
-            "happy-tree.png",
-        )?);
+        let mesh = scene.add_mesh_asset(PENTAGON_MESH_PATH, Mesh::pentagon(ctx));
+        let material = scene.add_material_asset(
+            HAPPY_TREE_MATERIAL_PATH,
+            Material::from_texture_bytes(ctx, material_layout, HAPPY_TREE_PNG, "happy-tree.png")?,
+        );
 
         let camera = Camera::new((0.0, 5.0, 10.0).into(), (0.0, 0.0, 0.0).into(), aspect);
         let camera_entity = scene.spawn(Some("Main Camera".to_owned()), None);
@@ -330,19 +668,257 @@ impl Scene {
             }
         }
 
+        self.changes.transforms.insert(id);
         id
     }
 
+    /// With `keep_world_transform` the local transform is rewritten so the entity stays put;
+    /// shear introduced by non-uniformly scaled parents cannot be kept in a TRS transform.
+    pub fn set_parent(
+        &mut self,
+        entity: EntityId,
+        parent: Option<EntityId>,
+        keep_world_transform: bool,
+    ) -> Result<()> {
+        let current_parent = self
+            .entities
+            .get(&entity)
+            .with_context(|| format!("entity {entity:?} does not exist"))?
+            .parent;
+
+        let mut ancestor = parent;
+        while let Some(id) = ancestor {
+            anyhow::ensure!(
+                id != entity,
+                "cannot parent {entity:?} to its own descendant {parent:?}"
+            );
+            ancestor = self
+                .entities
+                .get(&id)
+                .with_context(|| format!("parent entity {id:?} does not exist"))?
+                .parent;
+        }
+
+        let world = keep_world_transform
+            .then(|| self.world_transform(entity))
+            .flatten();
+
+        if let Some(old_parent) = current_parent.and_then(|id| self.entities.get_mut(&id)) {
+            old_parent.children.retain(|child| *child != entity);
+        }
+        if let Some(new_parent) = parent.and_then(|id| self.entities.get_mut(&id)) {
+            new_parent.children.push(entity);
+        }
+        if let Some(entity) = self.entities.get_mut(&entity) {
+            entity.parent = parent;
+        }
+
+        if let Some(world) = world {
+            let parent_world = parent
+                .and_then(|id| self.world_transform(id))
+                .unwrap_or_else(cgmath::Matrix4::identity);
+            let local = parent_world
+                .invert()
+                .context("new parent has a non-invertible world transform")?
+                * world;
+            self.insert(entity, TransformComponent::from_matrix(local));
+        }
+
+        self.changes.transforms.insert(entity);
+        Ok(())
+    }
+
+    pub fn despawn(&mut self, entity: EntityId) -> bool {
+        let Some(root) = self.entities.get(&entity) else {
+            return false;
+        };
+
+        if let Some(parent_id) = root.parent {
+            if let Some(parent_entity) = self.entities.get_mut(&parent_id) {
+                parent_entity.children.retain(|child| *child != entity);
+            }
+        }
+
+        let mut pending = vec![entity];
+        while let Some(id) = pending.pop() {
+            let Some(removed) = self.entities.remove(&id) else {
+                continue;
+            };
+            pending.extend(removed.children);
+
+            self.components.remove_entity(id);
+            self.world_transforms.remove(&id);
+            self.changes.renderers.insert(id);
+            if self.active_camera == Some(id) {
+                self.active_camera = None;
+            }
+        }
+
+        true
+    }
+
+    pub fn insert<T: 'static>(&mut self, entity: EntityId, component: T) -> Option<T> {
+        self.mark_changed::<T>(entity, true);
+        self.components.insert(entity, component)
+    }
+
+    pub fn get<T: 'static>(&self, entity: EntityId) -> Option<&T> {
+        self.components.get(entity)
+    }
+
+    pub fn get_mut<T: 'static>(&mut self, entity: EntityId) -> Option<&mut T> {
+        self.mark_changed::<T>(entity, false);
+        self.components.get_mut(entity)
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: EntityId) -> Option<T> {
+        let removed = self.components.remove::<T>(entity)?;
+        self.mark_changed::<T>(entity, true);
+        if TypeId::of::<T>() == TypeId::of::<CameraComponent>()
+            && self.active_camera == Some(entity)
+        {
+            self.active_camera = None;
+        }
+        Some(removed)
+    }
+
+    pub fn has<T: 'static>(&self, entity: EntityId) -> bool {
+        self.components.contains::<T>(entity)
+    }
+
+    pub fn iter<T: 'static>(&self) -> impl Iterator<Item = (EntityId, &T)> {
+        self.components.iter()
+    }
+
+    pub fn iter_mut<T: 'static>(&mut self) -> impl Iterator<Item = (EntityId, &mut T)> {
+        self.mark_all_changed::<T>();
+        self.components.iter_mut()
+    }
+
+    pub fn query<Q: Query>(&self) -> impl Iterator<Item = (EntityId, Q::Item<'_>)> {
+        self.components.query::<Q>()
+    }
+
+    // Mutable access to built-in render components invalidates the derived render state.
+    // Adding or removing a transform can also change whether the entity is drawn at all.
+    fn mark_changed<T: 'static>(&mut self, entity: EntityId, membership: bool) {
+        let type_id = TypeId::of::<T>();
+        if type_id == TypeId::of::<TransformComponent>() {
+            self.changes.transforms.insert(entity);
+            if membership {
+                self.changes.renderers.insert(entity);
+            }
+        } else if type_id == TypeId::of::<MeshRendererComponent>()
+            || type_id == TypeId::of::<ShadowFlags>()
+        {
+            self.changes.renderers.insert(entity);
+        }
+    }
+
+    fn mark_all_changed<T: 'static>(&mut self) {
+        let type_id = TypeId::of::<T>();
+        if type_id == TypeId::of::<TransformComponent>() {
+            self.changes.all_transforms = true;
+        } else if type_id == TypeId::of::<MeshRendererComponent>()
+            || type_id == TypeId::of::<ShadowFlags>()
+        {
+            self.changes.rebuild = true;
+        }
+    }
+
     pub fn set_transform(&mut self, entity: EntityId, transform: TransformComponent) {
-        self.transforms.insert(entity, transform);
+        self.insert(entity, transform);
+    }
+
+    pub fn remove_transform(&mut self, entity: EntityId) -> Option<TransformComponent> {
+        self.remove(entity)
+    }
+
+    /// Returns the cached world matrix when transforms have been propagated, otherwise walks
+    /// the parent chain. Entities without a `TransformComponent` inherit their parent's matrix.
+    pub fn world_transform(&self, entity: EntityId) -> Option<cgmath::Matrix4<f32>> {
+        if !self.changes.transforms_stale() {
+            return self.world_transforms.get(&entity).copied();
+        }
+
+        self.compute_world_transform(entity)
+    }
+
+    fn compute_world_transform(&self, entity: EntityId) -> Option<cgmath::Matrix4<f32>> {
+        let mut world = cgmath::Matrix4::identity();
+        let mut current = Some(self.entities.get(&entity)?);
+        while let Some(node) = current {
+            if let Some(transform) = self.get::<TransformComponent>(node.id) {
+                world = transform.matrix() * world;
+            }
+            current = node.parent.and_then(|parent| self.entities.get(&parent));
+        }
+
+        Some(world)
+    }
+
+    /// Recomputes world matrices for the subtrees under changed entities, or for the whole
+    /// hierarchy after bulk transform edits.
+    pub fn propagate_transforms(&mut self) {
+        let mut pending: Vec<(EntityId, cgmath::Matrix4<f32>)> = if self.changes.all_transforms {
+            self.world_transforms.clear();
+            self.changes.all_moved = true;
+            self.entities
+                .values()
+                .filter(|entity| entity.parent.is_none())
+                .map(|entity| (entity.id, cgmath::Matrix4::identity()))
+                .collect()
+        } else {
+            self.changes
+                .transforms
+                .iter()
+                .filter_map(|id| {
+                    let parent_world = match self.entities.get(id)?.parent {
+                        Some(parent) => self.compute_world_transform(parent)?,
+                        None => cgmath::Matrix4::identity(),
+                    };
+                    Some((*id, parent_world))
+                })
+                .collect()
+        };
+        self.changes.all_transforms = false;
+        self.changes.transforms.clear();
+
+        while let Some((id, parent_world)) = pending.pop() {
+            let Some(entity) = self.entities.get(&id) else {
+                continue;
+            };
+
+            let world = match self.get::<TransformComponent>(id) {
+                Some(transform) => parent_world * transform.matrix(),
+                None => parent_world,
+            };
+            self.world_transforms.insert(id, world);
+            if !self.changes.all_moved {
+                self.changes.moved.insert(id);
+            }
+            pending.extend(entity.children.iter().map(|child| (*child, world)));
+        }
     }
 
     pub fn add_mesh_renderer(&mut self, entity: EntityId, component: MeshRendererComponent) {
-        self.mesh_renderers.insert(entity, component);
+        self.insert(entity, component);
+    }
+
+    pub fn remove_mesh_renderer(&mut self, entity: EntityId) -> Option<MeshRendererComponent> {
+        self.remove(entity)
     }
 
     pub fn add_camera(&mut self, entity: EntityId, component: CameraComponent) {
-        self.cameras.insert(entity, component);
+        self.insert(entity, component);
+    }
+
+    pub fn remove_camera(&mut self, entity: EntityId) -> Option<CameraComponent> {
+        self.remove(entity)
+    }
+
+    pub fn render_batches_stale(&self) -> bool {
+        !self.changes.is_empty()
     }
 
     pub fn add_mesh(&mut self, mesh: Mesh) -> MeshHandle {
@@ -357,6 +933,42 @@ impl Scene {
         handle
     }
 
+    pub fn add_mesh_asset(&mut self, path: impl Into<String>, mesh: Mesh) -> MeshHandle {
+        let handle = self.add_mesh(mesh);
+        self.mesh_paths.insert(handle, path.into());
+        handle
+    }
+
+    pub fn add_material_asset(
+        &mut self,
+        path: impl Into<String>,
+        material: Material,
+    ) -> MaterialHandle {
+        let handle = self.add_material(material);
+        self.material_paths.insert(handle, path.into());
+        handle
+    }
+
+    pub fn mesh_path(&self, handle: MeshHandle) -> Option<&str> {
+        self.mesh_paths.get(&handle).map(String::as_str)
+    }
+
+    pub fn material_path(&self, handle: MaterialHandle) -> Option<&str> {
+        self.material_paths.get(&handle).map(String::as_str)
+    }
+
+    pub fn find_mesh(&self, path: &str) -> Option<MeshHandle> {
+        self.mesh_paths
+            .iter()
+            .find_map(|(handle, mesh_path)| (mesh_path == path).then_some(*handle))
+    }
+
+    pub fn find_material(&self, path: &str) -> Option<MaterialHandle> {
+        self.material_paths
+            .iter()
+            .find_map(|(handle, material_path)| (material_path == path).then_some(*handle))
+    }
+
     pub fn mesh(&self, handle: MeshHandle) -> Option<&Mesh> {
         self.meshes.get(handle.0)
     }
@@ -367,90 +979,198 @@ impl Scene {
 
     pub fn active_camera_uniform(&self) -> Option<CameraUniform> {
         let camera_id = self.active_camera?;
-        let camera = self.cameras.get(&camera_id)?;
+        let camera = self.get::<CameraComponent>(camera_id)?;
         Some(CameraUniform::from_camera(&camera.camera))
     }
 
     pub fn set_active_camera_aspect(&mut self, aspect: f32) {
         if let Some(camera_id) = self.active_camera {
-            if let Some(camera) = self.cameras.get_mut(&camera_id) {
+            if let Some(camera) = self.components.get_mut::<CameraComponent>(camera_id) {
                 camera.camera.set_aspect(aspect);
             }
         }
     }
 
     pub fn rebuild_render_batches(&mut self, ctx: &GpuContext) {
-        let mut grouped_instances: BTreeMap<(MeshHandle, MaterialHandle), Vec<InstanceRaw>> =
-            BTreeMap::new();
+        self.changes = ChangeTracker {
+            all_transforms: true,
+            ..ChangeTracker::default()
+        };
+        self.propagate_transforms();
+
+        self.render_batches.clear();
+        // Sorted so slots and batches come out the same on every rebuild.
+        let mut renderers: Vec<_> = self
+            .components
+            .query::<(MeshRendererComponent, TransformComponent)>()
+            .collect();
+        renderers.sort_unstable_by_key(|(entity, _)| *entity);
+        for (entity, (renderer, _)) in renderers {
+            if let Some(world) = self.world_transforms.get(&entity) {
+                self.render_batches.insert(
+                    ctx,
+                    entity,
+                    (renderer.mesh, renderer.material),
+                    instance_raw(&self.components, entity, *world),
+                );
+            }
+        }
+
+        self.render_batches.flush(ctx);
+        self.changes = ChangeTracker::default();
+        if !self.gpu_culled {
+            self.cull_render_batches(ctx);
+        }
+    }
+
+    /// Applies transform and mesh renderer changes since the last update, rewriting only the
+    /// instance slots that changed.
+    pub fn update_render_batches(&mut self, ctx: &GpuContext) {
+        if self.changes.rebuild {
+            self.rebuild_render_batches(ctx);
+            return;
+        }
+
+        self.propagate_transforms();
+
+        let mut renderers: Vec<_> = self.changes.renderers.drain().collect();
+        renderers.sort_unstable();
+        for entity in renderers {
+            self.render_batches.remove(entity);
 
-        for (entity, renderer) in &self.mesh_renderers {
-            let Some(transform) = self.transforms.get(entity) else {
+            let Some(renderer) = self.components.get::<MeshRendererComponent>(entity) else {
                 continue;
             };
-
-            grouped_instances
-                .entry((renderer.mesh, renderer.material))
-                .or_default()
-                .push(InstanceRaw::from_transform(transform));
+            if !self.components.contains::<TransformComponent>(entity) {
+                continue;
+            }
+            if let Some(world) = self.world_transforms.get(&entity) {
+                self.render_batches.insert(
+                    ctx,
+                    entity,
+                    (renderer.mesh, renderer.material),
+                    instance_raw(&self.components, entity, *world),
+                );
+            }
         }
 
-        self.render_batches = grouped_instances
-            .into_iter()
-            .filter_map(|((mesh, material), instances)| {
-                if instances.is_empty() {
-                    return None;
+        if self.changes.all_moved {
+            for (entity, world) in &self.world_transforms {
+                self.render_batches
+                    .update(*entity, instance_raw(&self.components, *entity, *world));
+            }
+        } else {
+            for entity in &self.changes.moved {
+                if let Some(world) = self.world_transforms.get(entity) {
+                    self.render_batches
+                        .update(*entity, instance_raw(&self.components, *entity, *world));
                 }
+            }
+        }
 
-                let instance_buffer =
-                    ctx.device
-                        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                            label: Some("Instance Buffer"),
-                            contents: bytemuck::cast_slice(&instances),
-                            usage: wgpu::BufferUsages::VERTEX,
-                        });
-
-                Some(RenderBatch {
-                    mesh,
-                    material,
-                    instance_buffer,
-                    instance_count: instances.len() as u32,
-                })
-            })
-            .collect();
+        self.render_batches.flush(ctx);
+        self.changes = ChangeTracker::default();
+        if !self.gpu_culled {
+            self.cull_render_batches(ctx);
+        }
+    }
+
+    /// Writes the instances inside the active camera's frustum into each batch's visible
+    /// buffer. Unless the camera moved since the last call, only batches whose instances
+    /// changed are culled again; without an active camera nothing is culled.
+    pub fn cull_render_batches(&mut self, ctx: &GpuContext) {
+        let frustum = self
+            .active_camera
+            .and_then(|camera| self.get::<CameraComponent>(camera))
+            .map(|camera| Frustum::from_camera(&camera.camera));
+        let meshes = &self.meshes;
+        self.render_batches.cull(ctx, frustum, |mesh| {
+            meshes.get(mesh.0).map(|mesh| mesh.bounds)
+        });
+    }
+
+    /// Visible and culled instance counts from the last [`Scene::cull_render_batches`].
+    /// Renderers culling on the GPU leave these untouched; see
+    /// [`crate::renderer::RenderResources::cull_stats`].
+    pub fn cull_stats(&self) -> CullStats {
+        self.render_batches.cull_stats()
+    }
+
+    pub fn gpu_culled(&self) -> bool {
+        self.gpu_culled
+    }
+
+    /// Whether the scene is drawn by renderers that cull on the GPU, so updating the render
+    /// batches skips the CPU cull. [`Scene::cull_render_batches`] still culls on request.
+    pub fn set_gpu_culled(&mut self, gpu_culled: bool) {
+        self.gpu_culled = gpu_culled;
+    }
+
+    /// Applies pending changes to the render batches before a frame and, unless the renderer
+    /// culls on the GPU, culls them against the active camera.
+    pub fn prepare_render_batches(&mut self, ctx: &GpuContext, gpu_culled: bool) {
+        self.set_gpu_culled(gpu_culled);
+        if self.render_batches_stale() {
+            self.update_render_batches(ctx);
+        }
+        if !gpu_culled {
+            self.cull_render_batches(ctx);
+        }
     }
 
     pub fn validate(&self) -> Result<()> {
         self.active_camera
             .context("scene has no active camera")
             .and_then(|camera_id| {
-                self.cameras
-                    .contains_key(&camera_id)
+                self.has::<CameraComponent>(camera_id)
                     .then_some(())
                     .context("active camera entity has no CameraComponent")
             })
     }
 }
 
+fn instance_raw(
+    components: &Components,
+    entity: EntityId,
+    world: cgmath::Matrix4<f32>,
+) -> InstanceRaw {
+    let flags = components
+        .get::<ShadowFlags>(entity)
+        .copied()
+        .unwrap_or_default();
+    InstanceRaw::from_matrix(world).with_shadow_flags(flags)
+}
+
 const PENTAGON_VERTICES: &[Vertex] = &[
     Vertex {
         position: [-0.0868241, 0.49240386, 0.0],
         tex_coords: [0.4131759, 0.00759614],
+        normal: [0.0, 0.0, 1.0],
+        tangent: [1.0, 0.0, 0.0, 1.0],
     },
     Vertex {
         position: [-0.49513406, 0.06958647, 0.0],
         tex_coords: [0.0048659444, 0.43041354],
+        normal: [0.0, 0.0, 1.0],
+        tangent: [1.0, 0.0, 0.0, 1.0],
     },
     Vertex {
         position: [-0.21918549, -0.44939706, 0.0],
         tex_coords: [0.28081453, 0.949397],
+        normal: [0.0, 0.0, 1.0],
+        tangent: [1.0, 0.0, 0.0, 1.0],
     },
     Vertex {
         position: [0.35966998, -0.3473291, 0.0],
         tex_coords: [0.85967, 0.84732914],
+        normal: [0.0, 0.0, 1.0],
+        tangent: [1.0, 0.0, 0.0, 1.0],
     },
     Vertex {
         position: [0.44147372, 0.2347359, 0.0],
         tex_coords: [0.9414737, 0.2652641],
+        normal: [0.0, 0.0, 1.0],
+        tangent: [1.0, 0.0, 0.0, 1.0],
     },
 ];
 
@@ -0,0 +1,591 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::{Camera, Projection},
+    gltf_import::{GltfAsset, GltfDocument},
+    light::LightComponent,
+    obj_import::{ObjAsset, ObjDocument},
+    pbr::MaterialLayout,
+    primitives::Primitive,
+    renderer::GpuContext,
+    scene::{
+        CameraComponent, EntityId, Material, Mesh, MeshRendererComponent, Scene, Shading,
+        TransformComponent, HAPPY_TREE_MATERIAL_PATH, HAPPY_TREE_PNG, PENTAGON_MESH_PATH,
+    },
+    shadow::ShadowFlags,
+    texture::{SamplerDescriptor, Texture},
+    tonemap::Tonemapping,
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SceneDescription {
+    #[serde(default)]
+    pub entities: Vec<EntityDescription>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_camera: Option<u32>,
+    #[serde(default, skip_serializing_if = "is_black")]
+    pub ambient_light: [f32; 3],
+}
+
+fn is_black(color: &[f32; 3]) -> bool {
+    *color == [0.0; 3]
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EntityDescription {
+    pub id: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<TransformDescription>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh_renderer: Option<MeshRendererDescription>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera: Option<CameraDescription>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub light: Option<LightComponent>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shadows: Option<ShadowFlags>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransformDescription {
+    pub translation: [f32; 3],
+    /// Quaternion as `[x, y, z, w]`.
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+impl Default for TransformDescription {
+    fn default() -> Self {
+        Self::from(&TransformComponent::identity())
+    }
+}
+
+impl From<&TransformComponent> for TransformDescription {
+    fn from(transform: &TransformComponent) -> Self {
+        let rotation = transform.rotation;
+        Self {
+            translation: transform.translation.into(),
+            rotation: [rotation.v.x, rotation.v.y, rotation.v.z, rotation.s],
+            scale: transform.scale.into(),
+        }
+    }
+}
+
+impl From<&TransformDescription> for TransformComponent {
+    fn from(description: &TransformDescription) -> Self {
+        let [x, y, z, w] = description.rotation;
+        Self {
+            translation: description.translation.into(),
+            rotation: cgmath::Quaternion::new(w, x, y, z),
+            scale: description.scale.into(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MeshRendererDescription {
+    pub mesh: String,
+    pub material: String,
+    /// Replaces the shading the material asset loads with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shading: Option<Shading>,
+    /// Replaces the default sampler of a plain texture material.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampler: Option<SamplerDescriptor>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredCameraDescription")]
+pub struct CameraDescription {
+    pub eye: [f32; 3],
+    pub target: [f32; 3],
+    pub up: [f32; 3],
+    pub projection: Projection,
+    pub znear: f32,
+    pub zfar: f32,
+    #[serde(default)]
+    pub exposure: f32,
+    #[serde(default)]
+    pub tonemapping: Tonemapping,
+}
+
+/// [`CameraDescription`] as read from a file. Scenes saved before cameras had a
+/// [`Projection`] store a perspective `fovy` instead.
+#[derive(Deserialize)]
+struct StoredCameraDescription {
+    eye: [f32; 3],
+    target: [f32; 3],
+    up: [f32; 3],
+    #[serde(default, deserialize_with = "present")]
+    projection: Option<Projection>,
+    #[serde(default, deserialize_with = "present")]
+    fovy: Option<f32>,
+    znear: f32,
+    zfar: f32,
+    #[serde(default)]
+    exposure: f32,
+    #[serde(default)]
+    tonemapping: Tonemapping,
+}
+
+/// Reads a field that is either missing, through `#[serde(default)]`, or a bare value,
+/// which RON would otherwise expect wrapped in `Some`.
+fn present<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+impl From<StoredCameraDescription> for CameraDescription {
+    fn from(stored: StoredCameraDescription) -> Self {
+        let legacy = stored.fovy.map(|fovy| Projection::Perspective { fovy });
+        Self {
+            eye: stored.eye,
+            target: stored.target,
+            up: stored.up,
+            projection: stored.projection.or(legacy).unwrap_or_default(),
+            znear: stored.znear,
+            zfar: stored.zfar,
+            exposure: stored.exposure,
+            tonemapping: stored.tonemapping,
+        }
+    }
+}
+
+impl From<&CameraComponent> for CameraDescription {
+    fn from(component: &CameraComponent) -> Self {
+        let camera = &component.camera;
+        Self {
+            eye: camera.eye.into(),
+            target: camera.target.into(),
+            up: camera.up.into(),
+            projection: camera.projection,
+            znear: camera.znear,
+            zfar: camera.zfar,
+            exposure: component.exposure,
+            tonemapping: component.tonemapping,
+        }
+    }
+}
+
+impl CameraDescription {
+    pub fn to_component(&self, aspect: f32) -> CameraComponent {
+        CameraComponent::new(self.to_camera(aspect))
+            .with_exposure(self.exposure)
+            .with_tonemapping(self.tonemapping)
+    }
+
+    pub fn to_camera(&self, aspect: f32) -> Camera {
+        Camera {
+            eye: self.eye.into(),
+            target: self.target.into(),
+            up: self.up.into(),
+            aspect,
+            projection: self.projection,
+            znear: self.znear,
+            zfar: self.zfar,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SceneFormat {
+    Ron,
+    Json,
+}
+
+impl SceneFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => Self::Json,
+            _ => Self::Ron,
+        }
+    }
+}
+
+impl SceneDescription {
+    pub fn to_string(&self, format: SceneFormat) -> Result<String> {
+        Ok(match format {
+            SceneFormat::Ron => {
+                ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?
+            }
+            SceneFormat::Json => serde_json::to_string_pretty(self)?,
+        })
+    }
+
+    pub fn from_str(source: &str, format: SceneFormat) -> Result<Self> {
+        Ok(match format {
+            SceneFormat::Ron => ron::from_str(source)?,
+            SceneFormat::Json => serde_json::from_str(source)?,
+        })
+    }
+
+    /// Replaces the file part of every asset path that points into an imported file.
+    fn map_imported_files(&mut self, mut map: impl FnMut(&str) -> Result<String>) -> Result<()> {
+        let renderers = self
+            .entities
+            .iter_mut()
+            .filter_map(|entity| entity.mesh_renderer.as_mut());
+        for renderer in renderers {
+            for path in [&mut renderer.mesh, &mut renderer.material] {
+                if let Some((file, asset)) = GltfAsset::parse(path) {
+                    *path = asset.path(&map(file)?);
+                } else if let Some((file, asset)) = ObjAsset::parse(path) {
+                    *path = asset.path(&map(file)?);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The directory a scene file is in, which its imported file paths are relative to.
+fn scene_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// `path` relative to the directory `base`, with `/` separators so the scene file loads on
+/// every platform. Paths on another drive stay absolute.
+fn relative_path(path: &Path, base: &Path) -> Result<String> {
+    let path = std::fs::canonicalize(path)
+        .with_context(|| format!("failed to find {}", path.display()))?;
+    let base = std::fs::canonicalize(base)
+        .with_context(|| format!("failed to find {}", base.display()))?;
+    let common = path
+        .components()
+        .zip(base.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        return Ok(path.to_string_lossy().into_owned());
+    }
+
+    let parents = base.components().count() - common;
+    let parts: Vec<_> = std::iter::repeat_n("..".into(), parents)
+        .chain(
+            path.components()
+                .skip(common)
+                .map(|component| component.as_os_str().to_string_lossy()),
+        )
+        .collect();
+    Ok(parts.join("/"))
+}
+
+/// Resolves the asset paths stored in scene files into GPU resources.
+pub trait AssetSource {
+    fn load_mesh(&mut self, ctx: &GpuContext, path: &str) -> Result<Mesh>;
+
+    /// Loads the material at `path`. `sampler` replaces the default sampler of plain texture
+    /// files; glTF and OBJ materials keep the samplers their files describe.
+    fn load_material(
+        &mut self,
+        ctx: &GpuContext,
+        material_layout: &MaterialLayout,
+        path: &str,
+        sampler: Option<SamplerDescriptor>,
+    ) -> Result<Material>;
+}
+
+/// Loads `builtin://` assets from the engine and everything else relative to `root`.
+/// glTF and OBJ files are parsed once and kept around while their meshes and materials load.
+pub struct FileAssets {
+    pub root: PathBuf,
+    gltf_documents: HashMap<String, GltfDocument>,
+    obj_documents: HashMap<String, ObjDocument>,
+}
+
+impl FileAssets {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            gltf_documents: HashMap::new(),
+            obj_documents: HashMap::new(),
+        }
+    }
+
+    pub fn resolve(&self, path: &str) -> PathBuf {
+        self.root.join(path)
+    }
+
+    fn gltf_document(&mut self, file: &str) -> Result<&GltfDocument> {
+        if !self.gltf_documents.contains_key(file) {
+            let path = self.resolve(file);
+            let bytes = std::fs::read(&path)
+                .with_context(|| format!("failed to read glTF file {}", path.display()))?;
+            let document = GltfDocument::from_slice(&bytes, file, path.parent())?;
+            self.gltf_documents.insert(file.to_owned(), document);
+        }
+        Ok(&self.gltf_documents[file])
+    }
+
+    fn obj_document(&mut self, file: &str) -> Result<&ObjDocument> {
+        if !self.obj_documents.contains_key(file) {
+            let document = ObjDocument::open(self.resolve(file))?.with_source(file);
+            self.obj_documents.insert(file.to_owned(), document);
+        }
+        Ok(&self.obj_documents[file])
+    }
+}
+
+impl AssetSource for FileAssets {
+    fn load_mesh(&mut self, ctx: &GpuContext, path: &str) -> Result<Mesh> {
+        if path == PENTAGON_MESH_PATH {
+            return Ok(Mesh::pentagon(ctx));
+        }
+        if let Some(primitive) = Primitive::from_builtin_path(path) {
+            return Ok(primitive?.to_mesh(ctx));
+        }
+        if let Some((file, GltfAsset::Primitive { mesh, primitive })) = GltfAsset::parse(path) {
+            return self
+                .gltf_document(file)?
+                .load_primitive(ctx, mesh, primitive);
+        }
+        match ObjAsset::parse(path) {
+            Some((file, ObjAsset::Model(model))) => self.obj_document(file)?.load_model(ctx, model),
+            _ => bail!("unsupported mesh asset `{path}`"),
+        }
+    }
+
+    fn load_material(
+        &mut self,
+        ctx: &GpuContext,
+        material_layout: &MaterialLayout,
+        path: &str,
+        sampler: Option<SamplerDescriptor>,
+    ) -> Result<Material> {
+        if let Some((file, GltfAsset::Material(material))) = GltfAsset::parse(path) {
+            return self
+                .gltf_document(file)?
+                .load_material(ctx, material_layout, material);
+        }
+
+        if let Some((file, ObjAsset::Material(material))) = ObjAsset::parse(path) {
+            return self
+                .obj_document(file)?
+                .load_material(ctx, material_layout, material);
+        }
+
+        let bytes = match path {
+            HAPPY_TREE_MATERIAL_PATH => HAPPY_TREE_PNG.to_vec(),
+            _ => std::fs::read(self.resolve(path))
+                .with_context(|| format!("failed to read material texture `{path}`"))?,
+        };
+        let texture = Texture::from_bytes(ctx, &bytes, path)?;
+        Material::from_texture_with_sampler(
+            ctx,
+            material_layout,
+            texture,
+            sampler.unwrap_or_default(),
+            path,
+        )
+    }
+}
+
+impl Scene {
+    pub fn load(
+        ctx: &GpuContext,
+        material_layout: &MaterialLayout,
+        path: impl AsRef<Path>,
+        aspect: f32,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read scene {}", path.display()))?;
+        let mut description = SceneDescription::from_str(&source, SceneFormat::from_path(path))
+            .with_context(|| format!("failed to parse scene {}", path.display()))?;
+        let dir = scene_dir(path);
+        description.map_imported_files(|file| {
+            Ok(std::path::absolute(dir.join(file))?
+                .to_string_lossy()
+                .into_owned())
+        })?;
+        let mut assets = FileAssets::new(dir);
+
+        Self::from_description(ctx, material_layout, &description, &mut assets, aspect)
+    }
+
+    /// Imported files are opened from the paths they were imported with, and saved relative
+    /// to the scene file so it still loads from another directory.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let mut description = self.to_description()?;
+        let dir = scene_dir(path);
+        description.map_imported_files(|file| relative_path(Path::new(file), dir))?;
+        let source = description.to_string(SceneFormat::from_path(path))?;
+        std::fs::write(path, source)
+            .with_context(|| format!("failed to write scene {}", path.display()))
+    }
+
+    /// Entities are written parents-first with ids renumbered from zero, so saving the same
+    /// scene twice produces the same file.
+    pub fn to_description(&self) -> Result<SceneDescription> {
+        let mut roots: Vec<EntityId> = self
+            .entities
+            .values()
+            .filter(|entity| entity.parent.is_none())
+            .map(|entity| entity.id)
+            .collect();
+        roots.sort_unstable();
+
+        let mut order = Vec::with_capacity(self.entities.len());
+        let mut pending: Vec<EntityId> = roots.into_iter().rev().collect();
+        while let Some(id) = pending.pop() {
+            let Some(entity) = self.entities.get(&id) else {
+                continue;
+            };
+            order.push(id);
+            pending.extend(entity.children.iter().rev());
+        }
+
+        let ids: HashMap<EntityId, u32> = order
+            .iter()
+            .enumerate()
+            .map(|(index, id)| (*id, index as u32))
+            .collect();
+
+        let mut entities = Vec::with_capacity(order.len());
+        for id in &order {
+            let entity = &self.entities[id];
+            let mesh_renderer = match self.get::<MeshRendererComponent>(*id) {
+                Some(renderer) => {
+                    let material = self.material(renderer.material);
+                    Some(MeshRendererDescription {
+                        mesh: self
+                            .mesh_path(renderer.mesh)
+                            .with_context(|| format!("{:?} has no asset path", renderer.mesh))?
+                            .to_owned(),
+                        material: self
+                            .material_path(renderer.material)
+                            .with_context(|| format!("{:?} has no asset path", renderer.material))?
+                            .to_owned(),
+                        shading: material.map(|material| material.shading),
+                        sampler: material
+                            .map(|material| material.sampler)
+                            .filter(|sampler| *sampler != SamplerDescriptor::default()),
+                    })
+                }
+                None => None,
+            };
+
+            entities.push(EntityDescription {
+                id: ids[id],
+                name: entity.name.clone(),
+                parent: entity.parent.and_then(|parent| ids.get(&parent).copied()),
+                transform: self
+                    .get::<TransformComponent>(*id)
+                    .map(TransformDescription::from),
+                mesh_renderer,
+                camera: self
+                    .get::<CameraComponent>(*id)
+                    .map(CameraDescription::from),
+                light: self.get::<LightComponent>(*id).copied(),
+                shadows: self.get::<ShadowFlags>(*id).copied(),
+            });
+        }
+
+        Ok(SceneDescription {
+            entities,
+            active_camera: self.active_camera.and_then(|id| ids.get(&id).copied()),
+            ambient_light: self.ambient_light,
+        })
+    }
+
+    pub fn from_description(
+        ctx: &GpuContext,
+        material_layout: &MaterialLayout,
+        description: &SceneDescription,
+        assets: &mut dyn AssetSource,
+        aspect: f32,
+    ) -> Result<Self> {
+        let mut scene = Self::new();
+        let mut ids = HashMap::with_capacity(description.entities.len());
+        for entity in &description.entities {
+            let id = scene.spawn(entity.name.clone(), None);
+            ensure!(
+                ids.insert(entity.id, id).is_none(),
+                "duplicate entity id {} in scene description",
+                entity.id
+            );
+        }
+
+        for entity in &description.entities {
+            let id = ids[&entity.id];
+            if let Some(parent) = entity.parent {
+                let parent = *ids
+                    .get(&parent)
+                    .with_context(|| format!("entity {} has unknown parent {parent}", entity.id))?;
+                scene.set_parent(id, Some(parent), false)?;
+            }
+
+            if let Some(transform) = &entity.transform {
+                scene.set_transform(id, TransformComponent::from(transform));
+            }
+
+            if let Some(renderer) = &entity.mesh_renderer {
+                let mesh = match scene.find_mesh(&renderer.mesh) {
+                    Some(mesh) => mesh,
+                    None => {
+                        let mesh = assets.load_mesh(ctx, &renderer.mesh)?;
+                        scene.add_mesh_asset(renderer.mesh.clone(), mesh)
+                    }
+                };
+                let material = match scene.find_material(&renderer.material) {
+                    Some(material) => material,
+                    None => {
+                        let mut material = assets.load_material(
+                            ctx,
+                            material_layout,
+                            &renderer.material,
+                            renderer.sampler,
+                        )?;
+                        if let Some(shading) = renderer.shading {
+                            material = material.with_shading(shading);
+                        }
+                        scene.add_material_asset(renderer.material.clone(), material)
+                    }
+                };
+                scene.add_mesh_renderer(id, MeshRendererComponent { mesh, material });
+            }
+
+            if let Some(camera) = &entity.camera {
+                scene.add_camera(id, camera.to_component(aspect));
+            }
+
+            if let Some(light) = entity.light {
+                scene.add_light(id, light);
+            }
+
+            if let Some(shadows) = entity.shadows {
+                scene.set_shadow_flags(id, shadows);
+            }
+        }
+
+        scene.active_camera = match description.active_camera {
+            Some(camera) => Some(
+                *ids.get(&camera)
+                    .with_context(|| format!("active camera {camera} is not an entity"))?,
+            ),
+            None => None,
+        };
+
+        scene.ambient_light = description.ambient_light;
+        scene.rebuild_render_batches(ctx);
+        Ok(scene)
+    }
+}
@@ -1,7 +1,10 @@
 // Vertex shader
 
+const SHADOW_RECEIVE: u32 = 2u;
+
 struct Camera {
     view_proj: mat4x4<f32>,
+    view_position: vec4<f32>,
 }
 @group(1) @binding(0)
 var<uniform> camera: Camera;
@@ -9,17 +12,27 @@ var<uniform> camera: Camera;
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec4<f32>,
 }
 struct InstanceInput {
     @location(5) model_matrix_0: vec4<f32>,
     @location(6) model_matrix_1: vec4<f32>,
     @location(7) model_matrix_2: vec4<f32>,
     @location(8) model_matrix_3: vec4<f32>,
+    @location(9) normal_matrix_0: vec3<f32>,
+    @location(10) normal_matrix_1: vec3<f32>,
+    @location(11) normal_matrix_2: vec3<f32>,
+    @location(12) flags: u32,
 }
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    @location(1) world_position: vec3<f32>,
+    @location(2) world_normal: vec3<f32>,
+    @location(3) world_tangent: vec4<f32>,
+    @location(4) shadow_receiver: f32,
 }
 
 @vertex
@@ -33,9 +46,19 @@ fn vs_main(
         instance.model_matrix_2,
         instance.model_matrix_3,
     );
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
-    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    out.world_position = world_position.xyz;
+    out.world_normal = normal_matrix * model.normal;
+    out.world_tangent = vec4<f32>((model_matrix * vec4<f32>(model.tangent.xyz, 0.0)).xyz, model.tangent.w);
+    out.shadow_receiver = select(0.0, 1.0, (instance.flags & SHADOW_RECEIVE) != 0u);
+    out.clip_position = camera.view_proj * world_position;
     return out;
 }
 
@@ -46,7 +69,294 @@ var t_diffuse: texture_2d<f32>;
 @group(0)@binding(1)
 var s_diffuse: sampler;
 
+struct Material {
+    base_color_factor: vec4<f32>,
+    emissive_factor: vec4<f32>,
+    // x: metallic, y: roughness, z: normal scale, w: occlusion strength
+    metallic_roughness_normal_occlusion: vec4<f32>,
+}
+@group(0) @binding(2)
+var<uniform> material: Material;
+@group(0) @binding(3)
+var t_metallic_roughness: texture_2d<f32>;
+@group(0) @binding(4)
+var t_normal: texture_2d<f32>;
+@group(0) @binding(5)
+var t_occlusion: texture_2d<f32>;
+@group(0) @binding(6)
+var t_emissive: texture_2d<f32>;
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     return textureSample(t_diffuse, s_diffuse, in.tex_coords);
-}
\ No newline at end of file
+}
+
+// Lit fragment shader
+
+const LIGHT_DIRECTIONAL: f32 = 0.0;
+const LIGHT_POINT: f32 = 1.0;
+const LIGHT_SPOT: f32 = 2.0;
+const MAX_LIGHTS: u32 = 16u;
+
+const SHININESS: f32 = 32.0;
+const SPECULAR_STRENGTH: f32 = 0.5;
+
+struct Light {
+    // w: kind
+    position: vec4<f32>,
+    // w: range, zero for no falloff
+    direction: vec4<f32>,
+    // w: intensity
+    color: vec4<f32>,
+    // x: cos(inner angle), y: cos(outer angle)
+    cone: vec2<f32>,
+    // First atlas tile, negative without shadows
+    shadow_tile: i32,
+    shadow_count: u32,
+}
+struct Lights {
+    ambient: vec4<f32>,
+    count: vec4<u32>,
+    lights: array<Light, MAX_LIGHTS>,
+}
+@group(2) @binding(0)
+var<uniform> lights: Lights;
+
+const MAX_SHADOW_TILES: u32 = 16u;
+
+struct ShadowTile {
+    view_proj: mat4x4<f32>,
+    // xy: offset, zw: size, in atlas UV space
+    atlas_rect: vec4<f32>,
+}
+struct Shadows {
+    tiles: array<ShadowTile, MAX_SHADOW_TILES>,
+    // View distance at which each directional cascade ends.
+    cascade_splits: vec4<f32>,
+    view_forward: vec4<f32>,
+    // x: normal bias, y: atlas texel size
+    params: vec4<f32>,
+}
+@group(3) @binding(0)
+var<uniform> shadows: Shadows;
+@group(3) @binding(1)
+var t_shadow: texture_depth_2d;
+@group(3) @binding(2)
+var s_shadow: sampler_comparison;
+
+// Windowed inverse-square falloff that reaches zero at `range`.
+fn distance_attenuation(distance: f32, range: f32) -> f32 {
+    let inverse_square = 1.0 / max(distance * distance, 1e-4);
+    if range <= 0.0 {
+        return inverse_square;
+    }
+    let ratio = distance / range;
+    let window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+    return window * window * inverse_square;
+}
+
+// Direction towards the light in `xyz` and the attenuated intensity in `w`.
+fn light_incidence(light: Light, world_position: vec3<f32>) -> vec4<f32> {
+    if light.position.w == LIGHT_DIRECTIONAL {
+        return vec4<f32>(-light.direction.xyz, light.color.w);
+    }
+    let to_light = light.position.xyz - world_position;
+    let distance = length(to_light);
+    let light_dir = to_light / max(distance, 1e-4);
+    var attenuation = distance_attenuation(distance, light.direction.w);
+    if light.position.w == LIGHT_SPOT {
+        let cos_angle = dot(-light_dir, light.direction.xyz);
+        attenuation *= smoothstep(light.cone.y, light.cone.x, cos_angle);
+    }
+    return vec4<f32>(light_dir, light.color.w * attenuation);
+}
+
+// The atlas tile covering `world_position` for this light, or -1 when there is none.
+fn shadow_tile(light: Light, world_position: vec3<f32>) -> i32 {
+    if light.shadow_tile < 0 {
+        return -1;
+    }
+    let first = light.shadow_tile;
+    let count = i32(light.shadow_count);
+    if light.position.w == LIGHT_DIRECTIONAL {
+        let depth = dot(world_position - camera.view_position.xyz, shadows.view_forward.xyz);
+        for (var cascade = 0; cascade < count; cascade += 1) {
+            if depth <= shadows.cascade_splits[cascade] {
+                return first + cascade;
+            }
+        }
+        return -1;
+    }
+    if light.position.w == LIGHT_POINT {
+        // Cube faces are stored as +X, -X, +Y, -Y, +Z, -Z.
+        let to_surface = world_position - light.position.xyz;
+        let axis = abs(to_surface);
+        if axis.x >= axis.y && axis.x >= axis.z {
+            return first + select(1, 0, to_surface.x > 0.0);
+        }
+        if axis.y >= axis.z {
+            return first + select(3, 2, to_surface.y > 0.0);
+        }
+        return first + select(5, 4, to_surface.z > 0.0);
+    }
+    return first;
+}
+
+// Fraction of the light reaching `world_position`, filtered with a 3x3 PCF kernel.
+fn shadow_visibility(
+    light: Light,
+    world_position: vec3<f32>,
+    normal: vec3<f32>,
+    light_dir: vec3<f32>,
+) -> f32 {
+    let tile_index = shadow_tile(light, world_position);
+    if tile_index < 0 {
+        return 1.0;
+    }
+    let tile = shadows.tiles[tile_index];
+    let n_dot_l = clamp(dot(normal, light_dir), 0.0, 1.0);
+    let offset = normal * shadows.params.x * (1.0 - n_dot_l);
+    let clip = tile.view_proj * vec4<f32>(world_position + offset, 1.0);
+    if clip.w <= 0.0 {
+        return 1.0;
+    }
+    let ndc = clip.xyz / clip.w;
+    if any(abs(ndc.xy) > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    let texel = shadows.params.y;
+    let uv = tile.atlas_rect.xy + (ndc.xy * vec2<f32>(0.5, -0.5) + 0.5) * tile.atlas_rect.zw;
+    // Stay inside the tile so the kernel never reads a neighbouring shadow map.
+    let min_uv = tile.atlas_rect.xy + vec2<f32>(texel);
+    let max_uv = tile.atlas_rect.xy + tile.atlas_rect.zw - vec2<f32>(texel);
+    var visibility = 0.0;
+    for (var y = -1; y <= 1; y += 1) {
+        for (var x = -1; x <= 1; x += 1) {
+            let sample_uv = clamp(uv + vec2<f32>(f32(x), f32(y)) * texel, min_uv, max_uv);
+            visibility += textureSampleCompareLevel(t_shadow, s_shadow, sample_uv, ndc.z);
+        }
+    }
+    return visibility / 9.0;
+}
+
+fn light_shadow(
+    light: Light,
+    in: VertexOutput,
+    normal: vec3<f32>,
+    light_dir: vec3<f32>,
+) -> f32 {
+    if in.shadow_receiver < 0.5 {
+        return 1.0;
+    }
+    return shadow_visibility(light, in.world_position, normal, light_dir);
+}
+
+@fragment
+fn fs_lit(in: VertexOutput) -> @location(0) vec4<f32> {
+    let albedo = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let normal = normalize(in.world_normal);
+    let view_dir = normalize(camera.view_position.xyz - in.world_position);
+
+    var color = lights.ambient.rgb * albedo.rgb;
+    for (var i = 0u; i < min(lights.count.x, MAX_LIGHTS); i += 1u) {
+        let light = lights.lights[i];
+        let incidence = light_incidence(light, in.world_position);
+        let light_dir = incidence.xyz;
+
+        let n_dot_l = max(dot(normal, light_dir), 0.0);
+        let half_dir = normalize(light_dir + view_dir);
+        let specular = select(
+            0.0,
+            pow(max(dot(normal, half_dir), 0.0), SHININESS) * SPECULAR_STRENGTH,
+            n_dot_l > 0.0,
+        );
+        let shadow = light_shadow(light, in, normal, light_dir);
+        let radiance = light.color.rgb * incidence.w * shadow;
+        color += (albedo.rgb * n_dot_l + vec3<f32>(specular)) * radiance;
+    }
+    return vec4<f32>(color, albedo.a);
+}
+
+// PBR fragment shader, following the glTF 2.0 metallic-roughness BRDF (specification
+// appendix B).
+
+const PI: f32 = 3.14159265359;
+
+fn fresnel_schlick(f0: vec3<f32>, v_dot_h: f32) -> vec3<f32> {
+    return f0 + (vec3<f32>(1.0) - f0) * pow(clamp(1.0 - v_dot_h, 0.0, 1.0), 5.0);
+}
+
+// Trowbridge-Reitz (GGX) normal distribution.
+fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+    let alpha_sq = alpha * alpha;
+    let f = n_dot_h * n_dot_h * (alpha_sq - 1.0) + 1.0;
+    return alpha_sq / (PI * f * f);
+}
+
+// Height-correlated Smith-GGX visibility, the geometry term divided by 4 N.L N.V.
+fn visibility_smith_ggx(n_dot_l: f32, n_dot_v: f32, alpha: f32) -> f32 {
+    let alpha_sq = alpha * alpha;
+    let ggx_v = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - alpha_sq) + alpha_sq);
+    let ggx_l = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - alpha_sq) + alpha_sq);
+    let ggx = ggx_v + ggx_l;
+    return select(0.0, 0.5 / ggx, ggx > 0.0);
+}
+
+// Applies the normal map in the frame of the interpolated normal and tangent.
+fn perturbed_normal(in: VertexOutput, sampled: vec3<f32>) -> vec3<f32> {
+    let normal = normalize(in.world_normal);
+    let tangent = in.world_tangent.xyz - normal * dot(normal, in.world_tangent.xyz);
+    if dot(tangent, tangent) < 1e-8 {
+        return normal;
+    }
+    let t = normalize(tangent);
+    let b = cross(normal, t) * in.world_tangent.w;
+    let scaled = vec3<f32>(sampled.xy * material.metallic_roughness_normal_occlusion.z, sampled.z);
+    return normalize(mat3x3<f32>(t, b, normal) * scaled);
+}
+
+@fragment
+fn fs_pbr(in: VertexOutput) -> @location(0) vec4<f32> {
+    let factors = material.metallic_roughness_normal_occlusion;
+    let base_color = textureSample(t_diffuse, s_diffuse, in.tex_coords) * material.base_color_factor;
+    let metallic_roughness = textureSample(t_metallic_roughness, s_diffuse, in.tex_coords);
+    let metallic = clamp(factors.x * metallic_roughness.b, 0.0, 1.0);
+    let roughness = clamp(factors.y * metallic_roughness.g, 0.04, 1.0);
+    let alpha = roughness * roughness;
+    let occlusion = mix(1.0, textureSample(t_occlusion, s_diffuse, in.tex_coords).r, factors.w);
+    let emissive = textureSample(t_emissive, s_diffuse, in.tex_coords).rgb * material.emissive_factor.rgb;
+
+    let c_diff = mix(base_color.rgb, vec3<f32>(0.0), metallic);
+    let f0 = mix(vec3<f32>(0.04), base_color.rgb, metallic);
+
+    let normal_sample = textureSample(t_normal, s_diffuse, in.tex_coords).xyz * 2.0 - 1.0;
+    let normal = perturbed_normal(in, normal_sample);
+    let geometric_normal = normalize(in.world_normal);
+    let view_dir = normalize(camera.view_position.xyz - in.world_position);
+    let n_dot_v = max(dot(normal, view_dir), 1e-4);
+
+    var color = lights.ambient.rgb * c_diff * occlusion;
+    for (var i = 0u; i < min(lights.count.x, MAX_LIGHTS); i += 1u) {
+        let light = lights.lights[i];
+        let incidence = light_incidence(light, in.world_position);
+        let light_dir = incidence.xyz;
+        let n_dot_l = dot(normal, light_dir);
+        if n_dot_l <= 0.0 {
+            continue;
+        }
+
+        let half_dir = normalize(light_dir + view_dir);
+        let n_dot_h = max(dot(normal, half_dir), 0.0);
+        let v_dot_h = max(dot(view_dir, half_dir), 0.0);
+
+        let fresnel = fresnel_schlick(f0, v_dot_h);
+        let diffuse = (vec3<f32>(1.0) - fresnel) * c_diff / PI;
+        let specular = fresnel * distribution_ggx(n_dot_h, alpha)
+            * visibility_smith_ggx(n_dot_l, n_dot_v, alpha);
+        let shadow = light_shadow(light, in, geometric_normal, light_dir);
+        let radiance = light.color.rgb * incidence.w * shadow;
+        color += (diffuse + specular) * radiance * n_dot_l;
+    }
+    return vec4<f32>(color + emissive, base_color.a);
+}
@@ -0,0 +1,652 @@
+use anyhow::{ensure, Result};
+use cgmath::prelude::*;
+use cgmath::{Deg, Matrix4, Point3, Vector3};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::{Camera, OPENGL_TO_WGPU_MATRIX},
+    light::{LightComponent, LightKind, LightsUniform},
+    renderer::GpuContext,
+    scene::{CameraComponent, EntityId, InstanceRaw, Scene, Vertex},
+    texture::Texture,
+};
+
+/// Shadow maps that fit in the atlas at once, matching the shader's tile array.
+pub const MAX_SHADOW_TILES: usize = 16;
+pub const MAX_CASCADES: usize = 4;
+
+const SHADOW_CAST: u32 = 1;
+const SHADOW_RECEIVE: u32 = 2;
+const SHADOW_NEAR: f32 = 0.05;
+
+/// Cube faces in the order the shader picks them by major axis: +X, -X, +Y, -Y, +Z, -Z.
+const CUBE_FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, -1.0, 0.0], [0.0, 0.0, -1.0]),
+    ([0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+];
+
+/// Per-entity shadow behaviour. Entities without the component cast and receive shadows.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ShadowFlags {
+    pub cast: bool,
+    pub receive: bool,
+}
+
+impl Default for ShadowFlags {
+    fn default() -> Self {
+        Self {
+            cast: true,
+            receive: true,
+        }
+    }
+}
+
+impl ShadowFlags {
+    /// The packed form stored in `InstanceRaw::flags`.
+    pub fn bits(&self) -> u32 {
+        let mut bits = 0;
+        if self.cast {
+            bits |= SHADOW_CAST;
+        }
+        if self.receive {
+            bits |= SHADOW_RECEIVE;
+        }
+        bits
+    }
+
+    pub fn from_bits(bits: u32) -> Self {
+        Self {
+            cast: bits & SHADOW_CAST != 0,
+            receive: bits & SHADOW_RECEIVE != 0,
+        }
+    }
+}
+
+impl Scene {
+    pub fn set_shadow_flags(&mut self, entity: EntityId, flags: ShadowFlags) {
+        self.insert(entity, flags);
+    }
+
+    pub fn remove_shadow_flags(&mut self, entity: EntityId) -> Option<ShadowFlags> {
+        self.remove(entity)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowConfig {
+    /// Width and height of the square atlas in texels.
+    pub atlas_size: u32,
+    /// Width and height of one shadow map in the atlas.
+    pub tile_size: u32,
+    /// Cascades per directional light, at most `MAX_CASCADES`.
+    pub cascade_count: u32,
+    /// Directional shadows end this far from the camera. Also the range of point and spot
+    /// lights without one.
+    pub max_distance: f32,
+    /// Blends cascade splits from uniform (0) to logarithmic (1).
+    pub cascade_split_lambda: f32,
+    /// World-space offset along the surface normal before sampling, against shadow acne.
+    pub normal_bias: f32,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        Self {
+            atlas_size: 2048,
+            tile_size: 512,
+            cascade_count: 4,
+            max_distance: 50.0,
+            cascade_split_lambda: 0.7,
+            normal_bias: 0.02,
+        }
+    }
+}
+
+impl ShadowConfig {
+    /// Checks that at least one tile fits in an atlas the device can create.
+    pub fn validate(&self, ctx: &GpuContext) -> Result<()> {
+        let max_size = ctx.device.limits().max_texture_dimension_2d;
+        ensure!(
+            (1..=max_size).contains(&self.atlas_size),
+            "shadow atlas size {} is outside 1..={}",
+            self.atlas_size,
+            max_size
+        );
+        ensure!(
+            (1..=self.atlas_size).contains(&self.tile_size),
+            "shadow tile size {} is outside 1..={}, the atlas size",
+            self.tile_size,
+            self.atlas_size
+        );
+        Ok(())
+    }
+
+    fn tiles_per_row(&self) -> u32 {
+        (self.atlas_size / self.tile_size.max(1)).max(1)
+    }
+
+    pub fn tile_capacity(&self) -> usize {
+        (self.tiles_per_row() as usize).pow(2).min(MAX_SHADOW_TILES)
+    }
+
+    fn tile_origin(&self, tile: usize) -> (u32, u32) {
+        let per_row = self.tiles_per_row() as usize;
+        (
+            (tile % per_row) as u32 * self.tile_size,
+            (tile / per_row) as u32 * self.tile_size,
+        )
+    }
+}
+
+/// View distances at which each cascade ends. Entries past `count` repeat `far`.
+pub fn cascade_splits(near: f32, far: f32, count: u32, lambda: f32) -> [f32; MAX_CASCADES] {
+    let count = count.clamp(1, MAX_CASCADES as u32);
+    let mut splits = [far; MAX_CASCADES];
+    for (index, split) in splits.iter_mut().enumerate().take(count as usize) {
+        let fraction = (index + 1) as f32 / count as f32;
+        let logarithmic = near.max(1e-4) * (far / near.max(1e-4)).powf(fraction);
+        let uniform = near + (far - near) * fraction;
+        *split = lambda * logarithmic + (1.0 - lambda) * uniform;
+    }
+    splits
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ShadowTileRaw {
+    pub view_proj: [[f32; 4]; 4],
+    /// Offset and size of the tile in atlas UV space.
+    pub atlas_rect: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ShadowsUniform {
+    pub tiles: [ShadowTileRaw; MAX_SHADOW_TILES],
+    pub cascade_splits: [f32; MAX_CASCADES],
+    /// The active camera's view direction; `w` is unused.
+    pub view_forward: [f32; 4],
+    /// Normal bias, then the size of one atlas texel in UV space.
+    pub params: [f32; 4],
+}
+
+impl Default for ShadowsUniform {
+    fn default() -> Self {
+        bytemuck::Zeroable::zeroed()
+    }
+}
+
+fn up_hint(direction: Vector3<f32>) -> Vector3<f32> {
+    if direction.y.abs() > 0.99 {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_y()
+    }
+}
+
+/// An orthographic light matrix covering the bounding sphere of the camera frustum between
+/// `near` and `far`, snapped to whole texels so the cascade does not shimmer as the camera
+/// moves.
+fn cascade_matrix(
+    camera: &Camera,
+    direction: Vector3<f32>,
+    near: f32,
+    far: f32,
+    config: &ShadowConfig,
+) -> Matrix4<f32> {
+    let forward = (camera.target - camera.eye).normalize();
+    let right = forward.cross(camera.up).normalize();
+    let up = right.cross(forward);
+
+    let mut corners = Vec::with_capacity(8);
+    for distance in [near, far] {
+        let center = camera.eye + forward * distance;
+        let half_height = camera.projection.half_height(distance);
+        let half_width = right * half_height * camera.aspect;
+        let half_height = up * half_height;
+        for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+            corners.push(center + half_width * x + half_height * y);
+        }
+    }
+    let center = Point3::centroid(&corners);
+    let radius = corners
+        .iter()
+        .map(|corner| corner.distance(center))
+        .fold(0.0, f32::max);
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let view = Matrix4::look_at_rh(
+        Point3::origin(),
+        Point3::from_vec(direction),
+        up_hint(direction),
+    );
+    let texel = 2.0 * radius / config.tile_size as f32;
+    let center = view.transform_point(center);
+    let x = (center.x / texel).floor() * texel;
+    let y = (center.y / texel).floor() * texel;
+    // Reach back towards the light so casters outside the slice still land in the map.
+    let projection = cgmath::ortho(
+        x - radius,
+        x + radius,
+        y - radius,
+        y + radius,
+        -(center.z + radius + config.max_distance),
+        -(center.z - radius),
+    );
+    OPENGL_TO_WGPU_MATRIX * projection * view
+}
+
+fn perspective_matrix(
+    position: Point3<f32>,
+    direction: Vector3<f32>,
+    up: Vector3<f32>,
+    fovy: Deg<f32>,
+    far: f32,
+) -> Matrix4<f32> {
+    let view = Matrix4::look_at_rh(position, position + direction, up);
+    OPENGL_TO_WGPU_MATRIX * cgmath::perspective(fovy, 1.0, SHADOW_NEAR, far) * view
+}
+
+/// The depth atlas shadow-casting lights render into, and the depth-only pass that fills it.
+/// Directional lights take one tile per cascade, point lights six and spot lights one.
+pub struct ShadowMaps {
+    pub config: ShadowConfig,
+    pub atlas: Texture,
+    /// Bound at group 3 of the main pass: tiles uniform, atlas and comparison sampler.
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+    view_buffer: wgpu::Buffer,
+    view_bind_group: wgpu::BindGroup,
+    view_stride: wgpu::BufferAddress,
+    pipeline: wgpu::RenderPipeline,
+    tile_count: usize,
+}
+
+impl ShadowMaps {
+    pub fn new(ctx: &GpuContext, config: ShadowConfig) -> Self {
+        let bind_group_layout =
+            ctx.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                sample_type: wgpu::TextureSampleType::Depth,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                            count: None,
+                        },
+                    ],
+                    label: Some("shadow_bind_group_layout"),
+                });
+
+        let uniform_buffer = ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shadows Buffer"),
+            size: std::mem::size_of::<ShadowsUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // One light matrix per tile, selected with a dynamic offset while filling the atlas.
+        let view_size = std::mem::size_of::<[[f32; 4]; 4]>() as wgpu::BufferAddress;
+        let alignment = ctx.device.limits().min_uniform_buffer_offset_alignment as u64;
+        let view_stride = view_size.div_ceil(alignment) * alignment;
+        let view_buffer = ctx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shadow View Buffer"),
+            size: view_stride * MAX_SHADOW_TILES as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let view_bind_group_layout =
+            ctx.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    entries: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: wgpu::BufferSize::new(view_size),
+                        },
+                        count: None,
+                    }],
+                    label: Some("shadow_view_bind_group_layout"),
+                });
+        let view_bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &view_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &view_buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(view_size),
+                }),
+            }],
+            label: Some("shadow_view_bind_group"),
+        });
+
+        let shader = ctx
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Shadow Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("shadow.wgsl").into()),
+            });
+        let pipeline_layout = ctx
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Shadow Pipeline Layout"),
+                immediate_size: 0,
+                bind_group_layouts: &[Some(&view_bind_group_layout)],
+            });
+        let pipeline = ctx
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Shadow Pipeline"),
+                multiview_mask: None,
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_shadow"),
+                    buffers: &[Vertex::desc(), InstanceRaw::desc()],
+                    compilation_options: Default::default(),
+                },
+                fragment: None,
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    // Single-sided geometry such as planes should shadow from both sides.
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Texture::DEPTH_FORMAT,
+                    depth_write_enabled: Some(true),
+                    depth_compare: Some(wgpu::CompareFunction::LessEqual),
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState {
+                        constant: 2,
+                        slope_scale: 2.0,
+                        clamp: 0.0,
+                    },
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                cache: None,
+            });
+
+        let atlas = Self::create_atlas(ctx, &config);
+        let bind_group = Self::create_bind_group(ctx, &bind_group_layout, &uniform_buffer, &atlas);
+
+        Self {
+            config,
+            atlas,
+            bind_group_layout,
+            bind_group,
+            uniform_buffer,
+            view_buffer,
+            view_bind_group,
+            view_stride,
+            pipeline,
+            tile_count: 0,
+        }
+    }
+
+    fn create_atlas(ctx: &GpuContext, config: &ShadowConfig) -> Texture {
+        Texture::create_depth_texture(
+            &ctx.device,
+            config.atlas_size,
+            config.atlas_size,
+            "shadow_atlas",
+        )
+    }
+
+    fn create_bind_group(
+        ctx: &GpuContext,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &wgpu::Buffer,
+        atlas: &Texture,
+    ) -> wgpu::BindGroup {
+        ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&atlas.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&atlas.sampler),
+                },
+            ],
+            label: Some("shadow_bind_group"),
+        })
+    }
+
+    /// Fails without changing anything if `config` does not pass
+    /// [`ShadowConfig::validate`].
+    pub fn set_config(&mut self, ctx: &GpuContext, config: ShadowConfig) -> Result<()> {
+        if self.config == config {
+            return Ok(());
+        }
+        config.validate(ctx)?;
+        if self.config.atlas_size != config.atlas_size {
+            self.atlas = Self::create_atlas(ctx, &config);
+            self.bind_group = Self::create_bind_group(
+                ctx,
+                &self.bind_group_layout,
+                &self.uniform_buffer,
+                &self.atlas,
+            );
+        }
+        self.config = config;
+        Ok(())
+    }
+
+    /// Shadow maps rendered by the last `prepare`.
+    pub fn tile_count(&self) -> usize {
+        self.tile_count
+    }
+
+    /// Assigns atlas tiles to the scene's shadow-casting lights, recording them in `lights`,
+    /// and uploads the light matrices. Lights that no longer fit in the atlas go without.
+    pub fn prepare(&mut self, ctx: &GpuContext, scene: &Scene, lights: &mut LightsUniform) {
+        let config = self.config;
+        let camera = scene
+            .active_camera
+            .and_then(|camera| scene.get::<CameraComponent>(camera))
+            .map(|camera| camera.camera);
+        let cascade_count = config.cascade_count.clamp(1, MAX_CASCADES as u32);
+        let splits = camera.map(|camera| {
+            cascade_splits(
+                camera.znear,
+                camera.zfar.min(config.max_distance),
+                cascade_count,
+                config.cascade_split_lambda,
+            )
+        });
+
+        let mut matrices: Vec<Matrix4<f32>> = Vec::new();
+        for (index, (entity, light, world)) in scene.active_lights().enumerate() {
+            if !light.cast_shadows {
+                continue;
+            }
+            let position = Point3::from_vec(world.w.truncate());
+            let direction = LightComponent::direction(world);
+            let light_matrices = match (light.kind, camera, splits) {
+                (LightKind::Directional, Some(camera), Some(splits)) => {
+                    let mut near = camera.znear;
+                    splits[..cascade_count as usize]
+                        .iter()
+                        .map(|&far| {
+                            let matrix = cascade_matrix(&camera, direction, near, far, &config);
+                            near = far;
+                            matrix
+                        })
+                        .collect()
+                }
+                (LightKind::Directional, ..) => continue,
+                (LightKind::Point { range }, ..) => {
+                    let far = if range > 0.0 {
+                        range
+                    } else {
+                        config.max_distance
+                    };
+                    CUBE_FACES
+                        .iter()
+                        .map(|&(direction, up)| {
+                            perspective_matrix(
+                                position,
+                                direction.into(),
+                                up.into(),
+                                Deg(90.0),
+                                far,
+                            )
+                        })
+                        .collect()
+                }
+                (
+                    LightKind::Spot {
+                        range, outer_angle, ..
+                    },
+                    ..,
+                ) => {
+                    let far = if range > 0.0 {
+                        range
+                    } else {
+                        config.max_distance
+                    };
+                    let fovy = Deg((outer_angle * 2.0).clamp(1.0, 170.0));
+                    vec![perspective_matrix(
+                        position,
+                        direction,
+                        up_hint(direction),
+                        fovy,
+                        far,
+                    )]
+                }
+            };
+
+            if matrices.len() + light_matrices.len() > config.tile_capacity() {
+                warn!("shadow atlas is full, {entity:?} casts no shadows");
+                continue;
+            }
+            lights.lights[index].shadow_tile = matrices.len() as i32;
+            lights.lights[index].shadow_count = light_matrices.len() as u32;
+            matrices.extend(light_matrices);
+        }
+
+        let atlas_size = config.atlas_size as f32;
+        let mut uniform = ShadowsUniform {
+            cascade_splits: splits.unwrap_or([0.0; MAX_CASCADES]),
+            params: [config.normal_bias, 1.0 / atlas_size, 0.0, 0.0],
+            ..Default::default()
+        };
+        if let Some(camera) = camera {
+            let forward = (camera.target - camera.eye).normalize();
+            uniform.view_forward = forward.extend(0.0).into();
+        }
+        for (tile, matrix) in matrices.iter().enumerate() {
+            let (x, y) = config.tile_origin(tile);
+            let size = config.tile_size as f32 / atlas_size;
+            uniform.tiles[tile] = ShadowTileRaw {
+                view_proj: (*matrix).into(),
+                atlas_rect: [x as f32 / atlas_size, y as f32 / atlas_size, size, size],
+            };
+            let view_proj: [[f32; 4]; 4] = (*matrix).into();
+            ctx.queue.write_buffer(
+                &self.view_buffer,
+                tile as wgpu::BufferAddress * self.view_stride,
+                bytemuck::bytes_of(&view_proj),
+            );
+        }
+        ctx.queue
+            .write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+        self.tile_count = matrices.len();
+    }
+
+    /// Renders every render batch into each tile assigned by the last `prepare`.
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, scene: &Scene) {
+        if self.tile_count == 0 {
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Shadow Pass"),
+            multiview_mask: None,
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.atlas.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+
+        let tile_size = self.config.tile_size;
+        for tile in 0..self.tile_count {
+            let (x, y) = self.config.tile_origin(tile);
+            render_pass.set_viewport(
+                x as f32,
+                y as f32,
+                tile_size as f32,
+                tile_size as f32,
+                0.0,
+                1.0,
+            );
+            render_pass.set_scissor_rect(x, y, tile_size, tile_size);
+            render_pass.set_bind_group(
+                0,
+                &self.view_bind_group,
+                &[(tile as wgpu::BufferAddress * self.view_stride) as u32],
+            );
+
+            for batch in &scene.render_batches {
+                let Some((instances, count)) = batch.shadow_casters() else {
+                    continue;
+                };
+                let Some(mesh) = scene.mesh(batch.mesh) else {
+                    continue;
+                };
+                render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                render_pass.set_vertex_buffer(1, instances.slice(..));
+                render_pass.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
+                render_pass.draw_indexed(0..mesh.index_count, 0, 0..count);
+            }
+        }
+    }
+}
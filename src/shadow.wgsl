@@ -0,0 +1,31 @@
+// Depth-only pass filling one shadow atlas tile per draw. Only shadow casters are drawn.
+
+struct ShadowView {
+    view_proj: mat4x4<f32>,
+}
+@group(0) @binding(0)
+var<uniform> shadow_view: ShadowView;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+}
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+}
+
+@vertex
+fn vs_shadow(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> @builtin(position) vec4<f32> {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    return shadow_view.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+}
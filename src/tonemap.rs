@@ -0,0 +1,259 @@
+use serde::{Deserialize, Serialize};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    post_process::PostProcessSettings,
+    render_graph::{PassBuilder, PassContext, RenderGraphPass, ResourceId, TransientTexture},
+    renderer::{GpuContext, RenderResources},
+    scene::{CameraComponent, Scene},
+};
+
+/// The curve mapping HDR scene color into the output's 0..1 range.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Tonemapping {
+    /// Clips at 1.0, matching rendering without HDR.
+    Linear,
+    Reinhard,
+    #[default]
+    Aces,
+    AgX,
+}
+
+impl Tonemapping {
+    fn curve(self) -> u32 {
+        match self {
+            Tonemapping::Linear => 0,
+            Tonemapping::Reinhard => 1,
+            Tonemapping::Aces => 2,
+            Tonemapping::AgX => 3,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TonemapUniform {
+    pub exposure: f32,
+    pub curve: u32,
+    pub encode_srgb: u32,
+    _padding: u32,
+}
+
+impl TonemapUniform {
+    pub fn new(
+        exposure: f32,
+        tonemapping: Tonemapping,
+        output_format: wgpu::TextureFormat,
+    ) -> Self {
+        Self {
+            exposure: exposure.exp2(),
+            curve: tonemapping.curve(),
+            encode_srgb: (!output_format.is_srgb()) as u32,
+            _padding: 0,
+        }
+    }
+}
+
+struct TonemapPipeline {
+    format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+}
+
+impl TonemapPipeline {
+    fn new(ctx: &GpuContext, format: wgpu::TextureFormat) -> Self {
+        let bind_group_layout =
+            ctx.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                    ],
+                    label: Some("tonemap_bind_group_layout"),
+                });
+
+        let uniform_buffer = ctx
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Tonemap Buffer"),
+                contents: bytemuck::bytes_of(&TonemapUniform::new(
+                    0.0,
+                    Tonemapping::default(),
+                    format,
+                )),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        let shader = ctx
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Tonemap Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("tonemap.wgsl").into()),
+            });
+        let layout = ctx
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Tonemap Pipeline Layout"),
+                immediate_size: 0,
+                bind_group_layouts: &[Some(&bind_group_layout)],
+            });
+        let pipeline = ctx
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Tonemap Pipeline"),
+                multiview_mask: None,
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_fullscreen"),
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_tonemap"),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                cache: None,
+            });
+
+        Self {
+            format,
+            pipeline,
+            bind_group_layout,
+            uniform_buffer,
+        }
+    }
+}
+
+/// Resolves [`ResourceId::HDR_COLOR`] into [`ResourceId::LDR_COLOR`] with the active
+/// camera's exposure and tonemapping, or straight into [`ResourceId::OUTPUT`] when the
+/// camera has no effects to run on the LDR image.
+#[derive(Default)]
+pub struct TonemapPass {
+    /// Rebuilt when the target format changes.
+    pipeline: Option<TonemapPipeline>,
+}
+
+impl TonemapPass {
+    pub const NAME: &'static str = "tonemap";
+
+    fn target(scene: Option<&Scene>) -> ResourceId {
+        let ldr_effects = scene.is_none_or(|scene| {
+            scene
+                .active_post_process()
+                .is_some_and(PostProcessSettings::has_ldr_effects)
+        });
+        if ldr_effects {
+            ResourceId::LDR_COLOR
+        } else {
+            ResourceId::OUTPUT
+        }
+    }
+}
+
+impl RenderGraphPass for TonemapPass {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn setup(&self, builder: &mut PassBuilder) {
+        builder.read(ResourceId::HDR_COLOR);
+        match Self::target(builder.scene()) {
+            ResourceId::LDR_COLOR => builder.create_texture(
+                ResourceId::LDR_COLOR,
+                TransientTexture::color(RenderResources::LDR_FORMAT),
+            ),
+            output => builder.write(output),
+        };
+    }
+
+    fn execute(&mut self, pass: &mut PassContext<'_>) {
+        let target = Self::target(Some(pass.scene));
+        let format = pass
+            .texture_format(target)
+            .expect("the tonemapping target is always a texture");
+        if self.pipeline.as_ref().map(|pipeline| pipeline.format) != Some(format) {
+            self.pipeline = Some(TonemapPipeline::new(pass.gpu, format));
+        }
+        let pipeline = self.pipeline.as_ref().unwrap();
+
+        let (exposure, tonemapping) = pass
+            .scene
+            .active_camera
+            .and_then(|id| pass.scene.get::<CameraComponent>(id))
+            .map(|camera| (camera.exposure, camera.tonemapping))
+            .unwrap_or_default();
+        pass.gpu.queue.write_buffer(
+            &pipeline.uniform_buffer,
+            0,
+            bytemuck::bytes_of(&TonemapUniform::new(exposure, tonemapping, format)),
+        );
+        let bind_group = pass
+            .gpu
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &pipeline.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(
+                            pass.texture(ResourceId::HDR_COLOR),
+                        ),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: pipeline.uniform_buffer.as_entire_binding(),
+                    },
+                ],
+                label: Some("tonemap_bind_group"),
+            });
+
+        let mut render_pass = pass.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Tonemap Pass"),
+            multiview_mask: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: pass.texture(target),
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&pipeline.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
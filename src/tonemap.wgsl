@@ -0,0 +1,116 @@
+// Fullscreen pass mapping the HDR scene color into the output's displayable range.
+
+const TONEMAP_LINEAR: u32 = 0u;
+const TONEMAP_REINHARD: u32 = 1u;
+const TONEMAP_ACES: u32 = 2u;
+const TONEMAP_AGX: u32 = 3u;
+
+struct Tonemap {
+    // Linear multiplier derived from the camera's exposure in stops.
+    exposure: f32,
+    curve: u32,
+    // Set when the output format does not apply the sRGB transfer function itself.
+    encode_srgb: u32,
+    _padding: u32,
+}
+@group(0) @binding(0)
+var hdr_color: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> tonemap: Tonemap;
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // One triangle covering the screen.
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    // Scaling by luminance keeps hues from shifting towards white.
+    return color / (1.0 + luminance(color));
+}
+
+// Stephen Hill's fit of the ACES reference rendering and output transforms.
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let input = mat3x3<f32>(
+        vec3<f32>(0.59719, 0.07600, 0.02840),
+        vec3<f32>(0.35458, 0.90834, 0.13383),
+        vec3<f32>(0.04823, 0.01566, 0.83777),
+    );
+    let output = mat3x3<f32>(
+        vec3<f32>(1.60475, -0.10208, -0.00327),
+        vec3<f32>(-0.53108, 1.10813, -0.07276),
+        vec3<f32>(-0.07367, -0.00605, 1.07602),
+    );
+    let v = input * color;
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return clamp(output * (a / b), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Polynomial approximation of the AgX base contrast curve by Benjamin Wrensch.
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2
+        + 0.1191 * x - 0.00232;
+}
+
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104,
+    );
+    let outset = mat3x3<f32>(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116,
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    var v = inset * max(color, vec3<f32>(1e-10));
+    v = clamp(log2(v), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    v = agx_contrast((v - min_ev) / (max_ev - min_ev));
+    v = outset * v;
+    // The curve targets a 2.2 display; return to linear for the output encoding.
+    return pow(max(v, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+fn srgb_encode(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+@fragment
+fn fs_tonemap(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let hdr = textureLoad(hdr_color, vec2<i32>(position.xy), 0).rgb * tonemap.exposure;
+
+    var color: vec3<f32>;
+    switch tonemap.curve {
+        case TONEMAP_REINHARD: {
+            color = reinhard(hdr);
+        }
+        case TONEMAP_ACES: {
+            color = aces(hdr);
+        }
+        case TONEMAP_AGX: {
+            color = agx(hdr);
+        }
+        default: {
+            color = hdr;
+        }
+    }
+    color = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+
+    if tonemap.encode_srgb != 0u {
+        color = srgb_encode(color);
+    }
+    return vec4<f32>(color, 1.0);
+}
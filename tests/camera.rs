@@ -0,0 +1,123 @@
+use cgmath::{Matrix4, Point3, Vector4};
+use engine_rust::{
+    camera::{Camera, CameraUniform, Projection},
+    renderer::{GpuContext, HeadlessRenderer},
+    scene::{CameraComponent, Material, Mesh, MeshRendererComponent, Scene, TransformComponent},
+    tonemap::Tonemapping,
+};
+use winit::dpi::PhysicalSize;
+
+/// `point` in wgpu normalized device coordinates.
+fn project(camera: &Camera, point: [f32; 3]) -> [f32; 3] {
+    let view_proj: Matrix4<f32> = CameraUniform::from_camera(camera).view_proj.into();
+    let clip = view_proj * Vector4::new(point[0], point[1], point[2], 1.0);
+    [clip.x / clip.w, clip.y / clip.w, clip.z / clip.w]
+}
+
+fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+    assert!(
+        actual
+            .iter()
+            .zip(&expected)
+            .all(|(a, b)| (a - b).abs() < 1e-4),
+        "{:?} != {:?}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn orthographic_cameras_ignore_distance() {
+    let camera = Camera::new(Point3::new(0.0, 0.0, 10.0), Point3::new(0.0, 0.0, 0.0), 2.0)
+        .with_projection(Projection::Orthographic { height: 4.0 });
+
+    let near = project(&camera, [4.0, 2.0, 5.0]);
+    let far = project(&camera, [4.0, 2.0, -50.0]);
+    assert_close([near[0], near[1], 0.0], [1.0, 1.0, 0.0]);
+    assert_close([far[0], far[1], 0.0], [1.0, 1.0, 0.0]);
+    assert!(near[2] < far[2] && near[2] >= 0.0 && far[2] <= 1.0);
+
+    let mut scene = Scene::new();
+    let entity = scene.spawn(None, None);
+    scene.add_camera(entity, CameraComponent::new(camera));
+    scene.active_camera = Some(entity);
+    scene.set_active_camera_aspect(1.0);
+    let camera = scene.get::<CameraComponent>(entity).unwrap().camera;
+    let corner = project(&camera, [2.0, -2.0, 0.0]);
+    assert_close([corner[0], corner[1], 0.0], [1.0, -1.0, 0.0]);
+}
+
+#[test]
+fn infinite_reverse_z_maps_the_near_plane_to_one() {
+    let camera = Camera::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0), 1.0)
+        .with_projection(Projection::InfiniteReverseZ { fovy: 90.0 });
+    let perspective = Camera {
+        projection: Projection::Perspective { fovy: 90.0 },
+        ..camera
+    };
+
+    assert_close(project(&camera, [0.0, 0.0, -0.1]), [0.0, 0.0, 1.0]);
+    assert_close(project(&camera, [1.0, 1.0, -1.0]), [1.0, 1.0, 0.1]);
+    let distant = project(&camera, [0.0, 0.0, -1.0e6]);
+    assert!(distant[2] > 0.0 && distant[2] < 1.0e-6);
+
+    // Same framing as the finite perspective, only depth differs.
+    let finite = project(&perspective, [0.5, -0.25, -3.0]);
+    let infinite = project(&camera, [0.5, -0.25, -3.0]);
+    assert_close([infinite[0], infinite[1], 0.0], [finite[0], finite[1], 0.0]);
+    assert!(camera.projection.is_reverse_z());
+    assert!(!perspective.projection.is_reverse_z());
+}
+
+/// Each projection draws the pentagon in front of it, including the reversed depth test.
+#[test]
+fn every_projection_renders() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let mut renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(32, 32));
+    renderer.clear_color = wgpu::Color::BLACK;
+
+    let mut scene = Scene::new();
+    let mesh = scene.add_mesh(Mesh::pentagon(&ctx));
+    let material = scene.add_material(Material::from_color(
+        &ctx,
+        renderer.material_layout(),
+        [1.0; 4],
+        "White",
+    )?);
+    let pentagon = scene.spawn(None, None);
+    scene.set_transform(pentagon, TransformComponent::identity());
+    scene.add_mesh_renderer(pentagon, MeshRendererComponent { mesh, material });
+    let camera = scene.spawn(None, None);
+    scene.active_camera = Some(camera);
+    scene.rebuild_render_batches(&ctx);
+
+    for projection in [
+        Projection::Perspective { fovy: 45.0 },
+        Projection::Orthographic { height: 2.0 },
+        Projection::InfiniteReverseZ { fovy: 45.0 },
+    ] {
+        scene.add_camera(
+            camera,
+            CameraComponent::new(
+                Camera::new((0.0, 0.0, 2.0).into(), (0.0, 0.0, 0.0).into(), 1.0)
+                    .with_projection(projection),
+            )
+            .with_tonemapping(Tonemapping::Linear),
+        );
+        let image = renderer.render_to_image(&ctx, &mut scene)?;
+        assert_eq!(
+            renderer.resources.depth().reverse_z,
+            projection.is_reverse_z()
+        );
+        if !ctx.is_noop() {
+            assert_eq!(
+                image.get_pixel(16, 16).0,
+                [255, 255, 255, 255],
+                "{:?}",
+                projection
+            );
+            assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0, 255], "{:?}", projection);
+        }
+    }
+    Ok(())
+}
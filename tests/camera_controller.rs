@@ -0,0 +1,119 @@
+use cgmath::{InnerSpace, MetricSpace, Point3, Vector3};
+use engine_rust::{
+    camera::Camera,
+    camera_controller::{FlyController, FollowController, OrbitController},
+    input::InputService,
+    scene::{CameraComponent, EntityId, Scene, TransformComponent},
+};
+use winit::{event::MouseButton, keyboard::KeyCode};
+
+fn camera_scene(eye: [f32; 3], target: [f32; 3]) -> (Scene, EntityId) {
+    let mut scene = Scene::new();
+    let entity = scene.spawn(None, None);
+    scene.add_camera(
+        entity,
+        CameraComponent::new(Camera::new(eye.into(), target.into(), 1.0)),
+    );
+    scene.active_camera = Some(entity);
+    (scene, entity)
+}
+
+fn active_camera(scene: &Scene) -> Camera {
+    scene
+        .get::<CameraComponent>(scene.active_camera.unwrap())
+        .unwrap()
+        .camera
+}
+
+#[test]
+fn fly_moves_with_keys_and_turns_with_the_mouse() {
+    let (mut scene, entity) = camera_scene([0.0, 0.0, 5.0], [0.0, 0.0, 0.0]);
+    let controller = FlyController::new(&active_camera(&scene));
+    scene.insert(entity, controller);
+
+    let mut input = InputService::new();
+    input.set_key(KeyCode::KeyW, true);
+    scene.update_camera_controller(&input, 0.5);
+    let camera = active_camera(&scene);
+    assert!(camera.eye.distance(Point3::new(0.0, 0.0, 2.5)) < 1e-4);
+    assert!((camera.target - camera.eye).normalize().z < -0.999);
+
+    // Looking only happens while the look button is held.
+    input.set_key(KeyCode::KeyW, false);
+    input.add_mouse_motion(100.0, 0.0);
+    scene.update_camera_controller(&input, 0.1);
+    assert_eq!(active_camera(&scene).eye, camera.eye);
+    assert_eq!(active_camera(&scene).target, camera.target);
+
+    input.set_mouse_button(MouseButton::Right, true);
+    scene.update_camera_controller(&input, 0.1);
+    let direction = active_camera(&scene).target - active_camera(&scene).eye;
+    assert!(direction.x > 0.1, "{:?}", direction);
+    assert_eq!(active_camera(&scene).eye, camera.eye);
+
+    input.end_frame();
+    assert_eq!(input.mouse_delta(), [0.0, 0.0]);
+}
+
+#[test]
+fn orbit_keeps_its_distance_and_zooms() {
+    let (mut scene, entity) = camera_scene([0.0, 0.0, 10.0], [1.0, 0.0, 0.0]);
+    let controller = OrbitController::new(&active_camera(&scene));
+    scene.insert(entity, controller);
+    let distance = controller.distance;
+
+    let mut input = InputService::new();
+    input.set_mouse_button(MouseButton::Left, true);
+    input.add_mouse_motion(200.0, -50.0);
+    scene.update_camera_controller(&input, 0.016);
+    let camera = active_camera(&scene);
+    assert_eq!(camera.target, Point3::new(1.0, 0.0, 0.0));
+    assert!((camera.eye.distance(camera.target) - distance).abs() < 1e-4);
+    assert!(camera.eye.y < -0.1);
+
+    input.end_frame();
+    input.add_scroll(2.0);
+    scene.update_camera_controller(&input, 0.016);
+    let zoomed = active_camera(&scene).eye.distance(camera.target);
+    assert!((zoomed - distance * 0.81).abs() < 1e-3, "{}", zoomed);
+
+    input.end_frame();
+    input.add_scroll(-1000.0);
+    scene.update_camera_controller(&input, 0.016);
+    let controller = scene.get::<OrbitController>(entity).unwrap();
+    assert_eq!(controller.distance, controller.max_distance);
+}
+
+#[test]
+fn follow_eases_towards_the_target_offset() {
+    let (mut scene, camera) = camera_scene([0.0, 0.0, 0.0], [0.0, 0.0, -1.0]);
+    let player = scene.spawn(None, None);
+    scene.set_transform(
+        player,
+        TransformComponent::from_translation_rotation(
+            Vector3::new(10.0, 0.0, 0.0),
+            TransformComponent::identity().rotation,
+        ),
+    );
+    scene.insert(
+        camera,
+        FollowController::new(player).with_offset(Vector3::new(0.0, 2.0, 4.0)),
+    );
+
+    let input = InputService::new();
+    let goal = Point3::new(10.0, 2.0, 4.0);
+    let mut previous = active_camera(&scene).eye.distance(goal);
+    for _ in 0..5 {
+        scene.update_camera_controller(&input, 0.1);
+        let distance = active_camera(&scene).eye.distance(goal);
+        assert!(distance < previous);
+        previous = distance;
+    }
+    assert!(previous > 0.1);
+
+    scene.get_mut::<FollowController>(camera).unwrap().smoothing = 0.0;
+    scene.update_camera_controller(&input, 0.1);
+    let snapped = active_camera(&scene);
+    assert!(snapped.eye.distance(goal) < 1e-4);
+    assert!(snapped.target.distance(Point3::new(10.0, 1.0, 0.0)) < 1e-4);
+}
@@ -0,0 +1,113 @@
+use engine_rust::{
+    component::Components,
+    scene::{EntityId, Scene},
+};
+
+#[derive(Debug, PartialEq)]
+struct Health(u32);
+
+#[derive(Debug, PartialEq)]
+struct Speed(f32);
+
+#[derive(Debug, PartialEq)]
+struct Tag;
+
+fn entities(count: usize) -> Vec<EntityId> {
+    let mut scene = Scene::new();
+    (0..count).map(|_| scene.spawn(None, None)).collect()
+}
+
+fn sorted<T>(query: impl Iterator<Item = (EntityId, T)>) -> Vec<(EntityId, T)> {
+    let mut items: Vec<_> = query.collect();
+    items.sort_by_key(|(entity, _)| *entity);
+    items
+}
+
+#[test]
+fn components_insert_get_and_remove() {
+    let ids = entities(2);
+    let mut components = Components::new();
+
+    assert_eq!(components.insert(ids[0], Health(10)), None);
+    assert_eq!(components.insert(ids[0], Health(20)), Some(Health(10)));
+    assert_eq!(components.get::<Health>(ids[0]), Some(&Health(20)));
+    assert_eq!(components.get::<Health>(ids[1]), None);
+    assert_eq!(components.get::<Speed>(ids[0]), None);
+    assert!(components.get_mut::<Speed>(ids[0]).is_none());
+
+    components.get_mut::<Health>(ids[0]).unwrap().0 += 5;
+    assert_eq!(components.get::<Health>(ids[0]), Some(&Health(25)));
+    assert!(components.contains::<Health>(ids[0]));
+
+    assert_eq!(components.remove::<Health>(ids[0]), Some(Health(25)));
+    assert_eq!(components.remove::<Health>(ids[0]), None);
+    assert_eq!(components.remove::<Speed>(ids[0]), None);
+    assert!(!components.contains::<Health>(ids[0]));
+}
+
+#[test]
+fn queries_match_entities_with_every_component() {
+    let ids = entities(5);
+    let mut components = Components::new();
+    for (index, &entity) in ids.iter().enumerate() {
+        components.insert(entity, Health(index as u32));
+        if index % 2 == 0 {
+            components.insert(entity, Speed(index as f32));
+        }
+    }
+    components.insert(ids[4], Tag);
+
+    assert_eq!(components.query::<(Health,)>().count(), 5);
+    assert_eq!(
+        sorted(components.query::<(Health, Speed)>()),
+        [
+            (ids[0], (&Health(0), &Speed(0.0))),
+            (ids[2], (&Health(2), &Speed(2.0))),
+            (ids[4], (&Health(4), &Speed(4.0))),
+        ]
+    );
+    // The order of the types does not matter, the smallest storage drives the query.
+    assert_eq!(
+        sorted(components.query::<(Tag, Speed, Health)>()),
+        [(ids[4], (&Tag, &Speed(4.0), &Health(4)))]
+    );
+    assert_eq!(
+        sorted(components.query::<(Health, Speed, Tag)>()),
+        [(ids[4], (&Health(4), &Speed(4.0), &Tag))]
+    );
+    // A type nothing was ever inserted for matches nothing.
+    assert_eq!(components.query::<(Health, u8)>().count(), 0);
+}
+
+#[test]
+fn removed_components_leave_queries() {
+    let ids = entities(3);
+    let mut components = Components::new();
+    for &entity in &ids {
+        components.insert(entity, Health(1));
+        components.insert(entity, Speed(1.0));
+    }
+
+    components.remove::<Speed>(ids[1]);
+    assert_eq!(
+        sorted(components.query::<(Health, Speed)>())
+            .into_iter()
+            .map(|(entity, _)| entity)
+            .collect::<Vec<_>>(),
+        [ids[0], ids[2]]
+    );
+
+    components.remove_entity(ids[0]);
+    assert!(components.get::<Health>(ids[0]).is_none());
+    assert_eq!(
+        sorted(components.query::<(Health,)>())
+            .into_iter()
+            .map(|(entity, _)| entity)
+            .collect::<Vec<_>>(),
+        [ids[1], ids[2]]
+    );
+
+    // Emptied storages still exist and simply match nothing.
+    components.remove::<Speed>(ids[2]);
+    assert_eq!(components.query::<(Speed, Health)>().count(), 0);
+}
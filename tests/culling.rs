@@ -0,0 +1,249 @@
+use std::cell::RefCell;
+
+use cgmath::{Matrix4, Point3, Vector3};
+use engine_rust::{
+    camera::{Camera, Projection},
+    culling::{Aabb, CullStats, Frustum},
+    renderer::{GpuContext, HeadlessRenderer},
+    scene::{CameraComponent, Material, Mesh, MeshRendererComponent, Scene, TransformComponent},
+    tonemap::Tonemapping,
+};
+use winit::dpi::PhysicalSize;
+
+fn unit_box() -> Aabb {
+    Aabb::new(Point3::new(-0.5, -0.5, -0.5), Point3::new(0.5, 0.5, 0.5))
+}
+
+fn at(x: f32, y: f32, z: f32) -> Aabb {
+    unit_box().transformed(&Matrix4::from_translation(Vector3::new(x, y, z)))
+}
+
+#[test]
+fn boxes_are_tested_against_every_plane() {
+    let camera = Camera::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0), 1.0);
+    let frustum = Frustum::from_camera(&camera);
+
+    assert!(frustum.intersects_aabb(&at(0.0, 0.0, -5.0)));
+    assert!(frustum.contains_point(Point3::new(0.0, 0.0, -5.0)));
+    assert!(!frustum.intersects_aabb(&at(0.0, 0.0, 5.0)));
+    assert!(!frustum.intersects_aabb(&at(20.0, 0.0, -5.0)));
+    assert!(!frustum.intersects_aabb(&at(0.0, -20.0, -5.0)));
+    assert!(!frustum.intersects_aabb(&at(0.0, 0.0, -200.0)));
+    // Straddling the left plane still counts.
+    assert!(frustum.intersects_aabb(&at(-2.4, 0.0, -5.0)));
+
+    let infinite =
+        Frustum::from_camera(&camera.with_projection(Projection::InfiniteReverseZ { fovy: 45.0 }));
+    assert!(infinite.intersects_aabb(&at(0.0, 0.0, -200.0)));
+    assert!(!infinite.intersects_aabb(&at(0.0, 0.0, 5.0)));
+}
+
+#[test]
+fn transformed_boxes_enclose_rotated_corners() {
+    let rotated = unit_box().transformed(&Matrix4::from_angle_y(cgmath::Deg(45.0)));
+    let half_diagonal = 0.5 * 2.0_f32.sqrt();
+    assert!((rotated.max.x - half_diagonal).abs() < 1e-5);
+    assert!((rotated.max.y - 0.5).abs() < 1e-5);
+
+    let scaled = unit_box().transformed(&Matrix4::from_nonuniform_scale(2.0, 1.0, 1.0));
+    assert_eq!(scaled.half_extents(), Vector3::new(1.0, 0.5, 0.5));
+}
+
+#[test]
+fn instances_outside_the_camera_are_not_drawn() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(16, 16));
+
+    let mut scene = Scene::new();
+    let mesh = scene.add_mesh(Mesh::pentagon(&ctx));
+    let pentagon = scene.mesh(mesh).unwrap().bounds;
+    assert!(pentagon.min.x < -0.49 && pentagon.max.y > 0.49);
+    assert_eq!(pentagon.min.z, 0.0);
+    let material = scene.add_material(Material::from_color(
+        &ctx,
+        renderer.material_layout(),
+        [1.0; 4],
+        "White",
+    )?);
+
+    for x in -5..5 {
+        let entity = scene.spawn(None, None);
+        scene.set_transform(
+            entity,
+            TransformComponent::from_translation_rotation(
+                Vector3::new(x as f32 * 10.0, 0.0, 0.0),
+                TransformComponent::identity().rotation,
+            ),
+        );
+        scene.add_mesh_renderer(entity, MeshRendererComponent { mesh, material });
+    }
+    let camera = scene.spawn(None, None);
+    scene.add_camera(
+        camera,
+        CameraComponent::new(Camera::new(
+            Point3::new(0.0, 0.0, 5.0),
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+        )),
+    );
+    scene.active_camera = Some(camera);
+    scene.rebuild_render_batches(&ctx);
+
+    assert_eq!(
+        scene.cull_stats(),
+        CullStats {
+            visible: 1,
+            culled: 9
+        }
+    );
+    let batch = scene.render_batches.iter().next().unwrap();
+    assert_eq!((batch.visible_count, batch.instance_count), (1, 10));
+
+    // Moving the camera back brings more of the row into view.
+    scene.get_mut::<CameraComponent>(camera).unwrap().camera.eye = Point3::new(0.0, 0.0, 40.0);
+    scene.cull_render_batches(&ctx);
+    let stats = scene.cull_stats();
+    assert_eq!(stats.total(), 10);
+    assert!(stats.visible > 1 && stats.culled > 0, "{:?}", stats);
+
+    scene.active_camera = None;
+    scene.cull_render_batches(&ctx);
+    assert_eq!(scene.cull_stats().visible, 10);
+    Ok(())
+}
+
+/// Headless renders cull against the camera as it is when drawing, not as it was when the
+/// batches were last built.
+#[test]
+fn headless_renders_cull_against_the_moved_camera() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let mut renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(16, 16));
+    renderer.clear_color = wgpu::Color::BLACK;
+    // The compute pass culls against the camera uniform every frame already.
+    renderer.resources.set_gpu_culling(&ctx, false)?;
+
+    let mut scene = Scene::new();
+    let mesh = scene.add_mesh(Mesh::pentagon(&ctx));
+    let material = scene.add_material(Material::from_color(
+        &ctx,
+        renderer.material_layout(),
+        [1.0; 4],
+        "White",
+    )?);
+    let entity = scene.spawn(None, None);
+    scene.set_transform(
+        entity,
+        TransformComponent::from_translation_rotation(
+            Vector3::new(10.0, 0.0, 0.0),
+            TransformComponent::identity().rotation,
+        ),
+    );
+    scene.add_mesh_renderer(entity, MeshRendererComponent { mesh, material });
+    let camera = scene.spawn(None, None);
+    scene.add_camera(
+        camera,
+        CameraComponent::new(Camera::new(
+            Point3::new(0.0, 0.0, 5.0),
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+        ))
+        .with_tonemapping(Tonemapping::Linear),
+    );
+    scene.active_camera = Some(camera);
+    scene.rebuild_render_batches(&ctx);
+    assert_eq!(scene.cull_stats().visible, 0);
+
+    let camera = &mut scene.get_mut::<CameraComponent>(camera).unwrap().camera;
+    camera.eye = Point3::new(10.0, 0.0, 5.0);
+    camera.target = Point3::new(10.0, 0.0, 0.0);
+    let image = renderer.render_to_image(&ctx, &mut scene)?;
+    assert_eq!(scene.cull_stats().visible, 1);
+    if !ctx.is_noop() {
+        assert_eq!(image.get_pixel(8, 8).0, [255, 255, 255, 255]);
+    }
+    Ok(())
+}
+
+#[test]
+fn only_changed_batches_are_culled_again() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(16, 16));
+
+    let mut scene = Scene::new();
+    let material = scene.add_material(Material::from_color(
+        &ctx,
+        renderer.material_layout(),
+        [1.0; 4],
+        "White",
+    )?);
+    let mut entities = Vec::new();
+    for x in 0..3 {
+        // A mesh each, so every entity gets a batch of its own.
+        let mesh = scene.add_mesh(Mesh::pentagon(&ctx));
+        let entity = scene.spawn(None, None);
+        scene.set_transform(
+            entity,
+            TransformComponent::from_translation_rotation(
+                Vector3::new(x as f32, 0.0, 0.0),
+                TransformComponent::identity().rotation,
+            ),
+        );
+        scene.add_mesh_renderer(entity, MeshRendererComponent { mesh, material });
+        entities.push(entity);
+    }
+    let camera = Camera::new(Point3::new(0.0, 0.0, 5.0), Point3::new(0.0, 0.0, 0.0), 1.0);
+    let frustum = Some(Frustum::from_camera(&camera));
+    let bounds = scene.meshes[0].bounds;
+    // Flush the batches without culling them, so every cull below is ours.
+    scene.set_gpu_culled(true);
+    scene.rebuild_render_batches(&ctx);
+
+    let culled = RefCell::new(Vec::new());
+    let cull = |scene: &mut Scene, frustum| {
+        culled.borrow_mut().clear();
+        scene.render_batches.cull(&ctx, frustum, |mesh| {
+            culled.borrow_mut().push(mesh);
+            Some(bounds)
+        });
+        culled.borrow().len()
+    };
+    assert_eq!(cull(&mut scene, frustum), 3);
+    assert_eq!(cull(&mut scene, frustum), 0);
+
+    scene
+        .get_mut::<TransformComponent>(entities[1])
+        .unwrap()
+        .translation = Vector3::new(20.0, 0.0, 0.0);
+    scene.update_render_batches(&ctx);
+    assert_eq!(cull(&mut scene, frustum), 1);
+    assert_eq!(
+        culled.borrow()[0],
+        scene
+            .get::<MeshRendererComponent>(entities[1])
+            .unwrap()
+            .mesh
+    );
+    assert_eq!(
+        scene.cull_stats(),
+        CullStats {
+            visible: 2,
+            culled: 1
+        }
+    );
+
+    // A moved camera culls every batch again.
+    let moved = Camera::new(
+        Point3::new(20.0, 0.0, 5.0),
+        Point3::new(20.0, 0.0, 0.0),
+        1.0,
+    );
+    assert_eq!(cull(&mut scene, Some(Frustum::from_camera(&moved))), 3);
+    assert_eq!(
+        scene.cull_stats(),
+        CullStats {
+            visible: 1,
+            culled: 2
+        }
+    );
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use cgmath::{Deg, Quaternion, Rotation3, Vector3};
+use engine_rust::{
+    camera::{Camera, Projection},
+    primitives::Primitive,
+    renderer::{DepthSettings, GpuContext, HeadlessRenderer},
+    scene::{CameraComponent, Material, MeshRendererComponent, Scene, TransformComponent},
+    tonemap::Tonemapping,
+};
+use winit::dpi::PhysicalSize;
+
+const RED: [u8; 4] = [255, 0, 0, 255];
+const GREEN: [u8; 4] = [0, 255, 0, 255];
+
+/// A red quad at `z = 0.5` and a green one behind it at `z = -0.5`, facing a camera at
+/// `z = 4`. Batches draw in the order their first entity spawned, so `near_first` decides
+/// which quad is drawn first.
+fn overlapping_quads(
+    ctx: &GpuContext,
+    renderer: &HeadlessRenderer,
+    projection: Projection,
+    near_first: bool,
+) -> anyhow::Result<Scene> {
+    let mut scene = Scene::new();
+    let mesh = scene.add_primitive(
+        ctx,
+        &Primitive::Plane {
+            width: 2.0,
+            depth: 2.0,
+            subdivisions_x: 1,
+            subdivisions_z: 1,
+        },
+    );
+    let mut quads = vec![(0.5, [1.0, 0.0, 0.0, 1.0]), (-0.5, [0.0, 1.0, 0.0, 1.0])];
+    if !near_first {
+        quads.reverse();
+    }
+    for (z, color) in quads {
+        let material = scene.add_material(Material::from_color(
+            ctx,
+            renderer.material_layout(),
+            color,
+            "Quad",
+        )?);
+        let quad = scene.spawn(None, None);
+        // The plane faces +Y; turned to face the camera.
+        scene.set_transform(
+            quad,
+            TransformComponent::from_translation_rotation(
+                Vector3::new(0.0, 0.0, z),
+                Quaternion::from_angle_x(Deg(90.0)),
+            ),
+        );
+        scene.add_mesh_renderer(quad, MeshRendererComponent { mesh, material });
+    }
+
+    let camera = scene.spawn(None, None);
+    scene.add_camera(
+        camera,
+        CameraComponent::new(
+            Camera::new((0.0, 0.0, 4.0).into(), (0.0, 0.0, 0.0).into(), 1.0)
+                .with_projection(projection),
+        )
+        .with_tonemapping(Tonemapping::Linear),
+    );
+    scene.active_camera = Some(camera);
+    scene.rebuild_render_batches(ctx);
+    Ok(scene)
+}
+
+/// The nearer quad covers the farther one whichever is drawn first, with standard and
+/// reversed depth.
+#[test]
+fn nearer_quad_wins_in_either_draw_order() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let mut renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(32, 32));
+    renderer.clear_color = wgpu::Color::BLACK;
+
+    for (projection, reverse_z) in [
+        (Projection::Perspective { fovy: 45.0 }, false),
+        (Projection::InfiniteReverseZ { fovy: 45.0 }, true),
+    ] {
+        for near_first in [false, true] {
+            let mut scene = overlapping_quads(&ctx, &renderer, projection, near_first)?;
+            let image = renderer.render_to_image(&ctx, &mut scene)?;
+            assert_eq!(
+                renderer.resources.depth(),
+                DepthSettings {
+                    reverse_z,
+                    ..DepthSettings::default()
+                }
+            );
+            if !ctx.is_noop() {
+                let context = format!("{:?}, near first: {}", projection, near_first);
+                assert_eq!(image.get_pixel(16, 16).0, RED, "{}", context);
+                assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0, 255], "{}", context);
+            }
+        }
+    }
+
+    // Without the depth test the last quad drawn covers the first.
+    renderer.resources.set_depth(
+        &ctx,
+        DepthSettings {
+            compare: wgpu::CompareFunction::Always,
+            ..DepthSettings::default()
+        },
+    );
+    let projection = Projection::Perspective { fovy: 45.0 };
+    let mut scene = overlapping_quads(&ctx, &renderer, projection, true)?;
+    let image = renderer.render_to_image(&ctx, &mut scene)?;
+    if !ctx.is_noop() {
+        assert_eq!(image.get_pixel(16, 16).0, GREEN);
+    }
+    Ok(())
+}
@@ -0,0 +1,199 @@
+use std::{
+    f32::consts::FRAC_1_SQRT_2,
+    path::{Path, PathBuf},
+};
+
+use base64::Engine as _;
+use engine_rust::{
+    renderer::{GpuContext, HeadlessRenderer},
+    scene::{EntityId, MeshRendererComponent, Scene, Shading, TransformComponent},
+};
+use winit::dpi::PhysicalSize;
+
+const QUAD_POSITIONS: [[f32; 3]; 4] = [
+    [-0.5, -0.5, 0.0],
+    [0.5, -0.5, 0.0],
+    [0.5, 0.5, 0.0],
+    [-0.5, 0.5, 0.0],
+];
+const QUAD_TEX_COORDS: [[f32; 2]; 4] = [[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]];
+const QUAD_INDICES: [u16; 6] = [0, 1, 2, 0, 2, 3];
+
+/// Two nodes sharing one quad buffer: `Parent` has a textured single-primitive mesh and
+/// `Child` has a two-primitive mesh using a colored material and the default material.
+fn write_gltf(dir: &Path) -> anyhow::Result<PathBuf> {
+    let mut buffer = Vec::new();
+    buffer.extend_from_slice(bytemuck::cast_slice(&QUAD_POSITIONS));
+    buffer.extend_from_slice(bytemuck::cast_slice(&QUAD_TEX_COORDS));
+    buffer.extend_from_slice(bytemuck::cast_slice(&QUAD_INDICES));
+    let uri = format!(
+        "data:application/octet-stream;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(&buffer)
+    );
+
+    let gltf = serde_json::json!({
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "name": "Quads", "nodes": [0] }],
+        "nodes": [
+            { "name": "Parent", "mesh": 0, "translation": [1.0, 0.0, 0.0], "children": [1] },
+            {
+                "name": "Child",
+                "mesh": 1,
+                "rotation": [0.0, FRAC_1_SQRT_2, 0.0, FRAC_1_SQRT_2],
+                "scale": [2.0, 2.0, 2.0]
+            }
+        ],
+        "meshes": [
+            { "primitives": [{ "attributes": { "POSITION": 0, "TEXCOORD_0": 1 }, "indices": 2, "material": 0 }] },
+            {
+                "primitives": [
+                    { "attributes": { "POSITION": 0 }, "indices": 2, "material": 1 },
+                    { "attributes": { "POSITION": 0 }, "indices": 2 }
+                ]
+            }
+        ],
+        "materials": [
+            { "pbrMetallicRoughness": { "baseColorTexture": { "index": 0 } } },
+            { "pbrMetallicRoughness": { "baseColorFactor": [1.0, 0.0, 0.0, 1.0] } }
+        ],
+        "textures": [{ "source": 0 }],
+        "images": [{ "uri": "happy-tree.png" }],
+        "buffers": [{ "byteLength": buffer.len(), "uri": uri }],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 48 },
+            { "buffer": 0, "byteOffset": 48, "byteLength": 32 },
+            { "buffer": 0, "byteOffset": 80, "byteLength": 12 }
+        ],
+        "accessors": [
+            {
+                "bufferView": 0,
+                "componentType": 5126,
+                "count": 4,
+                "type": "VEC3",
+                "min": [-0.5, -0.5, 0.0],
+                "max": [0.5, 0.5, 0.0]
+            },
+            { "bufferView": 1, "componentType": 5126, "count": 4, "type": "VEC2" },
+            { "bufferView": 2, "componentType": 5123, "count": 6, "type": "SCALAR" }
+        ]
+    });
+
+    std::fs::copy(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("src/happy-tree.png"),
+        dir.join("happy-tree.png"),
+    )?;
+    let path = dir.join("quads.gltf");
+    std::fs::write(&path, serde_json::to_vec_pretty(&gltf)?)?;
+    Ok(path)
+}
+
+fn child_named(scene: &Scene, parent: EntityId, name: &str) -> EntityId {
+    scene.entities[&parent]
+        .children
+        .iter()
+        .copied()
+        .find(|child| scene.entities[child].name.as_deref() == Some(name))
+        .unwrap_or_else(|| panic!("no child named {}", name))
+}
+
+#[test]
+fn imports_hierarchy_meshes_and_materials() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("engine-rust-gltf-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = write_gltf(&dir)?;
+
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(64, 48));
+    let layout = renderer.material_layout();
+    let mut scene = Scene::new();
+
+    let root = scene.import_gltf(&ctx, layout, &path, None)?;
+    assert_eq!(scene.entities[&root].name.as_deref(), Some("Quads"));
+    assert_eq!(scene.meshes.len(), 3);
+    assert_eq!(scene.materials.len(), 3);
+    assert!(scene
+        .materials
+        .iter()
+        .all(|material| material.shading == Shading::Pbr));
+
+    let parent = child_named(&scene, root, "Parent");
+    let child = child_named(&scene, parent, "Child");
+    assert!(scene.has::<MeshRendererComponent>(parent));
+    assert!(!scene.has::<MeshRendererComponent>(child));
+    assert_eq!(scene.entities[&child].children.len(), 2);
+    for primitive in &scene.entities[&child].children {
+        assert!(scene.has::<MeshRendererComponent>(*primitive));
+    }
+
+    let transform = scene.get::<TransformComponent>(child).unwrap();
+    assert_eq!(transform.scale, cgmath::Vector3::new(2.0, 2.0, 2.0));
+    scene.propagate_transforms();
+    let world = scene.world_transform(child).unwrap();
+    assert!((world.w.x - 1.0).abs() < 1e-6);
+
+    scene.import_gltf(&ctx, layout, &path, None)?;
+    assert_eq!(scene.meshes.len(), 3, "meshes are shared between imports");
+    assert_eq!(
+        scene.materials.len(),
+        3,
+        "materials are shared between imports"
+    );
+
+    scene.update_render_batches(&ctx);
+    let instances: u32 = scene
+        .render_batches
+        .iter()
+        .map(|batch| batch.instance_count)
+        .sum();
+    assert_eq!(instances, 6);
+
+    let saved = dir.join("scene.ron");
+    scene.save(&saved)?;
+    let loaded = Scene::load(&ctx, layout, &saved, 1.0)?;
+    assert_eq!(loaded.meshes.len(), 3);
+    assert_eq!(loaded.to_description()?, scene.to_description()?);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn saved_scenes_find_files_imported_through_relative_paths() -> anyhow::Result<()> {
+    // Relative to the package root, which tests run in.
+    let dir = Path::new("target").join(format!("engine-rust-gltf-paths-{}", std::process::id()));
+    let models = dir.join("models");
+    let scenes = dir.join("scenes");
+    let copies = dir.join("copies");
+    for dir in [&models, &scenes, &copies] {
+        std::fs::create_dir_all(dir)?;
+    }
+    let path = write_gltf(&models)?;
+    assert!(path.is_relative());
+
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(64, 48));
+    let layout = renderer.material_layout();
+    let mut scene = Scene::new();
+    scene.import_gltf(&ctx, layout, &path, None)?;
+
+    let saved = scenes.join("scene.ron");
+    scene.save(&saved)?;
+    assert!(std::fs::read_to_string(&saved)?.contains("\"../models/quads.gltf#mesh0/primitive0\""));
+    let loaded = Scene::load(&ctx, layout, &saved, 1.0)?;
+    assert_eq!(loaded.meshes.len(), 3);
+    assert_eq!(loaded.materials.len(), 3);
+
+    // Paths of a loaded scene are rebased again when it is saved somewhere else.
+    let copy = copies.join("scene.ron");
+    loaded.save(&copy)?;
+    assert_eq!(
+        std::fs::read_to_string(&copy)?,
+        std::fs::read_to_string(&saved)?
+    );
+    let reloaded = Scene::load(&ctx, layout, &copy, 1.0)?;
+    assert_eq!(reloaded.meshes.len(), 3);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
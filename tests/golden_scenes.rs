@@ -0,0 +1,269 @@
+use cgmath::{Deg, Quaternion, Rotation3, Vector3};
+use engine_rust::{
+    camera::Camera,
+    golden::GoldenTest,
+    light::LightComponent,
+    pbr::PbrMaterial,
+    primitives::Primitive,
+    scene::{CameraComponent, Material, MeshRendererComponent, Scene, Shading, TransformComponent},
+    shadow::ShadowFlags,
+};
+
+#[test]
+fn default_instanced_matches_golden() -> anyhow::Result<()> {
+    GoldenTest::new("default_instanced").run(Scene::default_instanced)
+}
+
+/// A sphere and a cube on a plane, lit by one light of each kind.
+#[test]
+fn lit_primitives_matches_golden() -> anyhow::Result<()> {
+    GoldenTest::new("lit_primitives").run(|ctx, layout, aspect| {
+        let mut scene = Scene::new();
+        scene.ambient_light = [0.05, 0.05, 0.05];
+
+        let objects = [
+            (
+                Primitive::Plane {
+                    width: 8.0,
+                    depth: 8.0,
+                    subdivisions_x: 1,
+                    subdivisions_z: 1,
+                },
+                [0.8, 0.8, 0.8, 1.0],
+                [0.0, 0.0, 0.0],
+            ),
+            (
+                Primitive::uv_sphere(0.75),
+                [0.9, 0.2, 0.2, 1.0],
+                [-1.0, 0.75, 0.0],
+            ),
+            (Primitive::cube(1.0), [0.2, 0.4, 0.9, 1.0], [1.0, 0.5, 0.0]),
+        ];
+        for (primitive, color, translation) in &objects {
+            let mesh = scene.add_primitive(ctx, primitive);
+            let material = scene.add_material(
+                Material::from_color(ctx, layout, *color, "Lit")?.with_shading(Shading::Lit),
+            );
+            let entity = scene.spawn(None, None);
+            scene.set_transform(
+                entity,
+                TransformComponent::from_translation_rotation(
+                    (*translation).into(),
+                    Quaternion::from_angle_y(Deg(30.0)),
+                ),
+            );
+            scene.add_mesh_renderer(entity, MeshRendererComponent { mesh, material });
+        }
+
+        let lights = [
+            (
+                LightComponent::directional([1.0, 0.95, 0.9], 0.6),
+                Vector3::new(0.0, 0.0, 0.0),
+                Quaternion::from_angle_x(Deg(-60.0)),
+            ),
+            (
+                LightComponent::point([0.2, 1.0, 0.2], 4.0, 6.0),
+                Vector3::new(2.5, 1.5, 1.5),
+                Quaternion::from_angle_x(Deg(0.0)),
+            ),
+            (
+                LightComponent::spot([1.0, 0.8, 0.4], 12.0, 10.0, 15.0, 25.0),
+                Vector3::new(-1.0, 4.0, 0.5),
+                Quaternion::from_angle_x(Deg(-90.0)),
+            ),
+        ];
+        for (light, translation, rotation) in lights.iter().copied() {
+            let entity = scene.spawn(None, None);
+            scene.set_transform(
+                entity,
+                TransformComponent::from_translation_rotation(translation, rotation),
+            );
+            scene.add_light(entity, light);
+        }
+
+        let camera = scene.spawn(Some("Camera".into()), None);
+        scene.add_camera(
+            camera,
+            CameraComponent::new(Camera::new(
+                (0.0, 3.5, 6.0).into(),
+                (0.0, 0.5, 0.0).into(),
+                aspect,
+            )),
+        );
+        scene.active_camera = Some(camera);
+        scene.rebuild_render_batches(ctx);
+        Ok(scene)
+    })
+}
+
+/// Spheres from dielectric to metal (left to right) and smooth to rough (top to bottom).
+#[test]
+fn pbr_spheres_matches_golden() -> anyhow::Result<()> {
+    GoldenTest::new("pbr_spheres").run(|ctx, layout, aspect| {
+        let mut scene = Scene::new();
+        scene.ambient_light = [0.03, 0.03, 0.03];
+        let sphere = scene.add_primitive(ctx, &Primitive::uv_sphere(0.4));
+
+        for row in 0..3 {
+            for column in 0..4 {
+                let material = scene.add_material(Material::pbr(
+                    ctx,
+                    layout,
+                    PbrMaterial {
+                        base_color_factor: [0.9, 0.5, 0.2, 1.0],
+                        metallic_factor: column as f32 / 3.0,
+                        roughness_factor: 0.2 + row as f32 * 0.4,
+                        ..Default::default()
+                    },
+                    "Sphere",
+                )?);
+                let entity = scene.spawn(None, None);
+                scene.set_transform(
+                    entity,
+                    TransformComponent::from_translation_rotation(
+                        Vector3::new(column as f32 - 1.5, 1.0 - row as f32, 0.0),
+                        Quaternion::from_angle_y(Deg(0.0)),
+                    ),
+                );
+                scene.add_mesh_renderer(
+                    entity,
+                    MeshRendererComponent {
+                        mesh: sphere,
+                        material,
+                    },
+                );
+            }
+        }
+
+        let sun = scene.spawn(None, None);
+        scene.set_transform(
+            sun,
+            TransformComponent::from_translation_rotation(
+                Vector3::new(0.0, 0.0, 0.0),
+                Quaternion::from_angle_y(Deg(-30.0)) * Quaternion::from_angle_x(Deg(-35.0)),
+            ),
+        );
+        scene.add_light(sun, LightComponent::directional([1.0, 1.0, 1.0], 3.0));
+
+        let camera = scene.spawn(Some("Camera".into()), None);
+        scene.add_camera(
+            camera,
+            CameraComponent::new(Camera::new(
+                (0.0, 0.0, 5.0).into(),
+                (0.0, 0.0, 0.0).into(),
+                aspect,
+            )),
+        );
+        scene.active_camera = Some(camera);
+        scene.rebuild_render_batches(ctx);
+        Ok(scene)
+    })
+}
+
+/// A cube casting onto a plane from a sun and a spot light, a sphere that casts no shadow
+/// and a floating plane that receives none.
+#[test]
+fn shadows_match_golden() -> anyhow::Result<()> {
+    GoldenTest::new("shadows").run(|ctx, layout, aspect| {
+        let mut scene = Scene::new();
+        scene.ambient_light = [0.1, 0.1, 0.1];
+
+        let objects = [
+            (
+                Primitive::Plane {
+                    width: 10.0,
+                    depth: 10.0,
+                    subdivisions_x: 1,
+                    subdivisions_z: 1,
+                },
+                [0.8, 0.8, 0.8, 1.0],
+                [0.0, 0.0, 0.0],
+                ShadowFlags::default(),
+            ),
+            (
+                Primitive::cube(1.0),
+                [0.2, 0.4, 0.9, 1.0],
+                [0.0, 0.5, 0.0],
+                ShadowFlags::default(),
+            ),
+            (
+                Primitive::uv_sphere(0.5),
+                [0.9, 0.2, 0.2, 1.0],
+                [-2.0, 0.5, 0.0],
+                ShadowFlags {
+                    cast: false,
+                    receive: true,
+                },
+            ),
+            (
+                Primitive::Plane {
+                    width: 1.0,
+                    depth: 1.0,
+                    subdivisions_x: 1,
+                    subdivisions_z: 1,
+                },
+                [0.9, 0.9, 0.2, 1.0],
+                [1.8, 0.3, 1.2],
+                ShadowFlags {
+                    cast: true,
+                    receive: false,
+                },
+            ),
+        ];
+        for (primitive, color, translation, flags) in objects.iter() {
+            let mesh = scene.add_primitive(ctx, primitive);
+            let material = scene.add_material(
+                Material::from_color(ctx, layout, *color, "Lit")?.with_shading(Shading::Lit),
+            );
+            let entity = scene.spawn(None, None);
+            scene.set_transform(
+                entity,
+                TransformComponent::from_translation_rotation(
+                    (*translation).into(),
+                    Quaternion::from_angle_y(Deg(0.0)),
+                ),
+            );
+            scene.add_mesh_renderer(entity, MeshRendererComponent { mesh, material });
+            scene.set_shadow_flags(entity, *flags);
+        }
+
+        let sun = scene.spawn(Some("Sun".into()), None);
+        scene.set_transform(
+            sun,
+            TransformComponent::from_translation_rotation(
+                Vector3::new(0.0, 0.0, 0.0),
+                Quaternion::from_angle_y(Deg(40.0)) * Quaternion::from_angle_x(Deg(-50.0)),
+            ),
+        );
+        scene.add_light(
+            sun,
+            LightComponent::directional([1.0, 1.0, 1.0], 0.8).with_shadows(true),
+        );
+
+        let spot = scene.spawn(Some("Spot".into()), None);
+        scene.set_transform(
+            spot,
+            TransformComponent::from_translation_rotation(
+                Vector3::new(2.5, 3.0, 0.0),
+                Quaternion::from_angle_z(Deg(-35.0)) * Quaternion::from_angle_x(Deg(-90.0)),
+            ),
+        );
+        scene.add_light(
+            spot,
+            LightComponent::spot([1.0, 0.6, 0.3], 12.0, 12.0, 20.0, 30.0).with_shadows(true),
+        );
+
+        let camera = scene.spawn(Some("Camera".into()), None);
+        scene.add_camera(
+            camera,
+            CameraComponent::new(Camera::new(
+                (0.0, 4.0, 7.0).into(),
+                (0.0, 0.0, 0.0).into(),
+                aspect,
+            )),
+        );
+        scene.active_camera = Some(camera);
+        scene.rebuild_render_batches(ctx);
+        Ok(scene)
+    })
+}
@@ -0,0 +1,228 @@
+use cgmath::{Deg, Point3, Quaternion, Rotation3, Vector3};
+use engine_rust::{
+    camera::Camera,
+    culling::CullStats,
+    gpu_culling::GpuCulling,
+    light::LightComponent,
+    primitives::Primitive,
+    renderer::{GpuContext, HeadlessRenderer},
+    scene::{
+        CameraComponent, Material, Mesh, MeshRendererComponent, Scene, Shading, TransformComponent,
+    },
+    shadow::ShadowFlags,
+    tonemap::Tonemapping,
+};
+use wgpu::util::DrawIndexedIndirectArgs;
+use winit::dpi::PhysicalSize;
+
+/// A row of ten pentagons 3 units apart with the camera looking at the middle one.
+fn row_scene(ctx: &GpuContext, renderer: &HeadlessRenderer) -> anyhow::Result<Scene> {
+    let mut scene = Scene::new();
+    let mesh = scene.add_mesh(Mesh::pentagon(ctx));
+    let material = scene.add_material(Material::from_color(
+        ctx,
+        renderer.material_layout(),
+        [1.0; 4],
+        "White",
+    )?);
+    for x in -5..5 {
+        let entity = scene.spawn(None, None);
+        scene.set_transform(
+            entity,
+            TransformComponent::from_translation_rotation(
+                Vector3::new(x as f32 * 3.0, 0.0, 0.0),
+                TransformComponent::identity().rotation,
+            ),
+        );
+        scene.add_mesh_renderer(entity, MeshRendererComponent { mesh, material });
+    }
+    let camera = scene.spawn(None, None);
+    scene.add_camera(
+        camera,
+        CameraComponent::new(Camera::new(
+            Point3::new(0.0, 0.0, 5.0),
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+        ))
+        .with_tonemapping(Tonemapping::Linear),
+    );
+    scene.active_camera = Some(camera);
+    scene.rebuild_render_batches(ctx);
+    Ok(scene)
+}
+
+/// A lit cube on a plane under a shadow-casting sun.
+fn shadow_scene(
+    ctx: &GpuContext,
+    renderer: &HeadlessRenderer,
+    cube_flags: ShadowFlags,
+) -> anyhow::Result<Scene> {
+    let mut scene = Scene::new();
+    scene.ambient_light = [0.1, 0.1, 0.1];
+    let plane = Primitive::Plane {
+        width: 10.0,
+        depth: 10.0,
+        subdivisions_x: 1,
+        subdivisions_z: 1,
+    };
+    let objects = [
+        (plane, [0.0, 0.0, 0.0], ShadowFlags::default()),
+        (Primitive::cube(1.0), [0.0, 0.5, 0.0], cube_flags),
+    ];
+    for (primitive, translation, flags) in objects.iter() {
+        let mesh = scene.add_primitive(ctx, primitive);
+        let material = scene.add_material(
+            Material::from_color(ctx, renderer.material_layout(), [0.8; 4], "Lit")?
+                .with_shading(Shading::Lit),
+        );
+        let entity = scene.spawn(None, None);
+        scene.set_transform(
+            entity,
+            TransformComponent::from_translation_rotation(
+                (*translation).into(),
+                TransformComponent::identity().rotation,
+            ),
+        );
+        scene.add_mesh_renderer(entity, MeshRendererComponent { mesh, material });
+        scene.set_shadow_flags(entity, *flags);
+    }
+
+    let sun = scene.spawn(None, None);
+    scene.set_transform(
+        sun,
+        TransformComponent::from_translation_rotation(
+            Vector3::new(0.0, 0.0, 0.0),
+            Quaternion::from_angle_y(Deg(40.0)) * Quaternion::from_angle_x(Deg(-50.0)),
+        ),
+    );
+    scene.add_light(
+        sun,
+        LightComponent::directional([1.0, 1.0, 1.0], 0.8).with_shadows(true),
+    );
+
+    let camera = scene.spawn(None, None);
+    scene.add_camera(
+        camera,
+        CameraComponent::new(Camera::new(
+            Point3::new(0.0, 4.0, 7.0),
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+        )),
+    );
+    scene.active_camera = Some(camera);
+    scene.rebuild_render_batches(ctx);
+    Ok(scene)
+}
+
+fn read_draw_args(ctx: &GpuContext, indirect: &wgpu::Buffer) -> anyhow::Result<[u32; 5]> {
+    let size = std::mem::size_of::<DrawIndexedIndirectArgs>() as wgpu::BufferAddress;
+    let readback = ctx.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("draw args readback"),
+        size,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = ctx
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    encoder.copy_buffer_to_buffer(indirect, 0, &readback, 0, size);
+    ctx.queue.submit(Some(encoder.finish()));
+    readback.slice(..).map_async(wgpu::MapMode::Read, |_| {});
+    ctx.device.poll(wgpu::PollType::wait_indefinitely())?;
+    let args = *bytemuck::from_bytes(&readback.slice(..).get_mapped_range());
+    Ok(args)
+}
+
+#[test]
+fn compute_culling_matches_the_cpu_path() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let mut renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(32, 32));
+    renderer.clear_color = wgpu::Color::BLACK;
+    let mut scene = row_scene(&ctx, &renderer)?;
+    if !GpuCulling::is_supported(&ctx) {
+        assert!(renderer.resources.gpu_culling().is_none());
+        assert!(renderer.resources.set_gpu_culling(&ctx, true).is_err());
+        return Ok(());
+    }
+
+    let gpu = renderer.render_to_image(&ctx, &mut scene)?;
+    let (_, indirect) = renderer.resources.gpu_culling().unwrap().batch(0).unwrap();
+    let args = read_draw_args(&ctx, indirect)?;
+    // The next frame collects the counts the first one read back.
+    ctx.device.poll(wgpu::PollType::wait_indefinitely())?;
+    renderer.render(&ctx, &mut scene)?;
+    let gpu_stats = renderer.resources.cull_stats(&scene);
+
+    renderer.resources.set_gpu_culling(&ctx, false)?;
+    assert!(renderer.resources.gpu_culling().is_none());
+    let cpu = renderer.render_to_image(&ctx, &mut scene)?;
+
+    if !ctx.is_noop() {
+        // Index count, then the single visible instance.
+        assert_eq!(args[..2], [10, 1]);
+        assert_eq!(
+            scene.cull_stats(),
+            CullStats {
+                visible: 1,
+                culled: 9
+            }
+        );
+        assert_eq!(gpu_stats, scene.cull_stats());
+        assert_eq!(gpu.get_pixel(16, 16).0, [255, 255, 255, 255]);
+        assert_eq!(gpu.as_raw(), cpu.as_raw());
+    }
+    Ok(())
+}
+
+#[test]
+fn compute_culling_keeps_shadow_flags() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let mut renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(64, 64));
+    if !GpuCulling::is_supported(&ctx) {
+        return Ok(());
+    }
+
+    let mut scene = shadow_scene(&ctx, &renderer, ShadowFlags::default())?;
+    let mut unshadowed = shadow_scene(
+        &ctx,
+        &renderer,
+        ShadowFlags {
+            cast: false,
+            receive: true,
+        },
+    )?;
+    let gpu = renderer.render_to_image(&ctx, &mut scene)?;
+    let gpu_unshadowed = renderer.render_to_image(&ctx, &mut unshadowed)?;
+    renderer.resources.set_gpu_culling(&ctx, false)?;
+    let cpu = renderer.render_to_image(&ctx, &mut scene)?;
+
+    if !ctx.is_noop() {
+        // The culled instances still carry their flags, so the cube's shadow lands.
+        assert_ne!(gpu.as_raw(), gpu_unshadowed.as_raw());
+        assert_eq!(gpu.as_raw(), cpu.as_raw());
+    }
+    Ok(())
+}
+
+#[test]
+fn gpu_culled_scenes_skip_the_cpu_cull() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(8, 8));
+    let mut scene = row_scene(&ctx, &renderer)?;
+    let culled = scene.cull_stats();
+    assert_eq!(culled.total(), 10);
+
+    scene.set_gpu_culled(true);
+    let entity = scene.spawn(None, None);
+    scene.set_transform(entity, TransformComponent::identity());
+    let batch = scene.render_batches.iter().next().unwrap();
+    let (mesh, material) = (batch.mesh, batch.material);
+    scene.add_mesh_renderer(entity, MeshRendererComponent { mesh, material });
+    scene.update_render_batches(&ctx);
+    assert_eq!(scene.cull_stats(), culled);
+
+    // Culling on request still works, and catches up with the skipped change.
+    scene.cull_render_batches(&ctx);
+    assert_eq!(scene.cull_stats().total(), 11);
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use engine_rust::{
+    camera::Camera,
+    renderer::{GpuContext, HeadlessRenderer},
+    scene::{
+        CameraComponent, Material, Mesh, MeshRendererComponent, Scene, TransformComponent, Vertex,
+    },
+    tonemap::Tonemapping,
+};
+use winit::dpi::PhysicalSize;
+
+#[test]
+fn smallest_index_format_fits_vertex_count() {
+    assert_eq!(Mesh::smallest_index_format(0), wgpu::IndexFormat::Uint16);
+    assert_eq!(
+        Mesh::smallest_index_format(65_536),
+        wgpu::IndexFormat::Uint16
+    );
+    assert_eq!(
+        Mesh::smallest_index_format(65_537),
+        wgpu::IndexFormat::Uint32
+    );
+}
+
+/// A quad stored after 70 000 unused vertices, so drawing it needs 32-bit indices.
+#[test]
+fn renders_mesh_with_32_bit_indices() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let mut renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(64, 48));
+    let layout = renderer.material_layout();
+
+    let mut vertices = vec![
+        Vertex {
+            position: [0.0; 3],
+            tex_coords: [0.0; 2],
+            normal: [0.0, 0.0, 1.0],
+            tangent: [1.0, 0.0, 0.0, 1.0],
+        };
+        70_000
+    ];
+    let first = vertices.len() as u32;
+    for position in [
+        [-1.0, -1.0, 0.0],
+        [1.0, -1.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [-1.0, 1.0, 0.0],
+    ] {
+        vertices.push(Vertex {
+            position,
+            tex_coords: [0.0; 2],
+            normal: [0.0, 0.0, 1.0],
+            tangent: [1.0, 0.0, 0.0, 1.0],
+        });
+    }
+    let indices: Vec<u32> = [0, 1, 2, 0, 2, 3].iter().map(|i| first + i).collect();
+
+    let mesh = Mesh::with_smallest_indices(&ctx, "Large", &vertices, &indices);
+    assert_eq!(mesh.index_format, wgpu::IndexFormat::Uint32);
+    assert_eq!(Mesh::pentagon(&ctx).index_format, wgpu::IndexFormat::Uint16);
+
+    let mut scene = Scene::new();
+    let mesh = scene.add_mesh(mesh);
+    let material = scene.add_material(Material::from_color(
+        &ctx,
+        layout,
+        [1.0, 0.0, 0.0, 1.0],
+        "Red",
+    )?);
+    let quad = scene.spawn(None, None);
+    scene.set_transform(quad, TransformComponent::identity());
+    scene.add_mesh_renderer(quad, MeshRendererComponent { mesh, material });
+
+    let camera = scene.spawn(None, None);
+    scene.add_camera(
+        camera,
+        CameraComponent::new(Camera::new(
+            (0.0, 0.0, 3.0).into(),
+            (0.0, 0.0, 0.0).into(),
+            renderer.target.aspect(),
+        ))
+        .with_tonemapping(Tonemapping::Linear),
+    );
+    scene.active_camera = Some(camera);
+    scene.rebuild_render_batches(&ctx);
+
+    let image = renderer.render_to_image(&ctx, &mut scene)?;
+    if !ctx.is_noop() {
+        assert_eq!(image.get_pixel(32, 24).0, [255, 0, 0, 255]);
+    }
+    Ok(())
+}
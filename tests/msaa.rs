@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+
+use engine_rust::{
+    camera::Camera,
+    renderer::{GpuContext, HeadlessRenderer},
+    scene::{CameraComponent, Material, Mesh, MeshRendererComponent, Scene, TransformComponent},
+    tonemap::Tonemapping,
+};
+use winit::dpi::PhysicalSize;
+
+/// A white pentagon on black.
+fn pentagon_scene(ctx: &GpuContext, renderer: &mut HeadlessRenderer) -> anyhow::Result<Scene> {
+    renderer.clear_color = wgpu::Color::BLACK;
+    let mut scene = Scene::new();
+    let mesh = scene.add_mesh(Mesh::pentagon(ctx));
+    let material = scene.add_material(Material::from_color(
+        ctx,
+        renderer.material_layout(),
+        [1.0, 1.0, 1.0, 1.0],
+        "White",
+    )?);
+    let pentagon = scene.spawn(None, None);
+    scene.set_transform(pentagon, TransformComponent::identity());
+    scene.add_mesh_renderer(pentagon, MeshRendererComponent { mesh, material });
+
+    let camera = scene.spawn(None, None);
+    scene.add_camera(
+        camera,
+        CameraComponent::new(Camera::new(
+            (0.0, 0.0, 2.0).into(),
+            (0.0, 0.0, 0.0).into(),
+            renderer.target.aspect(),
+        ))
+        .with_tonemapping(Tonemapping::Linear),
+    );
+    scene.active_camera = Some(camera);
+    scene.rebuild_render_batches(ctx);
+    Ok(scene)
+}
+
+fn distinct_reds(image: &image::RgbaImage) -> usize {
+    image
+        .pixels()
+        .map(|pixel| pixel[0])
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+#[test]
+fn unsupported_sample_counts_are_rejected() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let mut renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(8, 8));
+    let supported = renderer.resources.supported_msaa_samples(&ctx);
+    assert!(supported.contains(&1));
+    assert!(supported
+        .iter()
+        .all(|samples| [1, 2, 4, 8].contains(samples)));
+
+    assert!(renderer.resources.set_msaa_samples(&ctx, 3).is_err());
+    assert!(renderer.resources.set_msaa_samples(&ctx, 16).is_err());
+    assert_eq!(renderer.resources.msaa_samples(), 1);
+    Ok(())
+}
+
+/// Edges pick up in-between shades with MSAA and lose them again when it is switched off,
+/// including across resizes.
+#[test]
+fn msaa_smooths_edges_and_switches_at_runtime() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let mut renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(48, 48));
+    let mut scene = pentagon_scene(&ctx, &mut renderer)?;
+    if !renderer.resources.supported_msaa_samples(&ctx).contains(&4) {
+        return Ok(());
+    }
+
+    let aliased = renderer.render_to_image(&ctx, &mut scene)?;
+    renderer.resources.set_msaa_samples(&ctx, 4)?;
+    assert_eq!(renderer.resources.msaa_samples(), 4);
+    let smoothed = renderer.render_to_image(&ctx, &mut scene)?;
+    renderer.resize(&ctx, PhysicalSize::new(64, 64));
+    let resized = renderer.render_to_image(&ctx, &mut scene)?;
+    renderer.resources.set_msaa_samples(&ctx, 1)?;
+    let switched_off = renderer.render_to_image(&ctx, &mut scene)?;
+
+    if !ctx.is_noop() {
+        assert_eq!(distinct_reds(&aliased), 2);
+        assert!(distinct_reds(&smoothed) > 2);
+        assert!(distinct_reds(&resized) > 2);
+        assert_eq!(resized.get_pixel(32, 32).0, [255, 255, 255, 255]);
+        assert_eq!(distinct_reds(&switched_off), 2);
+    }
+    Ok(())
+}
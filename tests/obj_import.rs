@@ -0,0 +1,131 @@
+use std::path::{Path, PathBuf};
+
+use engine_rust::{
+    renderer::{GpuContext, HeadlessRenderer},
+    scene::{MeshRendererComponent, Scene},
+};
+use winit::dpi::PhysicalSize;
+
+const CUBE_MTL: &str = "\
+newmtl Textured
+map_Kd happy-tree.png
+
+newmtl Red
+Kd 1.0 0.0 0.0
+";
+
+/// An untextured group without a material, a textured quad and a red quad plus triangle
+/// that share positions but not texture coordinates.
+const CUBE_OBJ: &str = "\
+mtllib cube.mtl
+v -1 -1 0
+v 1 -1 0
+v 1 1 0
+v -1 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vt 0.5 0.5
+
+g Untextured
+f 1 2 3
+
+o Front
+usemtl Textured
+f 1/1 2/2 3/3 4/4
+
+o Side
+usemtl Red
+f 1/1 2/2 3/3 4/4
+f 1/5 3/5 4/5
+";
+
+fn write_obj(dir: &Path) -> anyhow::Result<PathBuf> {
+    std::fs::copy(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("src/happy-tree.png"),
+        dir.join("happy-tree.png"),
+    )?;
+    std::fs::write(dir.join("cube.mtl"), CUBE_MTL)?;
+    let path = dir.join("cube.obj");
+    std::fs::write(&path, CUBE_OBJ)?;
+    Ok(path)
+}
+
+#[test]
+fn imports_one_entity_per_object() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("engine-rust-obj-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = write_obj(&dir)?;
+
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(64, 48));
+    let layout = renderer.material_layout();
+    let mut scene = Scene::new();
+
+    let root = scene.import_obj(&ctx, layout, &path, None)?;
+    assert_eq!(scene.entities[&root].name.as_deref(), Some("cube"));
+
+    let children = &scene.entities[&root].children;
+    let names: Vec<_> = children
+        .iter()
+        .map(|child| scene.entities[child].name.as_deref())
+        .collect();
+    assert_eq!(names, [Some("Untextured"), Some("Front"), Some("Side")]);
+    for child in children {
+        assert!(scene.has::<MeshRendererComponent>(*child));
+    }
+
+    // Quads are split into two triangles; the side shares positions with different UVs.
+    let index_counts: Vec<_> = scene.meshes.iter().map(|mesh| mesh.index_count).collect();
+    assert_eq!(index_counts, [3, 6, 9]);
+    assert_eq!(scene.materials.len(), 3);
+
+    let saved = dir.join("scene.json");
+    scene.save(&saved)?;
+    let loaded = Scene::load(&ctx, layout, &saved, 1.0)?;
+    assert_eq!(loaded.to_description()?, scene.to_description()?);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn saved_scenes_find_files_imported_through_relative_paths() -> anyhow::Result<()> {
+    // Relative to the package root, which tests run in.
+    let dir = Path::new("target").join(format!("engine-rust-obj-paths-{}", std::process::id()));
+    let models = dir.join("models");
+    let scenes = dir.join("scenes");
+    let copies = dir.join("copies");
+    for dir in [&models, &scenes, &copies] {
+        std::fs::create_dir_all(dir)?;
+    }
+    let path = write_obj(&models)?;
+    assert!(path.is_relative());
+
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(64, 48));
+    let layout = renderer.material_layout();
+    let mut scene = Scene::new();
+    scene.import_obj(&ctx, layout, &path, None)?;
+
+    let saved = scenes.join("scene.json");
+    scene.save(&saved)?;
+    assert!(std::fs::read_to_string(&saved)?.contains("\"../models/cube.obj#model1\""));
+    let loaded = Scene::load(&ctx, layout, &saved, 1.0)?;
+    assert_eq!(loaded.meshes.len(), 3);
+    assert_eq!(loaded.materials.len(), 3);
+
+    // Paths of a loaded scene are rebased again when it is saved somewhere else.
+    let copy = copies.join("scene.json");
+    loaded.save(&copy)?;
+    assert_eq!(
+        std::fs::read_to_string(&copy)?,
+        std::fs::read_to_string(&saved)?
+    );
+    let reloaded = Scene::load(&ctx, layout, &copy, 1.0)?;
+    assert_eq!(reloaded.meshes.len(), 3);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,24 @@
+use engine_rust::{
+    pbr::PbrMaterial,
+    renderer::{GpuContext, HeadlessRenderer},
+    scene::{Material, Shading},
+};
+use winit::dpi::PhysicalSize;
+
+#[test]
+fn materials_share_the_layout_defaults() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(4, 4));
+    let layout = renderer.material_layout();
+
+    let first = Material::pbr(&ctx, layout, PbrMaterial::default(), "First")?;
+    let second = Material::pbr(&ctx, layout, PbrMaterial::plain(), "Second")?;
+    // Neither has a base color texture, so both fall back to the same white one.
+    assert_eq!(first.texture.texture, second.texture.texture);
+    assert_eq!(first.shading, Shading::Pbr);
+
+    let color = Material::from_color(&ctx, layout, [0.5, 0.5, 0.5, 1.0], "Gray")?;
+    assert_ne!(color.texture.texture, first.texture.texture);
+    assert_eq!(color.shading, Shading::Unlit);
+    Ok(())
+}
@@ -0,0 +1,149 @@
+use cgmath::{InnerSpace, MetricSpace, Point3, Vector3};
+use engine_rust::{
+    camera::{Camera, Projection},
+    picking::{Ray, RaycastMode},
+    primitives::MeshData,
+    renderer::{GpuContext, HeadlessRenderer},
+    scene::{CollisionIndices, Material, MeshRendererComponent, Scene, TransformComponent},
+};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+fn assert_near(actual: Vector3<f32>, expected: Vector3<f32>) {
+    assert!(
+        (actual - expected).magnitude() < 1e-4,
+        "{:?} != {:?}",
+        actual,
+        expected
+    );
+}
+
+/// A pentagon in the XY plane with its bounds spanning -0.5..0.5 on both axes.
+fn pentagon() -> MeshData {
+    let positions = vec![
+        [0.0, 0.5, 0.0],
+        [-0.5, 0.1, 0.0],
+        [-0.3, -0.5, 0.0],
+        [0.3, -0.5, 0.0],
+        [0.5, 0.1, 0.0],
+    ];
+    MeshData {
+        tex_coords: vec![[0.0; 2]; positions.len()],
+        positions,
+        indices: vec![0, 1, 4, 1, 2, 4, 2, 3, 4],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn screen_points_unproject_into_rays() {
+    let size = PhysicalSize::new(200, 100);
+    let camera = Camera::new(Point3::new(0.0, 0.0, 5.0), Point3::new(0.0, 0.0, 0.0), 2.0);
+
+    let center = camera.screen_ray(PhysicalPosition::new(100.0, 50.0), size);
+    assert_near(center.direction, Vector3::new(0.0, 0.0, -1.0));
+    assert!(center.origin.distance(Point3::new(0.0, 0.0, 4.9)) < 1e-3);
+
+    // The top right corner at 45 degrees vertically and an aspect of 2.
+    let corner = camera.screen_ray(PhysicalPosition::new(200.0, 0.0), size);
+    let half_height = (22.5_f32).to_radians().tan();
+    assert_near(
+        corner.direction,
+        Vector3::new(2.0 * half_height, half_height, -1.0).normalize(),
+    );
+
+    let infinite = camera.with_projection(Projection::InfiniteReverseZ { fovy: 45.0 });
+    let ray = infinite.screen_ray(PhysicalPosition::new(200.0, 0.0), size);
+    assert_near(ray.direction, corner.direction);
+    assert!(ray.origin.distance(corner.origin) < 1e-3);
+
+    let orthographic = camera.with_projection(Projection::Orthographic { height: 4.0 });
+    let ray = orthographic.screen_ray(PhysicalPosition::new(200.0, 0.0), size);
+    assert_near(ray.direction, Vector3::new(0.0, 0.0, -1.0));
+    assert!((ray.origin.x - 4.0).abs() < 1e-3 && (ray.origin.y - 2.0).abs() < 1e-3);
+}
+
+#[test]
+fn raycasts_return_sorted_hits() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(8, 8));
+    let mut scene = Scene::new();
+    let mesh = scene.add_mesh(pentagon().to_mesh_with_collision_data(&ctx, "Pentagon"));
+    let material = scene.add_material(Material::from_color(
+        &ctx,
+        renderer.material_layout(),
+        [1.0; 4],
+        "White",
+    )?);
+    let mut spawn = |z: f32| {
+        let entity = scene.spawn(None, None);
+        scene.set_transform(
+            entity,
+            TransformComponent::from_translation_rotation(
+                Vector3::new(0.0, 0.0, z),
+                TransformComponent::identity().rotation,
+            ),
+        );
+        scene.add_mesh_renderer(entity, MeshRendererComponent { mesh, material });
+        entity
+    };
+    let far = spawn(-4.0);
+    let near = spawn(1.0);
+    let behind = spawn(10.0);
+
+    let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+    let hits = scene.raycast(&ray, RaycastMode::Bounds);
+    assert_eq!(
+        hits.iter().map(|hit| hit.entity).collect::<Vec<_>>(),
+        [near, far]
+    );
+    assert!((hits[0].distance - 4.0).abs() < 1e-4);
+    assert!(hits[1].point.distance(Point3::new(0.0, 0.0, -4.0)) < 1e-4);
+    assert!(!hits.iter().any(|hit| hit.entity == behind));
+
+    let exact = scene.raycast(&ray, RaycastMode::Triangles);
+    assert_eq!(exact, hits);
+
+    // The bounding box's corner lies outside the pentagon itself.
+    let corner = Ray::new(Point3::new(0.4, 0.45, 5.0), Vector3::new(0.0, 0.0, -1.0));
+    assert_eq!(scene.raycast(&corner, RaycastMode::Bounds).len(), 2);
+    assert!(scene.pick(&corner, RaycastMode::Triangles).is_none());
+    Ok(())
+}
+
+#[test]
+fn collision_data_is_opt_in_and_keeps_the_index_width() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(8, 8));
+    let data = pentagon();
+    let plain = data.to_mesh(&ctx, "Pentagon");
+    assert!(plain.collision.is_none());
+    let collision = data
+        .to_mesh_with_collision_data(&ctx, "Pentagon")
+        .collision
+        .unwrap();
+    assert_eq!(
+        collision.indices,
+        CollisionIndices::U16(vec![0, 1, 4, 1, 2, 4, 2, 3, 4])
+    );
+    assert_eq!(collision.triangles().count(), 3);
+
+    // Without collision data, triangle raycasts fall back to the bounds.
+    let mut scene = Scene::new();
+    let mesh = scene.add_mesh(plain);
+    let material = scene.add_material(Material::from_color(
+        &ctx,
+        renderer.material_layout(),
+        [1.0; 4],
+        "White",
+    )?);
+    let entity = scene.spawn(None, None);
+    scene.set_transform(entity, TransformComponent::identity());
+    scene.add_mesh_renderer(entity, MeshRendererComponent { mesh, material });
+    let corner = Ray::new(Point3::new(0.4, 0.45, 5.0), Vector3::new(0.0, 0.0, -1.0));
+    assert_eq!(
+        scene.raycast(&corner, RaycastMode::Triangles),
+        scene.raycast(&corner, RaycastMode::Bounds)
+    );
+    assert_eq!(scene.raycast(&corner, RaycastMode::Bounds).len(), 1);
+    Ok(())
+}
@@ -0,0 +1,246 @@
+use std::sync::Arc;
+
+use engine_rust::{
+    camera::Camera,
+    post_process::{ColorLut, CustomEffect, PostProcessPass, PostProcessSettings},
+    renderer::{GpuContext, HeadlessRenderer},
+    scene::{CameraComponent, Material, Mesh, MeshRendererComponent, Scene, TransformComponent},
+    tonemap::Tonemapping,
+};
+use winit::dpi::PhysicalSize;
+
+const INVERT: &str = "
+@fragment
+fn fs_effect(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = sample_source(in.uv);
+    return vec4<f32>(mix(color.rgb, 1.0 - color.rgb, effect.params.x), color.a);
+}
+";
+
+/// A scene with only a camera carrying `settings`.
+fn camera_scene(settings: PostProcessSettings) -> Scene {
+    let mut scene = Scene::new();
+    let entity = scene.spawn(None, None);
+    let camera = Camera::new((0.0, 0.0, 3.0).into(), (0.0, 0.0, 0.0).into(), 1.0);
+    scene.add_camera(
+        entity,
+        CameraComponent::new(camera).with_tonemapping(Tonemapping::Linear),
+    );
+    scene.set_post_process(entity, settings);
+    scene.active_camera = Some(entity);
+    scene
+}
+
+/// Renders an empty scene cleared to `color` through a camera with `settings`.
+fn render_color(
+    ctx: &GpuContext,
+    renderer: &mut HeadlessRenderer,
+    [r, g, b]: [f64; 3],
+    settings: PostProcessSettings,
+) -> anyhow::Result<image::RgbaImage> {
+    renderer.clear_color = wgpu::Color { r, g, b, a: 1.0 };
+    renderer.render_to_image(ctx, &mut camera_scene(settings))
+}
+
+/// Renders an empty scene cleared to `intensity` through a camera with `settings`.
+fn render(
+    ctx: &GpuContext,
+    renderer: &mut HeadlessRenderer,
+    intensity: f64,
+    settings: PostProcessSettings,
+) -> anyhow::Result<image::RgbaImage> {
+    render_color(ctx, renderer, [intensity; 3], settings)
+}
+
+/// Renders a white pentagon on black, whose slanted edges are aliased.
+fn render_edges(
+    ctx: &GpuContext,
+    renderer: &mut HeadlessRenderer,
+    settings: PostProcessSettings,
+) -> anyhow::Result<image::RgbaImage> {
+    renderer.clear_color = wgpu::Color::BLACK;
+    let mut scene = camera_scene(settings);
+    let mesh = scene.add_mesh(Mesh::pentagon(ctx));
+    let material = scene.add_material(Material::from_color(
+        ctx,
+        renderer.material_layout(),
+        [1.0; 4],
+        "White",
+    )?);
+    let entity = scene.spawn(None, None);
+    scene.set_transform(entity, TransformComponent::identity());
+    scene.add_mesh_renderer(entity, MeshRendererComponent { mesh, material });
+    scene.rebuild_render_batches(ctx);
+    renderer.render_to_image(ctx, &mut scene)
+}
+
+/// A 16³ strip that swaps the red and blue channels.
+fn channel_swap_lut(ctx: &GpuContext) -> anyhow::Result<ColorLut> {
+    let size = 16;
+    let step = |i: u32| (i * 255 / (size - 1)) as u8;
+    let strip = image::RgbaImage::from_fn(size * size, size, |x, y| {
+        let (r, g, b) = (x % size, y, x / size);
+        image::Rgba([step(b), step(g), step(r), 255])
+    });
+    ColorLut::from_strip(ctx, &strip, "channel_swap_lut")
+}
+
+fn close(a: [u8; 4], b: [u8; 4]) -> bool {
+    a.iter()
+        .zip(&b)
+        .all(|(a, b)| (*a as i32 - *b as i32).abs() <= 1)
+}
+
+#[test]
+fn built_in_effects_change_the_image() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let mut renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(32, 32));
+    let plain = render(&ctx, &mut renderer, 0.5, PostProcessSettings::default())?;
+
+    let mut vignette = PostProcessSettings::default();
+    vignette.vignette.enabled = true;
+    let vignetted = render(&ctx, &mut renderer, 0.5, vignette)?;
+
+    // 0.5 and 0.2 linear are 188 and 124 in sRGB.
+    let color = [0.5, 0.2, 0.0];
+    let mut graded = PostProcessSettings::default();
+    graded.color_grading.enabled = true;
+    graded.color_grading.lut = Some(Arc::new(channel_swap_lut(&ctx)?));
+    let graded = render_color(&ctx, &mut renderer, color, graded)?;
+    let ungraded = render_color(&ctx, &mut renderer, color, PostProcessSettings::default())?;
+
+    let mut bloom = PostProcessSettings::default();
+    bloom.bloom.enabled = true;
+    bloom.bloom.threshold = 0.1;
+    bloom.bloom.intensity = 1.0;
+    let bloomed = render(&ctx, &mut renderer, 0.2, bloom)?;
+    let dim = render(&ctx, &mut renderer, 0.2, PostProcessSettings::default())?;
+
+    if !ctx.is_noop() {
+        // 0.5 linear is 188 in sRGB.
+        assert!(close(plain.get_pixel(16, 16).0, [188, 188, 188, 255]));
+        assert!(close(
+            vignetted.get_pixel(16, 16).0,
+            plain.get_pixel(16, 16).0
+        ));
+        assert!(vignetted.get_pixel(0, 0)[0] < plain.get_pixel(0, 0)[0] - 20);
+        assert!(close(ungraded.get_pixel(16, 16).0, [188, 124, 0, 255]));
+        assert!(graded
+            .pixels()
+            .all(|pixel| close(pixel.0, [0, 124, 188, 255])));
+        assert!(bloomed.get_pixel(16, 16)[0] > dim.get_pixel(16, 16)[0] + 10);
+    }
+    Ok(())
+}
+
+#[test]
+fn edge_effects_change_aliased_edges() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let mut renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(32, 32));
+    let plain = render_edges(&ctx, &mut renderer, PostProcessSettings::default())?;
+
+    let mut fxaa = PostProcessSettings::default();
+    fxaa.fxaa.enabled = true;
+    let smoothed = render_edges(&ctx, &mut renderer, fxaa)?;
+
+    let mut aberration = PostProcessSettings::default();
+    aberration.chromatic_aberration.enabled = true;
+    aberration.chromatic_aberration.intensity = 0.2;
+    let split = render_edges(&ctx, &mut renderer, aberration)?;
+
+    if !ctx.is_noop() {
+        let partial = |pixel: &image::Rgba<u8>| (32..224).contains(&pixel[1]);
+        // Without antialiasing every pixel is either the pentagon or the background.
+        assert!(plain.pixels().any(|pixel| pixel[1] == 255));
+        assert!(!plain.pixels().any(partial));
+        assert!(smoothed.pixels().any(partial));
+        // Flat areas stay as they were.
+        assert_eq!(smoothed.get_pixel(16, 16), plain.get_pixel(16, 16));
+        assert_eq!(smoothed.get_pixel(0, 0), plain.get_pixel(0, 0));
+
+        let fringe = |pixel: &image::Rgba<u8>| (pixel[0] as i32 - pixel[2] as i32).abs() > 128;
+        assert!(!plain.pixels().any(fringe));
+        assert!(split.pixels().any(fringe));
+        // Green is never shifted.
+        assert!(split
+            .pixels()
+            .zip(plain.pixels())
+            .all(|(a, b)| a[1] == b[1]));
+    }
+    Ok(())
+}
+
+#[test]
+fn post_processing_targets_follow_the_enabled_effects() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let mut renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(8, 8));
+    let textures = |renderer: &mut HeadlessRenderer, settings| {
+        render(&ctx, renderer, 0.5, settings)?;
+        let mut ids: Vec<_> = renderer.graph.transient_textures().map(|id| id.0).collect();
+        ids.sort_unstable();
+        anyhow::Ok(ids)
+    };
+
+    // Tonemapping writes the output, so only the scene targets remain.
+    let plain = textures(&mut renderer, PostProcessSettings::default())?;
+    assert_eq!(plain, ["depth", "hdr_color"]);
+
+    let mut vignette = PostProcessSettings::default();
+    vignette.vignette.enabled = true;
+    let vignetted = textures(&mut renderer, vignette.clone())?;
+    assert_eq!(vignetted, ["depth", "hdr_color", "ldr_color", "post_ping"]);
+
+    vignette.fxaa.enabled = true;
+    vignette.bloom.enabled = true;
+    let all = textures(&mut renderer, vignette)?;
+    assert_eq!(
+        all.iter().filter(|id| id.starts_with("bloom_mip")).count(),
+        6
+    );
+    assert!(all.contains(&"post_pong"));
+
+    assert_eq!(
+        textures(&mut renderer, PostProcessSettings::default())?,
+        plain
+    );
+    Ok(())
+}
+
+#[test]
+fn registered_effects_run_when_enabled_on_the_camera() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let mut renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(8, 8));
+    renderer.graph.add_pass(
+        PostProcessPass::new()
+            .with_effect("invert", INVERT)
+            .with_effect("broken", "fn fs_effect() {"),
+    );
+
+    let mut settings = PostProcessSettings {
+        custom: vec![
+            CustomEffect::new("broken"),
+            CustomEffect::new("missing"),
+            CustomEffect::new("invert").with_params([1.0, 0.0, 0.0, 0.0]),
+        ],
+        ..Default::default()
+    };
+    let inverted = render(&ctx, &mut renderer, 0.0, settings.clone())?;
+    settings.custom[2].enabled = false;
+    let plain = render(&ctx, &mut renderer, 0.0, settings)?;
+
+    if !ctx.is_noop() {
+        assert_eq!(inverted.get_pixel(4, 4).0, [255, 255, 255, 255]);
+        assert_eq!(plain.get_pixel(4, 4).0, [0, 0, 0, 255]);
+    }
+    Ok(())
+}
+
+#[test]
+fn lut_strips_must_be_square_slices() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let strip = image::RgbaImage::new(16, 4);
+    let lut = ColorLut::from_strip(&ctx, &strip, "lut")?;
+    assert_eq!(lut.size, 4);
+    assert!(ColorLut::from_strip(&ctx, &image::RgbaImage::new(16, 8), "lut").is_err());
+    Ok(())
+}
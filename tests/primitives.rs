@@ -0,0 +1,156 @@
+use cgmath::prelude::*;
+use cgmath::Vector3;
+use engine_rust::{
+    primitives::{MeshData, Primitive, PrimitiveAttributes},
+    renderer::GpuContext,
+    scene::Scene,
+};
+
+fn all_primitives() -> Vec<Primitive> {
+    vec![
+        Primitive::Cube {
+            size: 2.0,
+            subdivisions: 3,
+        },
+        Primitive::uv_sphere(1.5),
+        Primitive::icosphere(1.0),
+        Primitive::Plane {
+            width: 4.0,
+            depth: 2.0,
+            subdivisions_x: 4,
+            subdivisions_z: 2,
+        },
+        Primitive::Cylinder {
+            radius: 0.5,
+            height: 2.0,
+            sectors: 12,
+            stacks: 3,
+        },
+        Primitive::Cone {
+            radius: 1.0,
+            height: 2.0,
+            sectors: 12,
+            stacks: 2,
+        },
+        Primitive::capsule(0.5, 1.0),
+        Primitive::torus(1.0, 0.25),
+    ]
+}
+
+fn position(data: &MeshData, index: u32) -> Vector3<f32> {
+    data.positions[index as usize].into()
+}
+
+#[test]
+fn triangles_face_along_their_normals() {
+    for primitive in all_primitives() {
+        let data = primitive.generate(PrimitiveAttributes {
+            normals: true,
+            tangents: false,
+        });
+        let normals = data.normals.as_ref().expect("normals were requested");
+        assert_eq!(normals.len(), data.vertex_count(), "{:?}", primitive);
+        assert_eq!(
+            data.tex_coords.len(),
+            data.vertex_count(),
+            "{:?}",
+            primitive
+        );
+        assert!(data.tangents.is_none());
+        assert!(!data.indices.is_empty() && data.indices.len() % 3 == 0);
+
+        for triangle in data.indices.chunks_exact(3) {
+            let [a, b, c] = [
+                position(&data, triangle[0]),
+                position(&data, triangle[1]),
+                position(&data, triangle[2]),
+            ];
+            let face = (b - a).cross(c - a);
+            assert!(
+                face.magnitude() > 1e-7,
+                "{:?} has a degenerate triangle",
+                primitive
+            );
+
+            let normal: Vector3<f32> = triangle
+                .iter()
+                .map(|&index| Vector3::from(normals[index as usize]))
+                .sum();
+            assert!(
+                face.dot(normal) > 0.0,
+                "{:?} has a triangle wound against its normals",
+                primitive
+            );
+        }
+    }
+}
+
+#[test]
+fn tangents_are_orthonormal_to_normals() {
+    for primitive in all_primitives() {
+        let data = primitive.generate(PrimitiveAttributes::ALL);
+        let normals = data.normals.as_ref().unwrap();
+        let tangents = data.tangents.as_ref().unwrap();
+        for (normal, tangent) in normals.iter().zip(tangents) {
+            let normal = Vector3::from(*normal);
+            let direction = Vector3::new(tangent[0], tangent[1], tangent[2]);
+            assert!((normal.magnitude() - 1.0).abs() < 1e-4, "{:?}", primitive);
+            assert!(
+                (direction.magnitude() - 1.0).abs() < 1e-4,
+                "{:?}",
+                primitive
+            );
+            assert!(normal.dot(direction).abs() < 1e-4, "{:?}", primitive);
+            assert!(tangent[3] == 1.0 || tangent[3] == -1.0);
+        }
+    }
+}
+
+#[test]
+fn attributes_are_optional() {
+    let data = Primitive::cube(1.0).generate(PrimitiveAttributes::NONE);
+    assert!(data.normals.is_none());
+    assert!(data.tangents.is_none());
+    assert_eq!(data.vertex_count(), 24);
+    assert_eq!(data.indices.len(), 36);
+}
+
+#[test]
+fn spheres_have_the_requested_radius() {
+    for primitive in [Primitive::uv_sphere(1.5), Primitive::icosphere(1.5)] {
+        let data = primitive.generate(PrimitiveAttributes::NONE);
+        for &position in &data.positions {
+            assert!((Vector3::from(position).magnitude() - 1.5).abs() < 1e-4);
+        }
+    }
+}
+
+#[test]
+fn primitives_round_trip_through_builtin_paths() -> anyhow::Result<()> {
+    for primitive in all_primitives() {
+        let path = primitive.builtin_path();
+        assert!(path.starts_with("builtin://primitive/"));
+        assert_eq!(Primitive::from_builtin_path(&path).unwrap()?, primitive);
+    }
+    assert!(Primitive::from_builtin_path("builtin://pentagon").is_none());
+    assert!(Primitive::from_builtin_path("builtin://primitive/Teapot")
+        .unwrap()
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn scene_reuses_primitive_meshes() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let mut scene = Scene::new();
+    let sphere = scene.add_primitive(&ctx, &Primitive::uv_sphere(1.0));
+    let cube = scene.add_primitive(&ctx, &Primitive::cube(1.0));
+    assert_ne!(sphere, cube);
+    assert_eq!(
+        scene.add_primitive(&ctx, &Primitive::uv_sphere(1.0)),
+        sphere
+    );
+    assert_eq!(scene.meshes.len(), 2);
+    assert_eq!(scene.mesh(cube).unwrap().index_count, 36);
+    Ok(())
+}
@@ -0,0 +1,263 @@
+use cgmath::{Point3, Vector3};
+use engine_rust::{
+    batch::BatchKey,
+    camera::Camera,
+    renderer::{GpuContext, HeadlessRenderer},
+    scene::{
+        CameraComponent, EntityId, Material, MaterialHandle, Mesh, MeshHandle,
+        MeshRendererComponent, Scene, TransformComponent,
+    },
+};
+use winit::dpi::PhysicalSize;
+
+fn happy_tree(
+    ctx: &GpuContext,
+    renderer: &HeadlessRenderer,
+    label: &str,
+) -> anyhow::Result<Material> {
+    Material::from_texture_bytes(
+        ctx,
+        renderer.material_layout(),
+        include_bytes!("../src/happy-tree.png"),
+        label,
+    )
+}
+
+fn spawn_at(
+    scene: &mut Scene,
+    renderer: MeshRendererComponent,
+    translation: Vector3<f32>,
+) -> EntityId {
+    let entity = scene.spawn(None, None);
+    scene.set_transform(
+        entity,
+        TransformComponent::from_translation_rotation(
+            translation,
+            TransformComponent::identity().rotation,
+        ),
+    );
+    scene.add_mesh_renderer(entity, renderer);
+    entity
+}
+
+/// The handles and entities of [`scene`], the same in every copy since they spawn in order.
+struct Handles {
+    pentagon: MeshRendererComponent,
+    other_mesh: MeshHandle,
+    other_material: MaterialHandle,
+    row: Vec<EntityId>,
+    parent: EntityId,
+    children: Vec<EntityId>,
+}
+
+/// A row of twenty pentagons, more than a batch starts with room for, and a pentagon with
+/// three pentagon children above it.
+fn scene(ctx: &GpuContext, renderer: &HeadlessRenderer) -> anyhow::Result<(Scene, Handles)> {
+    let mut scene = Scene::new();
+    let mesh = scene.add_mesh(Mesh::pentagon(ctx));
+    let material = scene.add_material(happy_tree(ctx, renderer, "Happy Tree")?);
+    let pentagon = MeshRendererComponent { mesh, material };
+    let other_mesh = scene.add_mesh(Mesh::pentagon(ctx));
+    let other_material = scene.add_material(happy_tree(ctx, renderer, "Other Happy Tree")?);
+
+    let row = (0..20)
+        .map(|x| {
+            spawn_at(
+                &mut scene,
+                pentagon,
+                Vector3::new(x as f32 - 10.0, -1.0, 0.0),
+            )
+        })
+        .collect();
+    let parent = spawn_at(&mut scene, pentagon, Vector3::new(0.0, 1.0, 0.0));
+    let mut children = Vec::new();
+    for x in [-1.5, 0.0, 1.5] {
+        let child = spawn_at(&mut scene, pentagon, Vector3::new(x, 1.0, 0.0));
+        scene.set_parent(child, Some(parent), false)?;
+        children.push(child);
+    }
+
+    let camera = scene.spawn(None, None);
+    scene.add_camera(
+        camera,
+        CameraComponent::new(Camera::new(
+            Point3::new(0.0, 0.0, 12.0),
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+        )),
+    );
+    scene.active_camera = Some(camera);
+    scene.rebuild_render_batches(ctx);
+
+    let handles = Handles {
+        pentagon,
+        other_mesh,
+        other_material,
+        row,
+        parent,
+        children,
+    };
+    Ok((scene, handles))
+}
+
+/// Moves `entity` in place, which keeps its slot.
+fn move_to(scene: &mut Scene, entity: EntityId, translation: Vector3<f32>) {
+    scene
+        .get_mut::<TransformComponent>(entity)
+        .unwrap()
+        .translation = translation;
+}
+
+/// Applies the `step`th edit of [`incremental_updates_match_a_rebuild`].
+fn edit(scene: &mut Scene, handles: &Handles, step: usize) -> anyhow::Result<()> {
+    let row = &handles.row;
+    match step {
+        0 => {
+            move_to(scene, row[3], Vector3::new(-7.0, 2.0, 0.0));
+            move_to(scene, row[15], Vector3::new(2.0, -3.0, 1.0));
+            move_to(scene, handles.parent, Vector3::new(0.5, 2.0, 0.0));
+        }
+        1 => {
+            // The last instance of the batch moves into each freed slot.
+            scene.despawn(row[0]);
+            scene.despawn(row[7]);
+        }
+        2 => {
+            scene.set_parent(handles.children[0], Some(row[5]), true)?;
+            scene.set_parent(handles.children[1], None, false)?;
+        }
+        3 => {
+            let other_material = MeshRendererComponent {
+                material: handles.other_material,
+                ..handles.pentagon
+            };
+            let other_mesh = MeshRendererComponent {
+                mesh: handles.other_mesh,
+                ..handles.pentagon
+            };
+            scene.add_mesh_renderer(row[2], other_material);
+            scene.add_mesh_renderer(row[4], other_mesh);
+            scene.add_mesh_renderer(row[8], other_material);
+            scene.remove_mesh_renderer(row[6]);
+            scene.remove_transform(row[9]);
+        }
+        4 => {
+            scene.despawn(handles.parent);
+        }
+        5 => {
+            for y in 0..20 {
+                spawn_at(scene, handles.pentagon, Vector3::new(0.0, y as f32, -2.0));
+            }
+        }
+        6 => {
+            move_to(scene, row[5], Vector3::new(-3.0, 0.0, 1.0));
+            scene.add_mesh_renderer(row[6], handles.pentagon);
+            scene.add_mesh_renderer(row[8], handles.pentagon);
+            scene.set_transform(row[9], TransformComponent::identity());
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+/// A batch's instances as bytes by entity.
+type Instances = Vec<(EntityId, Vec<u8>)>;
+
+/// Each non-empty batch's instances by entity, since the slots depend on the edit history.
+fn batches(scene: &Scene) -> Vec<(BatchKey, Instances)> {
+    let mut batches: Vec<_> = scene
+        .render_batches
+        .iter()
+        .filter(|batch| !batch.instances().is_empty())
+        .map(|batch| {
+            assert_eq!(batch.entities().len(), batch.instances().len());
+            assert_eq!(batch.instance_count as usize, batch.instances().len());
+            assert!(batch.capacity() >= batch.instances().len());
+            let mut instances: Vec<_> = batch
+                .entities()
+                .iter()
+                .zip(batch.instances())
+                .map(|(entity, instance)| (*entity, bytemuck::bytes_of(instance).to_vec()))
+                .collect();
+            instances.sort_by_key(|(entity, _)| *entity);
+            ((batch.mesh, batch.material), instances)
+        })
+        .collect();
+    batches.sort_by_key(|(key, _)| *key);
+    batches
+}
+
+#[test]
+fn incremental_updates_match_a_rebuild() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let mut renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(32, 32));
+    renderer.clear_color = wgpu::Color::BLACK;
+    let (mut incremental, handles) = scene(&ctx, &renderer)?;
+    let (mut rebuilt, _) = scene(&ctx, &renderer)?;
+
+    for step in 0..7 {
+        edit(&mut incremental, &handles, step)?;
+        edit(&mut rebuilt, &handles, step)?;
+        incremental.update_render_batches(&ctx);
+        rebuilt.rebuild_render_batches(&ctx);
+
+        assert_eq!(batches(&incremental), batches(&rebuilt), "step {}", step);
+        let image = renderer.render_to_image(&ctx, &mut incremental)?;
+        let expected = renderer.render_to_image(&ctx, &mut rebuilt)?;
+        if !ctx.is_noop() {
+            assert_eq!(image.as_raw(), expected.as_raw(), "step {}", step);
+        }
+    }
+
+    let row = &handles.row;
+    for despawned in [row[0], row[7], handles.parent, handles.children[2]] {
+        assert!(!incremental.render_batches.contains(despawned));
+    }
+    let pentagon = handles.pentagon;
+    let keys: Vec<_> = batches(&incremental)
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect();
+    assert_eq!(
+        keys,
+        [
+            (pentagon.mesh, pentagon.material),
+            (pentagon.mesh, handles.other_material),
+            (handles.other_mesh, pentagon.material),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn batches_grow_past_their_capacity() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(8, 8));
+    let mut scene = Scene::new();
+    let mesh = scene.add_mesh(Mesh::pentagon(&ctx));
+    let material = scene.add_material(happy_tree(&ctx, &renderer, "Happy Tree")?);
+    let pentagon = MeshRendererComponent { mesh, material };
+    scene.rebuild_render_batches(&ctx);
+
+    let mut entities = Vec::new();
+    for (count, capacity) in [(10, 16), (17, 32), (40, 64)] {
+        while entities.len() < count {
+            let x = entities.len() as f32;
+            entities.push(spawn_at(&mut scene, pentagon, Vector3::new(x, 0.0, 0.0)));
+        }
+        scene.update_render_batches(&ctx);
+
+        let batch = scene.render_batches.iter().next().unwrap();
+        assert_eq!(batch.capacity(), capacity);
+        assert_eq!(batch.instance_count as usize, count);
+        assert_eq!(batch.entities(), &entities[..]);
+    }
+
+    // Moving an instance after the buffer grew rewrites only its own slot.
+    move_to(&mut scene, entities[20], Vector3::new(0.0, 5.0, 0.0));
+    scene.update_render_batches(&ctx);
+    let batch = scene.render_batches.iter().next().unwrap();
+    assert_eq!(batch.instances()[20].model[3][..3], [0.0, 5.0, 0.0]);
+    assert_eq!(batch.instances()[21].model[3][..3], [21.0, 0.0, 0.0]);
+    Ok(())
+}
@@ -0,0 +1,232 @@
+use engine_rust::{
+    post_process::{BloomPass, PostProcessPass},
+    render_graph::{
+        PassBuilder, PassContext, RenderGraph, RenderGraphPass, ResourceId, ScenePass, ShadowPass,
+        TransientTexture,
+    },
+    renderer::{GpuContext, HeadlessRenderer},
+    scene::Scene,
+    tonemap::TonemapPass,
+};
+use winit::dpi::PhysicalSize;
+
+const SCRATCH: ResourceId = ResourceId("scratch");
+
+/// Declares accesses without recording anything.
+struct Declare {
+    name: &'static str,
+    reads: Vec<ResourceId>,
+    writes: Vec<ResourceId>,
+    read_writes: Vec<ResourceId>,
+}
+
+impl Declare {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            reads: Vec::new(),
+            writes: Vec::new(),
+            read_writes: Vec::new(),
+        }
+    }
+
+    fn reads(mut self, id: ResourceId) -> Self {
+        self.reads.push(id);
+        self
+    }
+
+    fn writes(mut self, id: ResourceId) -> Self {
+        self.writes.push(id);
+        self
+    }
+
+    fn read_writes(mut self, id: ResourceId) -> Self {
+        self.read_writes.push(id);
+        self
+    }
+}
+
+impl RenderGraphPass for Declare {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn setup(&self, builder: &mut PassBuilder) {
+        for &id in &self.reads {
+            builder.read(id);
+        }
+        for &id in &self.writes {
+            builder.write(id);
+        }
+        for &id in &self.read_writes {
+            builder.read_write(id);
+        }
+    }
+
+    fn execute(&mut self, _: &mut PassContext<'_>) {}
+}
+
+#[test]
+fn passes_run_after_the_passes_they_depend_on() -> anyhow::Result<()> {
+    let mut graph = RenderGraph::new();
+    graph.add_pass(Declare::new("overlay").read_writes(ResourceId::OUTPUT));
+    graph.add_pass(
+        Declare::new("post")
+            .reads(SCRATCH)
+            .read_writes(ResourceId::OUTPUT),
+    );
+    graph.add_pass(ScenePass);
+    graph.add_pass(Declare::new("scratch").writes(SCRATCH));
+    graph.add_pass(ShadowPass);
+    graph.add_pass(PostProcessPass::default());
+    graph.add_pass(TonemapPass::default());
+
+    assert_eq!(
+        graph.execution_order()?,
+        [
+            "scratch",
+            "shadows",
+            "scene",
+            "tonemap",
+            "post_process",
+            "overlay",
+            "post"
+        ]
+    );
+
+    graph.remove_pass("post");
+    graph.add_pass(BloomPass::default());
+    assert_eq!(
+        graph.execution_order()?,
+        [
+            "scratch",
+            "shadows",
+            "scene",
+            "bloom",
+            "tonemap",
+            "post_process",
+            "overlay"
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn invalid_graphs_are_rejected() {
+    let mut graph = RenderGraph::new();
+    graph.add_pass(Declare::new("orphan").reads(SCRATCH));
+    assert!(graph.execution_order().is_err());
+
+    let mut graph = RenderGraph::new();
+    let other = ResourceId("other");
+    graph.add_pass(Declare::new("a").reads(SCRATCH).writes(other));
+    graph.add_pass(Declare::new("b").reads(other).writes(SCRATCH));
+    assert!(graph.execution_order().is_err());
+
+    let mut graph = RenderGraph::with_default_passes();
+    graph.add_pass(Declare::new("scene").writes(ResourceId::OUTPUT));
+    assert_eq!(
+        graph.pass_names().collect::<Vec<_>>(),
+        [
+            "cull",
+            "shadows",
+            "scene",
+            "bloom",
+            "tonemap",
+            "post_process"
+        ]
+    );
+}
+
+/// Clears a transient texture to green.
+struct FillScratch;
+
+impl RenderGraphPass for FillScratch {
+    fn name(&self) -> &str {
+        "fill"
+    }
+
+    fn setup(&self, builder: &mut PassBuilder) {
+        let mut scratch = TransientTexture::color(wgpu::TextureFormat::Rgba8UnormSrgb);
+        scratch.usage |= wgpu::TextureUsages::COPY_SRC;
+        builder.create_texture(SCRATCH, scratch);
+    }
+
+    fn execute(&mut self, pass: &mut PassContext<'_>) {
+        pass.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Fill Scratch"),
+            multiview_mask: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: pass.texture(SCRATCH),
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
+                    store: wgpu::StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+    }
+}
+
+/// Copies the whole transient texture over the output.
+struct CopyScratch;
+
+impl RenderGraphPass for CopyScratch {
+    fn name(&self) -> &str {
+        "copy"
+    }
+
+    fn setup(&self, builder: &mut PassBuilder) {
+        builder.read(SCRATCH).read_write(ResourceId::OUTPUT);
+    }
+
+    fn execute(&mut self, pass: &mut PassContext<'_>) {
+        let source = pass.texture(SCRATCH).texture();
+        let destination = pass.texture(ResourceId::OUTPUT).texture();
+        pass.encoder.copy_texture_to_texture(
+            source.as_image_copy(),
+            destination.as_image_copy(),
+            wgpu::Extent3d {
+                width: pass.size.width,
+                height: pass.size.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+#[test]
+fn custom_passes_use_target_sized_transient_textures() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let mut renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(32, 16));
+    renderer.graph.add_pass(CopyScratch);
+    renderer.graph.add_pass(FillScratch);
+    assert_eq!(
+        renderer.graph.execution_order()?,
+        [
+            "cull",
+            "shadows",
+            "scene",
+            "bloom",
+            "tonemap",
+            "post_process",
+            "fill",
+            "copy"
+        ]
+    );
+
+    let mut scene = Scene::new();
+    for size in [PhysicalSize::new(32, 16), PhysicalSize::new(48, 40)] {
+        renderer.resize(&ctx, size);
+        let image = renderer.render_to_image(&ctx, &mut scene)?;
+        assert_eq!(image.dimensions(), (size.width, size.height));
+        if !ctx.is_noop() {
+            assert!(image.pixels().all(|pixel| pixel.0 == [0, 255, 0, 255]));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,233 @@
+use cgmath::{Deg, Matrix4, Point3, Quaternion, Rotation3, Vector3};
+use engine_rust::{
+    camera::Camera,
+    renderer::{GpuContext, HeadlessRenderer},
+    scene::{CameraComponent, EntityId, MeshRendererComponent, Scene, TransformComponent},
+};
+use winit::dpi::PhysicalSize;
+
+fn spawn(scene: &mut Scene, parent: Option<EntityId>, transform: TransformComponent) -> EntityId {
+    let entity = scene.spawn(None, parent);
+    scene.set_transform(entity, transform);
+    entity
+}
+
+fn at(x: f32, y: f32, z: f32) -> TransformComponent {
+    TransformComponent::from_translation_rotation(
+        Vector3::new(x, y, z),
+        TransformComponent::identity().rotation,
+    )
+}
+
+fn assert_matrix_near(actual: Matrix4<f32>, expected: Matrix4<f32>) {
+    let actual: &[f32; 16] = actual.as_ref();
+    let expected: &[f32; 16] = expected.as_ref();
+    assert!(
+        actual
+            .iter()
+            .zip(expected)
+            .all(|(a, b)| (a - b).abs() < 1e-4),
+        "{:?} != {:?}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn despawn_removes_the_whole_subtree() {
+    let mut scene = Scene::new();
+    let root = spawn(&mut scene, None, at(1.0, 0.0, 0.0));
+    let child = spawn(&mut scene, Some(root), at(0.0, 1.0, 0.0));
+    let grandchild = spawn(&mut scene, Some(child), at(0.0, 0.0, 1.0));
+    let sibling = spawn(&mut scene, Some(root), at(0.0, 2.0, 0.0));
+    let other = spawn(&mut scene, None, at(5.0, 0.0, 0.0));
+
+    assert!(scene.despawn(child));
+    assert!(!scene.entities.contains_key(&child));
+    assert!(!scene.entities.contains_key(&grandchild));
+    assert!(!scene.has::<TransformComponent>(grandchild));
+    assert_eq!(scene.entities[&root].children, [sibling]);
+
+    assert!(scene.despawn(root));
+    assert!(!scene.entities.contains_key(&sibling));
+    assert_eq!(scene.entities.keys().collect::<Vec<_>>(), [&other]);
+    assert!(!scene.despawn(root));
+}
+
+#[test]
+fn despawning_the_active_camera_clears_it() {
+    let camera_component = CameraComponent::new(Camera::new(
+        Point3::new(0.0, 0.0, 5.0),
+        Point3::new(0.0, 0.0, 0.0),
+        1.0,
+    ));
+    let mut scene = Scene::new();
+    let camera = scene.spawn(None, None);
+    scene.add_camera(camera, camera_component);
+    scene.active_camera = Some(camera);
+    scene.despawn(camera);
+    assert_eq!(scene.active_camera, None);
+
+    let rig = scene.spawn(None, None);
+    let camera = scene.spawn(None, Some(rig));
+    scene.add_camera(camera, camera_component);
+    scene.active_camera = Some(camera);
+    let unrelated = scene.spawn(None, None);
+    scene.despawn(unrelated);
+    assert_eq!(scene.active_camera, Some(camera));
+
+    // Despawning an ancestor takes the camera with it.
+    scene.despawn(rig);
+    assert_eq!(scene.active_camera, None);
+    assert!(scene.active_camera_uniform().is_none());
+}
+
+#[test]
+fn removing_components_keeps_the_entity() {
+    let mut scene = Scene::new();
+    let entity = spawn(&mut scene, None, at(0.0, 0.0, 0.0));
+    scene.add_camera(
+        entity,
+        CameraComponent::new(Camera::new(
+            Point3::new(0.0, 0.0, 5.0),
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+        )),
+    );
+    scene.active_camera = Some(entity);
+
+    assert!(scene.remove_transform(entity).is_some());
+    assert!(scene.remove_transform(entity).is_none());
+    assert!(scene.remove_mesh_renderer(entity).is_none());
+    assert!(scene.remove_camera(entity).is_some());
+    assert_eq!(scene.active_camera, None);
+    assert!(scene.entities.contains_key(&entity));
+}
+
+#[test]
+fn world_transforms_follow_moved_ancestors() {
+    let mut scene = Scene::new();
+    let root = spawn(&mut scene, None, at(1.0, 0.0, 0.0));
+    let child = spawn(&mut scene, Some(root), at(0.0, 2.0, 0.0));
+    // Entities without a transform pass their parent's through.
+    let group = scene.spawn(None, Some(child));
+    let grandchild = spawn(&mut scene, Some(group), at(0.0, 0.0, 3.0));
+    scene.propagate_transforms();
+    assert_matrix_near(
+        scene.world_transform(grandchild).unwrap(),
+        at(1.0, 2.0, 3.0).matrix(),
+    );
+
+    let turned = TransformComponent::from_translation_rotation(
+        Vector3::new(-1.0, 0.0, 0.0),
+        Quaternion::from_angle_y(Deg(90.0)),
+    );
+    scene.set_transform(root, turned.clone());
+    let expected = turned.matrix() * at(0.0, 2.0, 3.0).matrix();
+    // Before propagation the parent chain is walked, afterwards the cache answers.
+    assert_matrix_near(scene.world_transform(grandchild).unwrap(), expected);
+    scene.propagate_transforms();
+    assert_matrix_near(scene.world_transform(grandchild).unwrap(), expected);
+    assert_matrix_near(
+        scene.world_transform(group).unwrap(),
+        scene.world_transform(child).unwrap(),
+    );
+}
+
+#[test]
+fn render_batches_draw_world_transforms() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let mut renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(64, 64));
+    let layout = renderer.material_layout();
+    let aspect = renderer.target.aspect();
+    let offset = Vector3::new(0.5, 1.0, -2.0);
+
+    let mut original = Scene::default_instanced(&ctx, layout, aspect)?;
+
+    // Each pentagon moved by hand, against the same pentagons under a moved root.
+    let mut moved = Scene::default_instanced(&ctx, layout, aspect)?;
+    let pentagons: Vec<_> = moved
+        .iter::<MeshRendererComponent>()
+        .map(|(entity, _)| entity)
+        .collect();
+    for &entity in &pentagons {
+        let mut transform = moved.get::<TransformComponent>(entity).unwrap().clone();
+        transform.translation += offset;
+        moved.set_transform(entity, transform);
+    }
+    moved.rebuild_render_batches(&ctx);
+
+    let mut parented = Scene::default_instanced(&ctx, layout, aspect)?;
+    let root = spawn(&mut parented, None, at(offset.x, offset.y, offset.z));
+    for &entity in &pentagons {
+        parented.set_parent(entity, Some(root), false)?;
+    }
+    parented.rebuild_render_batches(&ctx);
+
+    let original = renderer.render_to_image(&ctx, &mut original)?;
+    let moved = renderer.render_to_image(&ctx, &mut moved)?;
+    let parented = renderer.render_to_image(&ctx, &mut parented)?;
+    if !ctx.is_noop() {
+        assert_ne!(original.as_raw(), moved.as_raw());
+        assert_eq!(parented.as_raw(), moved.as_raw());
+    }
+    Ok(())
+}
+
+#[test]
+fn reparenting_can_keep_the_world_transform() -> anyhow::Result<()> {
+    let mut scene = Scene::new();
+    let old_parent = spawn(&mut scene, None, at(1.0, 2.0, 3.0));
+    let new_parent = spawn(
+        &mut scene,
+        None,
+        TransformComponent::from_translation_rotation(
+            Vector3::new(-4.0, 0.0, 1.0),
+            Quaternion::from_angle_y(Deg(90.0)),
+        ),
+    );
+    let kept = spawn(&mut scene, Some(old_parent), at(0.0, 1.0, 0.0));
+    let moved = spawn(&mut scene, Some(old_parent), at(0.0, 1.0, 0.0));
+    let world = scene.world_transform(kept).unwrap();
+
+    scene.set_parent(kept, Some(new_parent), true)?;
+    scene.set_parent(moved, Some(new_parent), false)?;
+    assert_eq!(scene.entities[&kept].parent, Some(new_parent));
+    assert_eq!(scene.entities[&new_parent].children, [kept, moved]);
+    assert!(scene.entities[&old_parent].children.is_empty());
+
+    assert_matrix_near(scene.world_transform(kept).unwrap(), world);
+    // Without keeping it the local transform stays, now relative to the new parent.
+    let new_parent_world = scene.world_transform(new_parent).unwrap();
+    assert_matrix_near(
+        scene.world_transform(moved).unwrap(),
+        new_parent_world * at(0.0, 1.0, 0.0).matrix(),
+    );
+
+    // Detaching keeps the world transform as the local one.
+    scene.set_parent(kept, None, true)?;
+    assert_matrix_near(scene.world_transform(kept).unwrap(), world);
+    assert_matrix_near(
+        scene.get::<TransformComponent>(kept).unwrap().matrix(),
+        world,
+    );
+    Ok(())
+}
+
+#[test]
+fn reparenting_rejects_cycles_and_missing_entities() {
+    let mut scene = Scene::new();
+    let root = scene.spawn(None, None);
+    let child = scene.spawn(None, Some(root));
+    let grandchild = scene.spawn(None, Some(child));
+
+    assert!(scene.set_parent(root, Some(grandchild), false).is_err());
+    assert!(scene.set_parent(root, Some(root), false).is_err());
+    assert_eq!(scene.entities[&root].parent, None);
+    assert_eq!(scene.entities[&grandchild].children, []);
+
+    scene.despawn(grandchild);
+    assert!(scene.set_parent(child, Some(grandchild), false).is_err());
+    assert!(scene.set_parent(grandchild, Some(root), false).is_err());
+    assert_eq!(scene.entities[&child].parent, Some(root));
+}
@@ -0,0 +1,216 @@
+use engine_rust::{
+    camera::Projection,
+    light::LightComponent,
+    renderer::{GpuContext, HeadlessRenderer},
+    scene::{CameraComponent, Scene, Shading, TransformComponent},
+    serialization::{FileAssets, SceneDescription, SceneFormat},
+    shadow::ShadowFlags,
+    texture::SamplerDescriptor,
+};
+use winit::dpi::PhysicalSize;
+
+fn build_scene(ctx: &GpuContext, renderer: &HeadlessRenderer) -> anyhow::Result<Scene> {
+    let mut scene =
+        Scene::default_instanced(ctx, renderer.material_layout(), renderer.target.aspect())?;
+
+    let root = scene
+        .query::<(TransformComponent,)>()
+        .map(|(entity, _)| entity)
+        .min()
+        .unwrap();
+    let child = scene.spawn(Some("Child".to_owned()), Some(root));
+    scene.set_transform(
+        child,
+        TransformComponent::from_translation_rotation(
+            (0.0, 1.5, 0.0).into(),
+            cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+        ),
+    );
+    scene.add_light(
+        child,
+        LightComponent::spot([1.0, 0.5, 0.25], 2.0, 10.0, 20.0, 30.0),
+    );
+    scene.set_shadow_flags(
+        child,
+        ShadowFlags {
+            cast: false,
+            receive: true,
+        },
+    );
+    scene.ambient_light = [0.1, 0.1, 0.1];
+    Ok(scene)
+}
+
+fn round_trip(format: SceneFormat) -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(64, 48));
+    let scene = build_scene(&ctx, &renderer)?;
+    let description = scene.to_description()?;
+
+    let source = description.to_string(format)?;
+    let parsed = SceneDescription::from_str(&source, format)?;
+    assert_eq!(parsed, description);
+
+    let loaded = Scene::from_description(
+        &ctx,
+        renderer.material_layout(),
+        &parsed,
+        &mut FileAssets::new("."),
+        renderer.target.aspect(),
+    )?;
+    loaded.validate()?;
+    assert_eq!(loaded.to_description()?, description);
+    assert_eq!(loaded.meshes.len(), 1);
+    assert_eq!(loaded.materials.len(), 1);
+
+    let camera = loaded
+        .active_camera
+        .expect("active camera survives the round trip");
+    assert_eq!(
+        loaded.entities[&camera].name.as_deref(),
+        Some("Main Camera")
+    );
+
+    let child = loaded
+        .entities
+        .values()
+        .find(|entity| entity.name.as_deref() == Some("Child"))
+        .expect("child entity survives the round trip");
+    assert!(child.parent.is_some());
+    assert_eq!(loaded.lights_uniform().count[0], 1);
+    assert_eq!(loaded.ambient_light, [0.1, 0.1, 0.1]);
+    Ok(())
+}
+
+#[test]
+fn scene_round_trips_through_ron() -> anyhow::Result<()> {
+    round_trip(SceneFormat::Ron)
+}
+
+#[test]
+fn scene_round_trips_through_json() -> anyhow::Result<()> {
+    round_trip(SceneFormat::Json)
+}
+
+#[test]
+fn save_and_load_pick_format_from_extension() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(64, 48));
+    let scene = build_scene(&ctx, &renderer)?;
+    let dir = std::env::temp_dir().join(format!("engine-rust-scene-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+
+    for file in ["scene.ron", "scene.json"] {
+        let path = dir.join(file);
+        scene.save(&path)?;
+        let loaded = Scene::load(
+            &ctx,
+            renderer.material_layout(),
+            &path,
+            renderer.target.aspect(),
+        )?;
+        assert_eq!(loaded.to_description()?, scene.to_description()?);
+    }
+
+    let json = std::fs::read_to_string(dir.join("scene.json"))?;
+    assert!(json.trim_start().starts_with('{'));
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn material_shading_and_sampler_survive_a_round_trip() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(64, 48));
+    let mut description = build_scene(&ctx, &renderer)?.to_description()?;
+    for renderer in description
+        .entities
+        .iter_mut()
+        .filter_map(|entity| entity.mesh_renderer.as_mut())
+    {
+        assert_eq!(renderer.shading, Some(Shading::Unlit));
+        assert_eq!(renderer.sampler, None);
+        renderer.shading = Some(Shading::Lit);
+        renderer.sampler = Some(SamplerDescriptor::pixel_art());
+    }
+
+    let source = description.to_string(SceneFormat::Ron)?;
+    let loaded = Scene::from_description(
+        &ctx,
+        renderer.material_layout(),
+        &SceneDescription::from_str(&source, SceneFormat::Ron)?,
+        &mut FileAssets::new("."),
+        renderer.target.aspect(),
+    )?;
+    let material = &loaded.materials[0];
+    assert_eq!(material.shading, Shading::Lit);
+    assert_eq!(material.sampler, SamplerDescriptor::pixel_art());
+    assert_eq!(loaded.to_description()?, description);
+    Ok(())
+}
+
+/// A camera as saved before projections existed, with a bare `fovy`.
+const LEGACY_SCENE: &str = r#"(
+    entities: [
+        (
+            id: 0,
+            name: Some("Main Camera"),
+            transform: Some((
+                translation: (0.0, 0.0, 0.0),
+                rotation: (0.0, 0.0, 0.0, 1.0),
+                scale: (1.0, 1.0, 1.0),
+            )),
+            camera: Some((
+                eye: (0.0, 5.0, 10.0),
+                target: (0.0, 0.0, 0.0),
+                up: (0.0, 1.0, 0.0),
+                fovy: 60.0,
+                znear: 0.1,
+                zfar: 100.0,
+            )),
+        ),
+        (
+            id: 1,
+            mesh_renderer: Some((
+                mesh: "builtin://pentagon",
+                material: "builtin://happy-tree.png",
+            )),
+        ),
+    ],
+    active_camera: Some(0),
+)"#;
+
+#[test]
+fn legacy_camera_fovy_loads_as_perspective() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(64, 48));
+    let parsed = SceneDescription::from_str(LEGACY_SCENE, SceneFormat::Ron)?;
+    let camera = parsed.entities[0].camera.as_ref().unwrap();
+    assert_eq!(camera.projection, Projection::Perspective { fovy: 60.0 });
+    assert_eq!(camera.exposure, 0.0);
+
+    let loaded = Scene::from_description(
+        &ctx,
+        renderer.material_layout(),
+        &parsed,
+        &mut FileAssets::new("."),
+        renderer.target.aspect(),
+    )?;
+    loaded.validate()?;
+
+    let camera = loaded.get::<CameraComponent>(loaded.active_camera.unwrap());
+    assert_eq!(
+        camera.unwrap().camera.projection,
+        Projection::Perspective { fovy: 60.0 }
+    );
+
+    // Saving writes the projection instead, which reads back unchanged.
+    let description = loaded.to_description()?;
+    let source = description.to_string(SceneFormat::Ron)?;
+    assert!(source.contains("projection: Perspective"));
+    assert_eq!(
+        SceneDescription::from_str(&source, SceneFormat::Ron)?,
+        description
+    );
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use engine_rust::{
+    light::LightComponent,
+    renderer::{GpuContext, HeadlessRenderer},
+    scene::{InstanceRaw, Material, Mesh, MeshRendererComponent, Scene, TransformComponent},
+    shadow::{cascade_splits, ShadowConfig, ShadowFlags, MAX_CASCADES},
+};
+use winit::dpi::PhysicalSize;
+
+#[test]
+fn cascade_splits_cover_the_view_range() {
+    for lambda in [0.0, 0.5, 1.0] {
+        let splits = cascade_splits(0.1, 50.0, 4, lambda);
+        assert!(splits[0] > 0.1);
+        assert!(splits.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!((splits[MAX_CASCADES - 1] - 50.0).abs() < 1e-3);
+    }
+
+    let uniform = cascade_splits(0.0, 40.0, 4, 0.0);
+    assert_eq!(uniform, [10.0, 20.0, 30.0, 40.0]);
+    assert_eq!(cascade_splits(0.1, 20.0, 2, 0.5)[2..], [20.0, 20.0]);
+}
+
+#[test]
+fn shadow_flags_are_packed_into_instances() {
+    let identity = cgmath::Matrix4::from_scale(1.0);
+    assert_eq!(
+        InstanceRaw::from_matrix(identity).flags,
+        ShadowFlags::default().bits()
+    );
+
+    let receive_only = ShadowFlags {
+        cast: false,
+        receive: true,
+    };
+    let instance = InstanceRaw::from_matrix(identity).with_shadow_flags(receive_only);
+    assert_eq!(instance.flags, receive_only.bits());
+    assert_ne!(receive_only.bits(), ShadowFlags::default().bits());
+}
+
+#[test]
+fn default_atlas_holds_every_shadow_tile() {
+    let config = ShadowConfig::default();
+    assert_eq!(config.tile_capacity(), 16);
+    let small = ShadowConfig {
+        atlas_size: 1024,
+        ..config
+    };
+    assert_eq!(small.tile_capacity(), 4);
+}
+
+#[test]
+fn shadow_configs_must_fit_a_tile() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let mut renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(8, 8));
+    let config = ShadowConfig::default();
+    for tile_size in [0, config.atlas_size + 1] {
+        let invalid = ShadowConfig {
+            tile_size,
+            ..config
+        };
+        assert!(renderer.resources.set_shadow_config(&ctx, invalid).is_err());
+    }
+    let empty = ShadowConfig {
+        atlas_size: 0,
+        ..config
+    };
+    assert!(renderer.resources.set_shadow_config(&ctx, empty).is_err());
+    assert_eq!(renderer.resources.shadows.config, config);
+
+    let single = ShadowConfig {
+        tile_size: config.atlas_size,
+        ..config
+    };
+    renderer.resources.set_shadow_config(&ctx, single)?;
+    assert_eq!(single.tile_capacity(), 1);
+    Ok(())
+}
+
+#[test]
+fn shadow_maps_only_draw_casters() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(8, 8));
+    let mut scene = Scene::new();
+    let mesh = scene.add_mesh(Mesh::pentagon(&ctx));
+    let material = scene.add_material(Material::from_color(
+        &ctx,
+        renderer.material_layout(),
+        [1.0; 4],
+        "White",
+    )?);
+    let entities: Vec<_> = (0..3)
+        .map(|_| {
+            let entity = scene.spawn(None, None);
+            scene.set_transform(entity, TransformComponent::identity());
+            scene.add_mesh_renderer(entity, MeshRendererComponent { mesh, material });
+            entity
+        })
+        .collect();
+    let receive_only = ShadowFlags {
+        cast: false,
+        receive: true,
+    };
+    let casters = |scene: &Scene| {
+        let batch = scene.render_batches.iter().next().unwrap();
+        batch
+            .shadow_casters()
+            .map(|(buffer, count)| (*buffer == batch.instance_buffer, count))
+    };
+
+    scene.rebuild_render_batches(&ctx);
+    assert_eq!(casters(&scene), Some((true, 3)));
+
+    scene.set_shadow_flags(entities[1], receive_only);
+    scene.update_render_batches(&ctx);
+    assert_eq!(casters(&scene), Some((false, 2)));
+
+    for &entity in &entities {
+        scene.set_shadow_flags(entity, receive_only);
+    }
+    scene.update_render_batches(&ctx);
+    assert_eq!(casters(&scene), None);
+    Ok(())
+}
+
+#[test]
+fn shadow_tiles_are_recorded_on_the_light() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let mut renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(8, 8));
+    let mut scene = Scene::new();
+    let light = LightComponent::point([1.0; 3], 1.0, 10.0);
+    for shadows in [false, true] {
+        let entity = scene.spawn(None, None);
+        scene.set_transform(entity, TransformComponent::identity());
+        scene.add_light(entity, light.with_shadows(shadows));
+    }
+
+    let mut lights = scene.lights_uniform();
+    assert!(lights.lights[..2]
+        .iter()
+        .all(|light| light.shadow_tile < 0 && light.shadow_count == 0));
+    renderer
+        .resources
+        .shadows
+        .prepare(&ctx, &scene, &mut lights);
+    assert_eq!(lights.lights[0].shadow_tile, -1);
+    // One tile per cube face, leaving the spot cone alone.
+    assert_eq!(
+        (lights.lights[1].shadow_tile, lights.lights[1].shadow_count),
+        (0, 6)
+    );
+    assert_eq!(lights.lights[1].cone, [0.0; 2]);
+    Ok(())
+}
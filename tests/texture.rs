@@ -0,0 +1,104 @@
+use engine_rust::{
+    renderer::GpuContext,
+    texture::{SamplerDescriptor, Texture},
+};
+
+#[test]
+fn mip_chains_reach_one_texel() {
+    assert_eq!(Texture::mip_level_count(1, 1), 1);
+    assert_eq!(Texture::mip_level_count(4, 4), 3);
+    assert_eq!(Texture::mip_level_count(256, 100), 9);
+    assert_eq!(Texture::mip_level_count(5, 3), 3);
+}
+
+#[test]
+fn anisotropy_needs_linear_filtering() {
+    let trilinear = SamplerDescriptor::default().with_anisotropy(8);
+    assert_eq!(trilinear.to_wgpu(None).anisotropy_clamp, 8);
+    assert_eq!(
+        SamplerDescriptor::default()
+            .with_anisotropy(64)
+            .to_wgpu(None)
+            .anisotropy_clamp,
+        16
+    );
+
+    let pixel_art = SamplerDescriptor::pixel_art()
+        .with_anisotropy(8)
+        .with_address_mode(wgpu::AddressMode::MirrorRepeat);
+    let descriptor = pixel_art.to_wgpu(None);
+    assert_eq!(descriptor.anisotropy_clamp, 1);
+    assert_eq!(descriptor.mag_filter, wgpu::FilterMode::Nearest);
+    assert_eq!(descriptor.address_mode_v, wgpu::AddressMode::MirrorRepeat);
+}
+
+/// The smallest mip of a black and white checkerboard is their average.
+#[test]
+fn uploads_generate_averaged_mips() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let checker = image::RgbaImage::from_fn(4, 4, |x, y| {
+        let value = if (x + y) % 2 == 0 { 255 } else { 0 };
+        image::Rgba([value, value, value, 255])
+    });
+    let texture = Texture::from_image_with_format(
+        &ctx,
+        &image::DynamicImage::ImageRgba8(checker),
+        Some("checker"),
+        wgpu::TextureFormat::Rgba8Unorm,
+    )?;
+    assert_eq!(texture.texture.mip_level_count(), 3);
+
+    let buffer = ctx.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("mip readback"),
+        size: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = ctx
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    encoder.copy_texture_to_buffer(
+        wgpu::TexelCopyTextureInfo {
+            texture: &texture.texture,
+            mip_level: 2,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT),
+                rows_per_image: Some(1),
+            },
+        },
+        wgpu::Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+    );
+    ctx.queue.submit(Some(encoder.finish()));
+    buffer.slice(..).map_async(wgpu::MapMode::Read, |_| {});
+    ctx.device.poll(wgpu::PollType::wait_indefinitely())?;
+    let texel: Vec<u8> = buffer.slice(..4).get_mapped_range().to_vec();
+
+    if !ctx.is_noop() {
+        assert!((texel[0] as i32 - 128).abs() <= 1, "{:?}", texel);
+        assert_eq!(texel[3], 255);
+    }
+    Ok(())
+}
+
+#[test]
+fn mipmap_pipelines_are_reused_per_format() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let image = image::DynamicImage::ImageRgba8(image::RgbaImage::new(8, 8));
+    for _ in 0..3 {
+        Texture::from_image_with_format(&ctx, &image, None, wgpu::TextureFormat::Rgba8Unorm)?;
+    }
+    assert_eq!(ctx.mipmaps.pipeline_count(), 1);
+    Texture::from_image(&ctx, &image, None)?;
+    assert_eq!(ctx.mipmaps.pipeline_count(), 2);
+    Ok(())
+}
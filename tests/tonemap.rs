@@ -0,0 +1,71 @@
+use engine_rust::{
+    camera::Camera,
+    renderer::{GpuContext, HeadlessRenderer},
+    scene::{CameraComponent, Scene},
+    tonemap::Tonemapping,
+};
+use winit::dpi::PhysicalSize;
+
+fn render_clear_color(
+    ctx: &GpuContext,
+    camera: CameraComponent,
+    intensity: f64,
+) -> anyhow::Result<[u8; 4]> {
+    let mut renderer = HeadlessRenderer::new(ctx, PhysicalSize::new(8, 8));
+    renderer.clear_color = wgpu::Color {
+        r: intensity,
+        g: intensity,
+        b: intensity,
+        a: 1.0,
+    };
+
+    let mut scene = Scene::new();
+    let entity = scene.spawn(None, None);
+    scene.add_camera(entity, camera);
+    scene.active_camera = Some(entity);
+
+    let image = renderer.render_to_image(ctx, &mut scene)?;
+    Ok(image.get_pixel(4, 4).0)
+}
+
+#[test]
+fn default_graph_tonemaps_after_the_scene() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let renderer = HeadlessRenderer::new(&ctx, PhysicalSize::new(8, 8));
+    assert_eq!(
+        renderer.graph.execution_order()?,
+        [
+            "cull",
+            "shadows",
+            "scene",
+            "bloom",
+            "tonemap",
+            "post_process"
+        ]
+    );
+    Ok(())
+}
+
+/// Colors brighter than 1.0 survive the scene pass and can be brought back with exposure.
+#[test]
+fn exposure_scales_hdr_color() -> anyhow::Result<()> {
+    let (ctx, _) = pollster::block_on(GpuContext::new(None))?;
+    let camera = CameraComponent::new(Camera::new(
+        (0.0, 0.0, 3.0).into(),
+        (0.0, 0.0, 0.0).into(),
+        1.0,
+    ))
+    .with_tonemapping(Tonemapping::Linear);
+
+    let clipped = render_clear_color(&ctx, camera, 4.0)?;
+    let exposed = render_clear_color(&ctx, camera.with_exposure(-3.0), 4.0)?;
+    let reinhard = render_clear_color(&ctx, camera.with_tonemapping(Tonemapping::Reinhard), 4.0)?;
+    if !ctx.is_noop() {
+        assert_eq!(clipped, [255, 255, 255, 255]);
+        // 4.0 * 2^-3 = 0.5 linear, which is 188 in sRGB.
+        assert!((exposed[0] as i32 - 188).abs() <= 1, "{:?}", exposed);
+        // 4.0 / (1.0 + 4.0) = 0.8 linear.
+        assert!((reinhard[0] as i32 - 231).abs() <= 1, "{:?}", reinhard);
+    }
+    Ok(())
+}